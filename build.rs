@@ -0,0 +1,9 @@
+use std::env;
+
+fn main() {
+    // Parsers compiled from grammar sources at runtime are built for the server's own target
+    println!(
+        "cargo:rustc-env=BUILD_TARGET={}",
+        env::var("TARGET").expect("TARGET is set by cargo")
+    );
+}
@@ -1,7 +1,7 @@
 use std::{
     env, fs,
     path::PathBuf,
-    sync::{Arc, LazyLock, atomic::AtomicI32},
+    sync::{Arc, LazyLock},
 };
 
 use dashmap::DashMap;
@@ -10,12 +10,16 @@ use tower_lsp::lsp_types::Url;
 
 use crate::{
     LanguageData, Options,
-    cli::lint::LintOptions,
+    cli::{lint::LintOptions, output::DiagnosticReport},
     handlers::did_open::init_language_data,
+    node_types::get_node_types,
     util::{self, get_scm_files},
 };
 
-use super::{format::format_directories, lint::lint_file};
+use super::{
+    format::format_files,
+    lint::{lint_file, warn_unknown_severity_codes},
+};
 
 static LANGUAGE_CACHE: LazyLock<DashMap<String, Arc<LanguageData>>> = LazyLock::new(DashMap::new);
 
@@ -25,30 +29,48 @@ pub async fn check_directories(
     config: String,
     workspace: Option<PathBuf>,
     format: bool,
-    fix: bool,
+    corpus: Option<PathBuf>,
+    lint_options: LintOptions,
 ) -> i32 {
-    let Ok(options) = serde_json::from_str::<Options>(&config) else {
+    let Ok(mut options) = serde_json::from_str::<Options>(&config) else {
         eprintln!("Could not parse the provided configuration");
         return 1;
     };
-    let options_arc: Arc<tokio::sync::RwLock<Options>> = Arc::new(options.clone().into());
-
-    let exit_code = Arc::new(AtomicI32::new(0));
+    warn_unknown_severity_codes(&options);
     // If directories are not specified, check all files in the current directory
     let directories = if directories.is_empty() {
         &[env::current_dir().expect("Failed to get current directory")]
     } else {
         directories
     };
+    let scm_files: Vec<_> = get_scm_files(directories).collect();
+    // The given corpus is used for the languages of all checked files
+    if let Some(corpus) = corpus {
+        let corpus = corpus.to_string_lossy();
+        for path in &scm_files {
+            if let Some(language_name) = path
+                .canonicalize()
+                .ok()
+                .and_then(|path| Url::from_file_path(path).ok())
+                .and_then(|uri| util::get_language_name(&uri, &options))
+            {
+                options
+                    .corpus_directories
+                    .insert(language_name, corpus.clone().into_owned());
+            }
+        }
+    }
+    let options_arc: Arc<tokio::sync::RwLock<Options>> = Arc::new(options.clone().into());
+
+    let report = Arc::new(DiagnosticReport::default());
     let workspace = workspace
         .unwrap_or_else(|| env::current_dir().expect("Failed to get current directory"))
         .canonicalize()
         .expect("Workspace path should be valid");
     let workspace = Arc::new(workspace);
-    let scm_files = get_scm_files(directories);
     let tasks = scm_files.into_iter().filter_map(|path| {
         let options_arc = options_arc.clone();
-        let exit_code = exit_code.clone();
+        let report = report.clone();
         let absolute_path = path.canonicalize().expect("Path should be valid");
         let uri = Url::from_file_path(&absolute_path).expect("Path should be absolute");
         let language_name = util::get_language_name(&uri, &options);
@@ -60,39 +82,48 @@ pub async fn check_directories(
 
         let language_data = language_name.and_then(|name| {
             LANGUAGE_CACHE.get(&name).as_deref().cloned().or_else(|| {
-                util::get_language(&name, &options)
-                    .map(|lang| Arc::new(init_language_data(lang, name)))
+                util::get_language(&name, &options).map(|lang| {
+                    let mut language_data = init_language_data(lang, name.clone());
+                    language_data.node_types = get_node_types(&name, &options);
+                    Arc::new(language_data)
+                })
             })
         });
         let Ok(source) = fs::read_to_string(&path) else {
             eprintln!("Failed to read {}", absolute_path.display());
-            exit_code.store(1, std::sync::atomic::Ordering::Relaxed);
+            report.fail();
             return None;
         };
         let workspace = workspace.clone();
-        let ignore_missing_language = false;
-        let lint_opts = LintOptions::new(fix, ignore_missing_language);
         Some(tokio::spawn(async move {
             if let Some(new_source) = lint_file(
                 absolute_path.as_path(),
                 &workspace,
                 &source,
                 options_arc.clone(),
-                lint_opts,
+                lint_options,
                 language_data,
-                &exit_code,
+                &report,
             )
             .await
                 && fs::write(&path, new_source).is_err()
             {
                 eprintln!("Failed to write {}", absolute_path.display());
-                exit_code.store(1, std::sync::atomic::Ordering::Relaxed);
+                report.fail();
             }
         }))
     });
     join_all(tasks).await;
-    if format && format_directories(directories, true, options.formatting_options).await != 0 {
-        exit_code.store(1, std::sync::atomic::Ordering::Relaxed);
+    if format {
+        format_files(
+            directories,
+            true,
+            options.formatting_options,
+            &report,
+            lint_options.output_format,
+        )
+        .await;
     }
-    exit_code.load(std::sync::atomic::Ordering::Relaxed)
+    report.print(lint_options.output_format, &workspace);
+    report.exit_code()
 }
@@ -0,0 +1,343 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    path::PathBuf,
+};
+
+use clap::ValueEnum;
+use ropey::Rope;
+use serde::Serialize;
+use streaming_iterator::StreamingIterator as _;
+use tower_lsp::lsp_types::Url;
+use tree_sitter::QueryCursor;
+
+use crate::{
+    LanguageData, Options,
+    handlers::did_open::init_language_data,
+    util::{
+        CAPTURES_QUERY, NODE_REFERENCES_QUERY, NodeUtil as _, TextProviderRope, get_language,
+        get_language_name, get_scm_files, parse,
+    },
+};
+
+use super::read_query_modules;
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum CoverageFormat {
+    /// A human readable report. For captures, this is a list of unused captures per language,
+    /// followed by a capture × language table.
+    #[default]
+    Text,
+    /// For captures, a map from query type to language to capture name to whether the capture is
+    /// used. For nodes, a map from query type to language to the unreferenced nodes and fields.
+    Json,
+}
+
+/// A map from query type to language to capture name to whether that capture is used.
+type CaptureMatrix = BTreeMap<String, BTreeMap<String, BTreeMap<String, bool>>>;
+
+/// A map from query type to language to the nodes and fields which are never referenced.
+type NodeReport = BTreeMap<String, BTreeMap<String, UnreferencedNodes>>;
+
+/// The captures, node kinds, and field names referenced by a set of queries.
+#[derive(Debug, Default)]
+struct QueryReferences {
+    captures: BTreeSet<String>,
+    nodes: BTreeSet<String>,
+    fields: BTreeSet<String>,
+}
+
+/// The named node kinds and field names of a language which are never referenced by a query.
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+struct UnreferencedNodes {
+    nodes: Vec<String>,
+    fields: Vec<String>,
+}
+
+/// Report which of the `valid_captures` for each query type are used by each language's queries
+/// in the given directories, or, if `nodes` is set, which of the language's named node kinds and
+/// field names are never referenced by them. Imported query modules are taken into account.
+/// Returns the exit code.
+pub fn coverage_directories(
+    directories: &[PathBuf],
+    config: &str,
+    workspace: Option<PathBuf>,
+    format: CoverageFormat,
+    nodes: bool,
+) -> i32 {
+    let Ok(options) = serde_json::from_str::<Options>(config) else {
+        eprintln!("Could not parse the provided configuration");
+        return 1;
+    };
+    let directories = if directories.is_empty() {
+        &[env::current_dir().expect("Failed to get current directory")]
+    } else {
+        directories
+    };
+    let workspace = workspace
+        .unwrap_or_else(|| env::current_dir().expect("Failed to get current directory"))
+        .canonicalize()
+        .expect("Workspace path should be valid");
+    let workspace_dirs = &[workspace];
+
+    let mut references: BTreeMap<(String, String), QueryReferences> = BTreeMap::new();
+    for path in get_scm_files(directories) {
+        let absolute_path = path.canonicalize().expect("Path should be valid");
+        let uri = Url::from_file_path(&absolute_path).expect("Path should be absolute");
+        let Some(query_type) = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+        else {
+            continue;
+        };
+        if !nodes && !options.valid_captures.contains_key(&query_type) {
+            continue;
+        }
+        let Some(language_name) = get_language_name(&uri, &options) else {
+            continue;
+        };
+        let Some(modules) = read_query_modules(&uri, workspace_dirs, &options) else {
+            eprintln!("Failed to read {}", absolute_path.display());
+            return 1;
+        };
+        let query_references = references.entry((query_type, language_name)).or_default();
+        for (_, rope) in modules {
+            add_references(&rope, query_references);
+        }
+    }
+
+    if nodes {
+        return report_nodes(references, &options, format);
+    }
+
+    let mut matrix = CaptureMatrix::new();
+    for ((query_type, language_name), QueryReferences { captures, .. }) in references {
+        let valid_captures = &options.valid_captures[&query_type];
+        matrix.entry(query_type).or_default().insert(
+            language_name,
+            valid_captures
+                .keys()
+                .map(|name| (name.clone(), captures.contains(name)))
+                .collect(),
+        );
+    }
+
+    match format {
+        CoverageFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&matrix).expect("Matrix should serialize")
+        ),
+        CoverageFormat::Text => print_matrix(&matrix),
+    }
+    0
+}
+
+fn add_references(rope: &Rope, references: &mut QueryReferences) {
+    let tree = parse(rope, None);
+    let mut cursor = QueryCursor::new();
+    let provider = TextProviderRope(rope);
+    let mut matches = cursor.matches(&CAPTURES_QUERY, tree.root_node(), &provider);
+    while let Some(match_) = matches.next() {
+        for capture in match_.captures {
+            references
+                .captures
+                .insert(capture.node.text(rope)[1..].to_string());
+        }
+    }
+    let capture_names = NODE_REFERENCES_QUERY.capture_names();
+    let mut matches = cursor.matches(&NODE_REFERENCES_QUERY, tree.root_node(), &provider);
+    while let Some(match_) = matches.next() {
+        for capture in match_.captures {
+            let text = capture.node.text(rope);
+            match capture_names[capture.index as usize] {
+                "node" => references.nodes.insert(text),
+                _ => references.fields.insert(text),
+            };
+        }
+    }
+}
+
+fn report_nodes(
+    references: BTreeMap<(String, String), QueryReferences>,
+    options: &Options,
+    format: CoverageFormat,
+) -> i32 {
+    let mut exit_code = 0;
+    let mut language_cache: BTreeMap<String, Option<LanguageData>> = BTreeMap::new();
+    let mut report = NodeReport::new();
+    for ((query_type, language_name), query_references) in references {
+        let language_data = language_cache
+            .entry(language_name.clone())
+            .or_insert_with(|| {
+                get_language(&language_name, options)
+                    .map(|language| init_language_data(language, language_name.clone()))
+            });
+        let Some(language_data) = language_data else {
+            eprintln!("Could not retrieve language {language_name:?}");
+            exit_code = 1;
+            continue;
+        };
+        report.entry(query_type).or_default().insert(
+            language_name,
+            get_unreferenced_nodes(language_data, &query_references),
+        );
+    }
+
+    match format {
+        CoverageFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("Report should serialize")
+        ),
+        CoverageFormat::Text => {
+            for (query_type, languages) in report {
+                println!("{query_type}:");
+                for (language_name, unreferenced) in languages {
+                    println!("  {language_name}:");
+                    for node in unreferenced.nodes {
+                        println!("    ({node})");
+                    }
+                    for field in unreferenced.fields {
+                        println!("    {field}:");
+                    }
+                }
+            }
+        }
+    }
+    exit_code
+}
+
+fn get_unreferenced_nodes(
+    language_data: &LanguageData,
+    references: &QueryReferences,
+) -> UnreferencedNodes {
+    let mut nodes: Vec<_> = language_data
+        .symbols_vec
+        .iter()
+        .filter(|symbol| {
+            symbol.named && symbol.label != "ERROR" && !references.nodes.contains(&symbol.label)
+        })
+        .map(|symbol| symbol.label.clone())
+        .collect();
+    nodes.sort_unstable();
+    nodes.dedup();
+    let mut fields: Vec<_> = language_data
+        .fields_vec
+        .iter()
+        .filter(|field| !references.fields.contains(*field))
+        .cloned()
+        .collect();
+    fields.sort_unstable();
+    UnreferencedNodes { nodes, fields }
+}
+
+fn print_matrix(matrix: &CaptureMatrix) {
+    for (query_type, languages) in matrix {
+        println!("{query_type}:");
+        for (language_name, captures) in languages {
+            let used = captures.values().filter(|used| **used).count();
+            println!(
+                "  {language_name} ({used}/{} captures used)",
+                captures.len()
+            );
+            let unused: Vec<_> = captures
+                .iter()
+                .filter(|(_, used)| !**used)
+                .map(|(name, _)| format!("@{name}"))
+                .collect();
+            if !unused.is_empty() {
+                println!("    unused: {}", unused.join(" "));
+            }
+        }
+        println!();
+
+        let capture_names: BTreeSet<_> = languages.values().flat_map(BTreeMap::keys).collect();
+        let name_width = capture_names
+            .iter()
+            .map(|name| name.len() + 1)
+            .max()
+            .unwrap_or_default();
+        let header: Vec<_> = languages.keys().map(String::as_str).collect();
+        println!("  {:name_width$}  {}", "", header.join("  "));
+        for name in capture_names {
+            let row: Vec<_> = languages
+                .iter()
+                .map(|(language_name, captures)| {
+                    let mark = if captures.get(name).copied().unwrap_or_default() {
+                        "x"
+                    } else {
+                        "-"
+                    };
+                    format!("{mark:^width$}", width = language_name.len())
+                })
+                .collect();
+            let line = format!("  {:name_width$}  {}", format!("@{name}"), row.join("  "));
+            println!("{}", line.trim_end());
+        }
+        println!();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use ropey::Rope;
+    use rstest::rstest;
+
+    use crate::{RUST_LANGUAGE, handlers::did_open::init_language_data};
+
+    use super::{QueryReferences, add_references, get_unreferenced_nodes};
+
+    #[rstest]
+    #[case(
+        r"(function_item name: (identifier) @function)",
+        &["function_item", "identifier"],
+        &["name"],
+        &["struct_item", "type_identifier"],
+        &["body", "parameters"]
+    )]
+    #[case(
+        r"(expression/call_expression !arguments) @call
+(MISSING identifier) @missing",
+        &["expression", "call_expression", "identifier"],
+        &["arguments"],
+        &["function_item"],
+        &["name"]
+    )]
+    fn node_coverage(
+        #[case] source: &str,
+        #[case] referenced_nodes: &[&str],
+        #[case] referenced_fields: &[&str],
+        #[case] unreferenced_nodes: &[&str],
+        #[case] unreferenced_fields: &[&str],
+    ) {
+        // Arrange
+        let language_data = init_language_data(RUST_LANGUAGE.clone(), String::from("rust"));
+        let mut references = QueryReferences::default();
+        add_references(&Rope::from(source), &mut references);
+
+        // Act
+        let unreferenced = get_unreferenced_nodes(&language_data, &references);
+
+        // Assert
+        assert_eq!(
+            references.nodes,
+            referenced_nodes.iter().map(ToString::to_string).collect()
+        );
+        assert_eq!(
+            references.fields,
+            referenced_fields.iter().map(ToString::to_string).collect()
+        );
+        for node in referenced_nodes {
+            assert!(!unreferenced.nodes.iter().any(|n| n == node));
+        }
+        for node in unreferenced_nodes {
+            assert!(unreferenced.nodes.iter().any(|n| n == node));
+        }
+        for field in referenced_fields {
+            assert!(!unreferenced.fields.iter().any(|f| f == field));
+        }
+        for field in unreferenced_fields {
+            assert!(unreferenced.fields.iter().any(|f| f == field));
+        }
+    }
+}
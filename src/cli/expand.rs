@@ -0,0 +1,34 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use tower_lsp::lsp_types::Url;
+
+use crate::{Options, handlers::execute_command::expand_query};
+
+use super::read_query_modules;
+
+/// Print the fully flattened query file, with its imported modules inlined in the order in which
+/// they are combined and each pattern annotated with its source location. Returns the exit code.
+pub fn expand_query_file(query_path: &Path, workspace: Option<PathBuf>, config: &str) -> i32 {
+    let Ok(options) = serde_json::from_str::<Options>(config) else {
+        eprintln!("Could not parse the provided configuration");
+        return 1;
+    };
+    let workspace = workspace
+        .unwrap_or_else(|| env::current_dir().expect("Failed to get current directory"))
+        .canonicalize()
+        .expect("Workspace path should be valid");
+    let Some(modules) = query_path
+        .canonicalize()
+        .ok()
+        .and_then(|path| Url::from_file_path(path).ok())
+        .and_then(|uri| read_query_modules(&uri, &[workspace], &options))
+    else {
+        eprintln!("Failed to read {}", query_path.display());
+        return 1;
+    };
+    print!("{}", expand_query(&modules));
+    0
+}
@@ -1,12 +1,9 @@
-use std::{
-    fs,
-    path::PathBuf,
-    sync::{Arc, atomic::AtomicI32},
-};
+use std::{env, fs, path::PathBuf, sync::Arc};
 
 use anstyle::{AnsiColor, Color, Style};
 use futures::future::join_all;
 use ropey::Rope;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Url};
 use ts_query_ls::FormattingOptions;
 
 use crate::{
@@ -14,18 +11,39 @@ use crate::{
     util::{get_scm_files, parse},
 };
 
+use super::output::{DiagnosticReport, OutputFormat};
+
+/// The diagnostic code reported for incorrectly formatted ranges by `format --check`.
+const FORMATTING_CODE: &str = "formatting";
+
 pub async fn format_directories(
     directories: &[PathBuf],
     check: bool,
     fmt_options: FormattingOptions,
+    output_format: OutputFormat,
 ) -> i32 {
     if directories.is_empty() {
         eprintln!("No directories were specified to be formatted. No work was done.");
         return 1;
     }
 
+    let report = Arc::new(DiagnosticReport::default());
+    format_files(directories, check, fmt_options, &report, output_format).await;
+    let workspace = env::current_dir().expect("Failed to get current directory");
+    report.print(output_format, &workspace);
+    report.exit_code()
+}
+
+/// Format the query files in the given directories. When checking, formatting issues are printed
+/// as diffs for the text output format, and otherwise added to the report.
+pub(super) async fn format_files(
+    directories: &[PathBuf],
+    check: bool,
+    fmt_options: FormattingOptions,
+    report: &Arc<DiagnosticReport>,
+    output_format: OutputFormat,
+) {
     let scm_files = get_scm_files(directories);
-    let exit_code = Arc::new(AtomicI32::new(0));
     let use_color = std::env::var("NO_COLOR").map_or(true, |v| v.is_empty());
     let (red, green, blue, purple) = if use_color {
         (
@@ -39,27 +57,50 @@ pub async fn format_directories(
     };
 
     let tasks = scm_files.into_iter().map(|path| {
-        let exit_code = exit_code.clone();
+        let report = report.clone();
         tokio::spawn(async move {
             let path_str = path.to_string_lossy();
             let Ok(contents) = fs::read_to_string(&path) else {
                 eprintln!("Failed to read {path_str:?}");
-                exit_code.store(1, std::sync::atomic::Ordering::Relaxed);
+                report.fail();
                 return;
             };
+            let uri = path
+                .canonicalize()
+                .ok()
+                .and_then(|path| Url::from_file_path(path).ok());
+            if check && let Some(uri) = &uri {
+                report.add_file(uri.clone());
+            }
             let rope = Rope::from(contents.as_str());
             let tree = parse(&rope, None);
             let Some(formatted) =
                 formatting::format_document(&rope, &tree.root_node(), fmt_options)
             else {
-                exit_code.store(1, std::sync::atomic::Ordering::Relaxed);
+                report.fail();
                 eprintln!("No formatting performed -- invalid syntax detected at {path_str:?}");
                 return;
             };
-            if check {
+            if check && output_format != OutputFormat::Text {
+                for edit in formatting::diffs(&contents, &formatted, rope) {
+                    report.fail();
+                    if let Some(uri) = &uri {
+                        report.push(
+                            uri.clone(),
+                            Diagnostic {
+                                range: edit.range,
+                                severity: Some(DiagnosticSeverity::ERROR),
+                                code: Some(NumberOrString::String(FORMATTING_CODE.to_string())),
+                                message: String::from("Incorrect formatting"),
+                                ..Default::default()
+                            },
+                        );
+                    }
+                }
+            } else if check {
                 let mut edits = formatting::diffs(&contents, &formatted, rope);
                 if edits.next().is_some() {
-                    exit_code.store(1, std::sync::atomic::Ordering::Relaxed);
+                    report.fail();
                     eprintln!("{}", paint(purple, &format!("{path_str:?}:")));
                     let patch = diffy::create_patch(&contents, &formatted).to_string();
                     for line in patch.lines() {
@@ -76,13 +117,12 @@ pub async fn format_directories(
                     eprintln!();
                 }
             } else if fs::write(&path, formatted).is_err() {
-                exit_code.store(1, std::sync::atomic::Ordering::Relaxed);
+                report.fail();
                 eprint!("Failed to write to {path_str:?}");
             }
         })
     });
     join_all(tasks).await;
-    exit_code.load(std::sync::atomic::Ordering::Relaxed)
 }
 
 pub fn paint(color: Option<impl Into<Color>>, text: &str) -> String {
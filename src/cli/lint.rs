@@ -1,19 +1,24 @@
 use std::{
     env, fs,
     path::{Path, PathBuf},
-    sync::{Arc, atomic::AtomicI32},
+    sync::Arc,
 };
 
 use dashmap::DashMap;
 use futures::future::join_all;
 use ropey::Rope;
-use tower_lsp::lsp_types::{CodeAction, CodeActionOrCommand, DiagnosticSeverity, Url};
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionOrCommand, DiagnosticSeverity, NumberOrString, Url,
+};
+use ts_query_ls::SeverityOverride;
 
 use crate::{
     DocumentData, LanguageData, Options,
+    cli::output::{DiagnosticReport, OutputFormat},
+    corpus::{get_dead_pattern_diagnostics, load_corpus},
     handlers::{
         code_action::diag_to_code_action,
-        diagnostic::{DiagnosticCode, get_diagnostics},
+        diagnostic::{DiagnosticCode, finish_diagnostics, get_diagnostics, unknown_severity_codes},
         did_open::populate_import_documents,
     },
     util::{edit_rope, get_imported_uris, get_language_name, get_scm_files, parse},
@@ -23,18 +28,36 @@ use crate::{
 pub struct LintOptions {
     pub fix: bool,
     pub ignore_missing_language: bool,
+    /// Report patterns which never match the corpus of their language, as configured by
+    /// `corpus_directories`.
+    pub dead_patterns: bool,
+    pub output_format: OutputFormat,
 }
 
 impl LintOptions {
     #[must_use]
-    pub const fn new(fix: bool, ignore_missing_language: bool) -> Self {
+    pub const fn new(
+        fix: bool,
+        ignore_missing_language: bool,
+        dead_patterns: bool,
+        output_format: OutputFormat,
+    ) -> Self {
         Self {
             fix,
             ignore_missing_language,
+            dead_patterns,
+            output_format,
         }
     }
 }
 
+/// Warn about the configured severity overrides which are not diagnostic codes.
+pub(super) fn warn_unknown_severity_codes(options: &Options) {
+    for code in unknown_severity_codes(options) {
+        eprintln!("Warning: Unknown diagnostic code {code:?} in `diagnostic_options.severity`");
+    }
+}
+
 pub(super) async fn lint_file(
     absolute_path: &Path,
     workspace: &Path,
@@ -42,11 +65,12 @@ pub(super) async fn lint_file(
     server_options: Arc<tokio::sync::RwLock<Options>>,
     lint_options: LintOptions,
     language_data: Option<Arc<LanguageData>>,
-    exit_code: &AtomicI32,
+    report: &DiagnosticReport,
 ) -> Option<String> {
     let rope = Rope::from(source);
     let tree = parse(&rope, None);
     let uri = Url::from_file_path(absolute_path).expect("Path should be absolute");
+    report.add_file(uri.clone());
     let options_val = server_options.clone().read().await.clone();
     let language_name = get_language_name(&uri, &options_val);
     let workspace_uris = &[workspace.to_owned()];
@@ -63,16 +87,33 @@ pub(super) async fn lint_file(
     let cache = false;
     // The query construction already validates node names, fields, supertypes,
     // etc.
-    let diagnostics = get_diagnostics(
+    let mut diagnostics = get_diagnostics(
         &uri,
         &document_map,
         doc.clone(),
-        language_data,
+        language_data.clone(),
         server_options,
         lint_options.ignore_missing_language,
         cache,
     )
     .await;
+    let corpus_dir = language_data
+        .as_ref()
+        .filter(|_| lint_options.dead_patterns)
+        .and_then(|language_data| options_val.corpus_directories.get(&language_data.name))
+        .map(PathBuf::from);
+    if let (Some(corpus_dir), Some(language_data)) = (corpus_dir, language_data) {
+        let tree = doc.tree.clone();
+        let rope = doc.rope.clone();
+        let dead_patterns = tokio::task::spawn_blocking(move || {
+            let corpus = load_corpus(&language_data.name, &language_data.language, &corpus_dir);
+            get_dead_pattern_diagnostics(&tree, &rope, &language_data.language, &corpus)
+        })
+        .await
+        .unwrap_or_default();
+        diagnostics.extend(dead_patterns);
+    }
+    finish_diagnostics(&mut diagnostics, &doc.tree, &doc.rope, &options_val, true);
     if diagnostics.is_empty() {
         return None;
     }
@@ -82,8 +123,20 @@ pub(super) async fn lint_file(
     } else {
         0
     });
-    if !lint_options.fix {
-        exit_code.store(1, std::sync::atomic::Ordering::Relaxed);
+    // Only diagnostics which are lowered to information or hints by the configuration do not fail
+    // the run
+    if !lint_options.fix
+        && diagnostics.iter().any(|diagnostic| {
+            let Some(NumberOrString::String(code)) = &diagnostic.code else {
+                return true;
+            };
+            !matches!(
+                options_val.diagnostic_options.severity.get(code),
+                Some(SeverityOverride::Info | SeverityOverride::Hint)
+            )
+        })
+    {
+        report.fail();
     }
     let mut unfixed_issues = 0;
     for diagnostic in diagnostics {
@@ -106,6 +159,8 @@ pub(super) async fn lint_file(
                 continue;
             };
             edits.append(&mut changes);
+        } else if lint_options.output_format != OutputFormat::Text {
+            report.push(uri.clone(), diagnostic);
         } else {
             let kind = match diagnostic.severity {
                 Some(DiagnosticSeverity::ERROR) => "Error",
@@ -150,6 +205,7 @@ pub(super) async fn lint_file(
     if !lint_options.fix || edits.is_empty() {
         return None;
     }
+    #[allow(clippy::unnecessary_sort_by)]
     edits.sort_unstable_by(|a, b| b.range.start.cmp(&a.range.start));
     let mut rope = doc.rope;
     for edit in edits {
@@ -168,13 +224,15 @@ pub async fn lint_directories(
     config: String,
     workspace: Option<PathBuf>,
     fix: bool,
+    output_format: OutputFormat,
 ) -> i32 {
     let Ok(options) = serde_json::from_str::<Options>(&config) else {
         eprintln!("Could not parse the provided configuration");
         return 1;
     };
+    warn_unknown_severity_codes(&options);
     let options: Arc<tokio::sync::RwLock<Options>> = Arc::new(options.into());
-    let exit_code = Arc::new(AtomicI32::new(0));
+    let report = Arc::new(DiagnosticReport::default());
     // If directories are not specified, lint all files in the current directory
     let directories = if directories.is_empty() {
         &[env::current_dir().expect("Failed to get current directory")]
@@ -189,11 +247,11 @@ pub async fn lint_directories(
     let scm_files = get_scm_files(directories);
     let tasks = scm_files.into_iter().filter_map(|path| {
         let absolute_path = path.canonicalize().expect("Path should be valid");
-        let exit_code = exit_code.clone();
+        let report = report.clone();
         let options = options.clone();
         if let Ok(source) = fs::read_to_string(&path) {
             let workspace = workspace.clone();
-            let lint_opts = LintOptions::new(fix, true);
+            let lint_opts = LintOptions::new(fix, true, false, output_format);
             Some(tokio::spawn(async move {
                 if let Some(new_source) = lint_file(
                     absolute_path.as_path(),
@@ -202,21 +260,22 @@ pub async fn lint_directories(
                     options,
                     lint_opts,
                     None,
-                    &exit_code,
+                    &report,
                 )
                 .await
                     && fs::write(&path, new_source).is_err()
                 {
                     eprintln!("Failed to write {}", absolute_path.display());
-                    exit_code.store(1, std::sync::atomic::Ordering::Relaxed);
+                    report.fail();
                 }
             }))
         } else {
             eprintln!("Failed to read {}", absolute_path.display());
-            exit_code.store(1, std::sync::atomic::Ordering::Relaxed);
+            report.fail();
             None
         }
     });
     join_all(tasks).await;
-    exit_code.load(std::sync::atomic::Ordering::Relaxed)
+    report.print(output_format, &workspace);
+    report.exit_code()
 }
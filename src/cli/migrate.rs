@@ -0,0 +1,609 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use streaming_iterator::StreamingIterator as _;
+use tower_lsp::lsp_types::{Range, Url};
+use tree_sitter::QueryCursor;
+
+use crate::{
+    LanguageData, Options,
+    handlers::did_open::init_language_data,
+    util::{
+        NODE_REFERENCES_QUERY, NodeUtil as _, TextProviderRope, edit_rope, get_language_name,
+        get_scm_files, load_language_file, parse,
+    },
+};
+
+/// Renames from symbols of the old grammar to symbols of the new grammar. A `null` target marks a
+/// symbol which has no replacement.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MigrationMapping {
+    #[serde(default)]
+    pub nodes: BTreeMap<String, Option<String>>,
+    #[serde(default)]
+    pub fields: BTreeMap<String, Option<String>>,
+}
+
+/// The differences between the symbols of two versions of a grammar.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct GrammarDiff {
+    removed_nodes: BTreeSet<String>,
+    added_nodes: BTreeSet<String>,
+    removed_fields: BTreeSet<String>,
+    added_fields: BTreeSet<String>,
+    removed_supertypes: BTreeSet<String>,
+    added_supertypes: BTreeSet<String>,
+    /// Supertypes present in both grammars, mapped to the subtypes they no longer include.
+    narrowed_supertypes: BTreeMap<String, BTreeSet<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SymbolKind {
+    Node,
+    Field,
+    /// A `supertype/subtype` pair, whose supertype no longer includes the subtype.
+    Subtype,
+}
+
+impl SymbolKind {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Node => "node",
+            Self::Field => "field",
+            Self::Subtype => "subtype",
+        }
+    }
+}
+
+/// A query location which references a symbol that was removed from the grammar.
+#[derive(Debug, PartialEq, Eq)]
+struct StaleReference {
+    kind: SymbolKind,
+    name: String,
+    range: Range,
+}
+
+/// Diff the node kinds, fields, and supertypes of the `from` and `to` grammars, then report each
+/// query location in the given directories which uses a removed symbol. Confirmed renames from the
+/// `mapping` file are applied to the queries; a template for this file can be written to
+/// `write_mapping`. Returns the exit code.
+pub fn migrate_directories(
+    directories: &[PathBuf],
+    from: &Path,
+    to: &Path,
+    language: Option<String>,
+    config: &str,
+    mapping: Option<PathBuf>,
+    write_mapping: Option<PathBuf>,
+) -> i32 {
+    let Ok(options) = serde_json::from_str::<Options>(config) else {
+        eprintln!("Could not parse the provided configuration");
+        return 1;
+    };
+    let directories = if directories.is_empty() {
+        &[env::current_dir().expect("Failed to get current directory")]
+    } else {
+        directories
+    };
+    let Some(language_name) = language.or_else(|| language_name_from_path(to)) else {
+        eprintln!("Could not determine the language name of {}", to.display());
+        return 1;
+    };
+    let Some(old_data) = load_language_data(from, &language_name) else {
+        return 1;
+    };
+    let Some(new_data) = load_language_data(to, &language_name) else {
+        return 1;
+    };
+    let diff = diff_languages(&old_data, &new_data);
+    print_diff(&diff);
+
+    let suggestions = suggest_mapping(&diff);
+    if let Some(path) = write_mapping {
+        let contents =
+            serde_json::to_string_pretty(&suggestions).expect("Mapping should serialize");
+        if fs::write(&path, contents + "\n").is_err() {
+            eprintln!("Failed to write {}", path.display());
+            return 1;
+        }
+    }
+    let mapping = match mapping {
+        Some(path) => {
+            let Ok(contents) = fs::read_to_string(&path) else {
+                eprintln!("Failed to read {}", path.display());
+                return 1;
+            };
+            let Ok(mapping) = serde_json::from_str::<MigrationMapping>(&contents) else {
+                eprintln!("Could not parse the mapping file {}", path.display());
+                return 1;
+            };
+            if let Err(err) = validate_mapping(&mapping, &new_data) {
+                eprintln!("{err}");
+                return 1;
+            }
+            Some(mapping)
+        }
+        None => None,
+    };
+
+    let mut exit_code = 0;
+    let mut rewritten = 0;
+    for path in get_scm_files(directories) {
+        let Ok(uri) = Url::from_file_path(path.canonicalize().expect("Path should be valid"))
+        else {
+            continue;
+        };
+        if get_language_name(&uri, &options).as_ref() != Some(&language_name) {
+            continue;
+        }
+        let Ok(source) = fs::read_to_string(&path) else {
+            eprintln!("Failed to read {}", path.display());
+            exit_code = 1;
+            continue;
+        };
+        let mut rope = Rope::from(source.as_str());
+        let references = find_stale_references(&rope, &diff);
+        let unresolved = mapping.as_ref().map_or_else(
+            || references.iter().collect(),
+            |mapping| apply_mapping(&mut rope, &references, mapping),
+        );
+        if unresolved.len() < references.len() {
+            if fs::write(&path, rope.to_string()).is_err() {
+                eprintln!("Failed to write {}", path.display());
+                exit_code = 1;
+                continue;
+            }
+            rewritten += references.len() - unresolved.len();
+        }
+        for reference in unresolved {
+            exit_code = 1;
+            let suggestions = match reference.kind {
+                SymbolKind::Node => &suggestions.nodes,
+                SymbolKind::Field => &suggestions.fields,
+                SymbolKind::Subtype => &BTreeMap::new(),
+            };
+            let hint = suggestions
+                .get(&reference.name)
+                .and_then(Option::as_ref)
+                .map_or_else(String::new, |new| {
+                    format!(" (perhaps renamed to \"{new}\"?)")
+                });
+            eprintln!(
+                "{}:{}:{}: {} \"{}\" was removed{hint}",
+                path.display(),
+                reference.range.start.line + 1,
+                reference.range.start.character + 1,
+                reference.kind.as_str(),
+                reference.name,
+            );
+        }
+    }
+    if mapping.is_some() {
+        println!("Rewrote {rewritten} reference(s)");
+    }
+    exit_code
+}
+
+/// Derive the language name from the name of a parser object, e.g. `tree-sitter-rust.so`.
+fn language_name_from_path(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    let stem = stem.strip_prefix("lib").unwrap_or(stem);
+    Some(stem.strip_prefix("tree-sitter-").unwrap_or(stem).to_owned())
+}
+
+fn load_language_data(path: &Path, name: &str) -> Option<LanguageData> {
+    let Some(language) = load_language_file(path, name) else {
+        eprintln!("Could not load language {name:?} from {}", path.display());
+        return None;
+    };
+    Some(init_language_data(language, name.to_owned()))
+}
+
+fn named_nodes(data: &LanguageData) -> BTreeSet<String> {
+    data.symbols_vec
+        .iter()
+        .filter(|symbol| symbol.named)
+        .map(|symbol| symbol.label.clone())
+        .collect()
+}
+
+fn diff_languages(old: &LanguageData, new: &LanguageData) -> GrammarDiff {
+    let old_nodes = named_nodes(old);
+    let new_nodes = named_nodes(new);
+    let old_fields: BTreeSet<_> = old.fields_vec.iter().cloned().collect();
+    let new_fields: BTreeSet<_> = new.fields_vec.iter().cloned().collect();
+    let supertypes = |data: &LanguageData| -> BTreeMap<String, BTreeSet<String>> {
+        data.supertype_map
+            .iter()
+            .map(|(supertype, subtypes)| {
+                let subtypes = subtypes.iter().map(|s| s.label.clone()).collect();
+                (supertype.label.clone(), subtypes)
+            })
+            .collect()
+    };
+    let old_supertypes = supertypes(old);
+    let new_supertypes = supertypes(new);
+
+    let narrowed_supertypes = old_supertypes
+        .iter()
+        .filter_map(|(supertype, old_subtypes)| {
+            let new_subtypes = new_supertypes.get(supertype)?;
+            let removed: BTreeSet<_> = old_subtypes.difference(new_subtypes).cloned().collect();
+            (!removed.is_empty()).then(|| (supertype.clone(), removed))
+        })
+        .collect();
+    GrammarDiff {
+        removed_nodes: old_nodes.difference(&new_nodes).cloned().collect(),
+        added_nodes: new_nodes.difference(&old_nodes).cloned().collect(),
+        removed_fields: old_fields.difference(&new_fields).cloned().collect(),
+        added_fields: new_fields.difference(&old_fields).cloned().collect(),
+        removed_supertypes: old_supertypes
+            .keys()
+            .filter(|s| !new_supertypes.contains_key(*s))
+            .cloned()
+            .collect(),
+        added_supertypes: new_supertypes
+            .keys()
+            .filter(|s| !old_supertypes.contains_key(*s))
+            .cloned()
+            .collect(),
+        narrowed_supertypes,
+    }
+}
+
+fn print_diff(diff: &GrammarDiff) {
+    let sections = [
+        ("Removed node kinds", &diff.removed_nodes),
+        ("Added node kinds", &diff.added_nodes),
+        ("Removed fields", &diff.removed_fields),
+        ("Added fields", &diff.added_fields),
+        ("Removed supertypes", &diff.removed_supertypes),
+        ("Added supertypes", &diff.added_supertypes),
+    ];
+    for (title, symbols) in sections {
+        if !symbols.is_empty() {
+            println!(
+                "{title}: {}",
+                symbols.iter().cloned().collect::<Vec<_>>().join(", ")
+            );
+        }
+    }
+    for (supertype, subtypes) in &diff.narrowed_supertypes {
+        println!(
+            "Supertype {supertype} no longer includes: {}",
+            subtypes.iter().cloned().collect::<Vec<_>>().join(", ")
+        );
+    }
+}
+
+/// Guess a rename for each removed symbol: the added symbol sharing the most `_`-separated words
+/// with it, if there is exactly one such symbol.
+fn suggest_mapping(diff: &GrammarDiff) -> MigrationMapping {
+    fn suggest(
+        removed: &BTreeSet<String>,
+        added: &BTreeSet<String>,
+    ) -> BTreeMap<String, Option<String>> {
+        removed
+            .iter()
+            .map(|old| {
+                let words: BTreeSet<_> = old.split('_').filter(|w| !w.is_empty()).collect();
+                let mut best = None;
+                let mut best_score = 0;
+                let mut tied = false;
+                for new in added {
+                    let score = new
+                        .split('_')
+                        .filter(|w| !w.is_empty())
+                        .collect::<BTreeSet<_>>()
+                        .intersection(&words)
+                        .count();
+                    if score > best_score {
+                        best = Some(new.clone());
+                        best_score = score;
+                        tied = false;
+                    } else if score == best_score && score > 0 {
+                        tied = true;
+                    }
+                }
+                (old.clone(), best.filter(|_| !tied))
+            })
+            .collect()
+    }
+
+    MigrationMapping {
+        nodes: suggest(&diff.removed_nodes, &diff.added_nodes),
+        fields: suggest(&diff.removed_fields, &diff.added_fields),
+    }
+}
+
+/// Ensure that every rename in the mapping targets a symbol of the new grammar.
+fn validate_mapping(mapping: &MigrationMapping, new: &LanguageData) -> Result<(), String> {
+    let nodes = named_nodes(new);
+    for target in mapping.nodes.values().flatten() {
+        if !nodes.contains(target) {
+            return Err(format!(
+                "\"{target}\" is not a node kind of the new grammar"
+            ));
+        }
+    }
+    for target in mapping.fields.values().flatten() {
+        if !new.fields_set.contains(target) {
+            return Err(format!("\"{target}\" is not a field of the new grammar"));
+        }
+    }
+    Ok(())
+}
+
+/// Find the node kind and field references in the query which use a removed symbol, and the
+/// `supertype/subtype` references whose supertype no longer includes the subtype.
+fn find_stale_references(rope: &Rope, diff: &GrammarDiff) -> Vec<StaleReference> {
+    let tree = parse(rope, None);
+    let mut cursor = QueryCursor::new();
+    let provider = TextProviderRope(rope);
+    let mut matches = cursor.matches(&NODE_REFERENCES_QUERY, tree.root_node(), &provider);
+    let mut references = Vec::new();
+    while let Some(match_) = matches.next() {
+        for capture in match_.captures {
+            let (kind, removed) =
+                if NODE_REFERENCES_QUERY.capture_names()[capture.index as usize] == "node" {
+                    (SymbolKind::Node, &diff.removed_nodes)
+                } else {
+                    (SymbolKind::Field, &diff.removed_fields)
+                };
+            let name = capture.node.text(rope);
+            if removed.contains(&name) {
+                references.push(StaleReference {
+                    kind,
+                    name,
+                    range: capture.node.lsp_range(rope),
+                });
+                continue;
+            }
+            let is_supertype = capture.node.parent().is_some_and(|parent| {
+                parent.child_by_field_name("supertype") == Some(capture.node)
+            });
+            let Some(subtype) = capture
+                .node
+                .next_named_sibling()
+                .filter(|_| is_supertype && kind == SymbolKind::Node)
+            else {
+                continue;
+            };
+            let subtype_name = if subtype.kind() == "identifier" {
+                subtype.text(rope)
+            } else {
+                subtype.text(rope).trim_matches('"').to_owned()
+            };
+            if !diff.removed_nodes.contains(&subtype_name)
+                && diff
+                    .narrowed_supertypes
+                    .get(&name)
+                    .is_some_and(|subtypes| subtypes.contains(&subtype_name))
+            {
+                references.push(StaleReference {
+                    kind: SymbolKind::Subtype,
+                    name: format!("{name}/{subtype_name}"),
+                    range: subtype.lsp_range(rope),
+                });
+            }
+        }
+    }
+    references
+        .sort_by_key(|reference| (reference.range.start.line, reference.range.start.character));
+    references
+}
+
+/// Rewrite the references which have a rename in the mapping. Returns the references which could
+/// not be rewritten.
+fn apply_mapping<'a>(
+    rope: &mut Rope,
+    references: &'a [StaleReference],
+    mapping: &MigrationMapping,
+) -> Vec<&'a StaleReference> {
+    let mut unresolved = Vec::new();
+    // Edit from the end of the document so that earlier ranges remain valid
+    for reference in references.iter().rev() {
+        let renames = match reference.kind {
+            SymbolKind::Node => &mapping.nodes,
+            SymbolKind::Field => &mapping.fields,
+            SymbolKind::Subtype => {
+                unresolved.push(reference);
+                continue;
+            }
+        };
+        match renames.get(&reference.name) {
+            Some(Some(new_name)) => edit_rope(rope, reference.range, new_name),
+            _ => unresolved.push(reference),
+        }
+    }
+    unresolved.reverse();
+    unresolved
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        path::Path,
+    };
+
+    use pretty_assertions::assert_eq;
+    use ropey::Rope;
+    use rstest::rstest;
+
+    use crate::{QUERY_LANGUAGE, RUST_LANGUAGE, handlers::did_open::init_language_data};
+
+    use super::{
+        GrammarDiff, MigrationMapping, SymbolKind, apply_mapping, diff_languages,
+        find_stale_references, language_name_from_path, suggest_mapping,
+    };
+
+    fn set(items: &[&str]) -> BTreeSet<String> {
+        items.iter().map(ToString::to_string).collect()
+    }
+
+    #[rstest]
+    #[case("tree-sitter-rust.so", "rust")]
+    #[case("/parsers/libtree-sitter-c_sharp.dylib", "c_sharp")]
+    #[case("lua.wasm", "lua")]
+    fn migrate_language_name(#[case] path: &str, #[case] expected: &str) {
+        assert_eq!(
+            language_name_from_path(Path::new(path)),
+            Some(expected.to_owned())
+        );
+    }
+
+    #[test]
+    fn migrate_diff_languages() {
+        // Arrange
+        let old = init_language_data(QUERY_LANGUAGE.clone(), String::from("query"));
+        let new = init_language_data(RUST_LANGUAGE.clone(), String::from("query"));
+
+        // Act
+        let diff = diff_languages(&old, &new);
+
+        // Assert
+        assert!(diff.removed_nodes.contains("named_node"));
+        assert!(diff.added_nodes.contains("function_item"));
+        assert!(!diff.removed_nodes.contains("identifier"));
+        assert!(!diff.added_nodes.contains("identifier"));
+        assert!(diff.removed_fields.contains("supertype"));
+        assert!(!diff.removed_fields.contains("name"));
+        assert!(diff.added_fields.contains("body"));
+        assert!(diff.added_supertypes.contains("_expression"));
+        assert!(diff.narrowed_supertypes.is_empty());
+    }
+
+    #[test]
+    fn migrate_suggest_mapping() {
+        // Arrange
+        let diff = GrammarDiff {
+            removed_nodes: set(&["function_item", "string_literal", "lifetime", "type_item"]),
+            added_nodes: set(&[
+                "function_definition",
+                "raw_string_literal",
+                "string_content",
+                "type_alias",
+                "type_bound",
+            ]),
+            removed_fields: set(&["params"]),
+            added_fields: set(&["parameters"]),
+            ..Default::default()
+        };
+
+        // Act
+        let mapping = suggest_mapping(&diff);
+
+        // Assert
+        assert_eq!(
+            mapping,
+            MigrationMapping {
+                nodes: BTreeMap::from([
+                    (
+                        String::from("function_item"),
+                        Some(String::from("function_definition"))
+                    ),
+                    (String::from("lifetime"), None),
+                    (
+                        String::from("string_literal"),
+                        Some(String::from("raw_string_literal"))
+                    ),
+                    (String::from("type_item"), None),
+                ]),
+                fields: BTreeMap::from([(String::from("params"), None)]),
+            }
+        );
+    }
+
+    #[test]
+    fn migrate_apply_mapping() {
+        // Arrange
+        let mut rope = Rope::from(
+            r"(function_item
+  name: (identifier) @function
+  !params)
+
+(_expression/string_literal) @string",
+        );
+        let diff = GrammarDiff {
+            removed_nodes: set(&["function_item", "string_literal"]),
+            removed_fields: set(&["name", "params"]),
+            ..Default::default()
+        };
+        let mapping = MigrationMapping {
+            nodes: BTreeMap::from([(
+                String::from("function_item"),
+                Some(String::from("function_definition")),
+            )]),
+            fields: BTreeMap::from([
+                (String::from("name"), Some(String::from("declarator"))),
+                (String::from("params"), None),
+            ]),
+        };
+
+        // Act
+        let references = find_stale_references(&rope, &diff);
+        let unresolved = apply_mapping(&mut rope, &references, &mapping);
+
+        // Assert
+        assert_eq!(references.len(), 4);
+        assert_eq!(
+            unresolved
+                .iter()
+                .map(|r| (r.name.as_str(), r.range.start.line, r.range.start.character))
+                .collect::<Vec<_>>(),
+            vec![("params", 2, 3), ("string_literal", 4, 13)]
+        );
+        assert_eq!(
+            rope.to_string(),
+            r"(function_definition
+  declarator: (identifier) @function
+  !params)
+
+(_expression/string_literal) @string"
+        );
+    }
+
+    #[test]
+    fn migrate_narrowed_supertype() {
+        // Arrange
+        let rope = Rope::from(
+            r#"(_expression/string_literal) @string
+(_expression/"!") @operator
+(_expression/identifier) @variable
+(_pattern/string_literal) @string"#,
+        );
+        let diff = GrammarDiff {
+            narrowed_supertypes: BTreeMap::from([(
+                String::from("_expression"),
+                set(&["string_literal", "!"]),
+            )]),
+            ..Default::default()
+        };
+
+        // Act
+        let references = find_stale_references(&rope, &diff);
+
+        // Assert
+        assert_eq!(
+            references
+                .iter()
+                .map(|r| (
+                    r.kind,
+                    r.name.as_str(),
+                    r.range.start.line,
+                    r.range.start.character
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (SymbolKind::Subtype, "_expression/string_literal", 0, 13),
+                (SymbolKind::Subtype, "_expression/!", 1, 13),
+            ]
+        );
+    }
+}
@@ -1,4 +1,47 @@
 pub mod check;
+pub mod coverage;
+pub mod expand;
 pub mod format;
 pub mod lint;
+pub mod migrate;
+pub mod output;
 pub mod profile;
+pub mod run;
+pub mod test_highlights;
+
+use std::{fs, path::PathBuf};
+
+use dashmap::DashMap;
+use ropey::Rope;
+use tower_lsp::lsp_types::Url;
+
+use crate::{
+    DocumentData, Options,
+    handlers::did_open::populate_import_documents,
+    util::{get_imported_uris, get_query_modules, parse},
+};
+
+/// Read the query file along with its (recursively) imported modules, in the order in which they
+/// are combined. Imported modules are searched for in the given workspace directories.
+fn read_query_modules(
+    uri: &Url,
+    workspace_dirs: &[PathBuf],
+    options: &Options,
+) -> Option<Vec<(Url, Rope)>> {
+    let rope = Rope::from(fs::read_to_string(uri.to_file_path().ok()?).ok()?);
+    let tree = parse(&rope, None);
+    let imported_uris = get_imported_uris(workspace_dirs, options, uri, &rope, &tree);
+    let document_map = DashMap::new();
+    populate_import_documents(&document_map, workspace_dirs, options, &imported_uris);
+    document_map.insert(
+        uri.clone(),
+        DocumentData {
+            rope,
+            tree,
+            version: None,
+            language_name: None,
+            imported_uris,
+        },
+    );
+    Some(get_query_modules(&document_map, uri))
+}
@@ -0,0 +1,385 @@
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    path::Path,
+    sync::{
+        Mutex,
+        atomic::{AtomicI32, Ordering},
+    },
+};
+
+use clap::ValueEnum;
+use serde_json::{Value, json};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range, Url};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable text, printed to stderr as diagnostics are found.
+    #[default]
+    Text,
+    /// A JSON array of diagnostics, printed to stdout.
+    Json,
+    /// A SARIF 2.1.0 log, printed to stdout.
+    Sarif,
+    /// GitHub Actions workflow commands, printed to stdout. These are shown as annotations on pull
+    /// requests.
+    Github,
+    /// A checkstyle XML report, printed to stdout.
+    Checkstyle,
+    /// A `JUnit` XML report, printed to stdout.
+    Junit,
+}
+
+/// The result of checking or linting a set of query files. Diagnostics are collected here (rather
+/// than printed as they are found) for output formats which are printed all at once.
+#[derive(Debug, Default)]
+pub struct DiagnosticReport {
+    exit_code: AtomicI32,
+    files: Mutex<BTreeSet<Url>>,
+    diagnostics: Mutex<Vec<(Url, Diagnostic)>>,
+}
+
+impl DiagnosticReport {
+    /// Mark the run as failed.
+    pub fn fail(&self) {
+        self.exit_code.store(1, Ordering::Relaxed);
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.exit_code.load(Ordering::Relaxed)
+    }
+
+    /// Record that the given file was checked, so that it is listed in reports even if it has no
+    /// diagnostics.
+    pub fn add_file(&self, uri: Url) {
+        self.files
+            .lock()
+            .expect("Report lock should not be poisoned")
+            .insert(uri);
+    }
+
+    pub fn push(&self, uri: Url, diagnostic: Diagnostic) {
+        self.diagnostics
+            .lock()
+            .expect("Report lock should not be poisoned")
+            .push((uri, diagnostic));
+    }
+
+    /// Print the collected diagnostics in the given format. Nothing is printed for the text
+    /// format, since those diagnostics are printed as they are found.
+    pub fn print(&self, format: OutputFormat, workspace: &Path) {
+        let mut diagnostics = self
+            .diagnostics
+            .lock()
+            .expect("Report lock should not be poisoned");
+        diagnostics.sort_by(|(uri_a, diag_a), (uri_b, diag_b)| {
+            (uri_a.as_str(), diag_a.range.start).cmp(&(uri_b.as_str(), diag_b.range.start))
+        });
+        let files = self
+            .files
+            .lock()
+            .expect("Report lock should not be poisoned");
+        let output = match format {
+            OutputFormat::Text => return,
+            OutputFormat::Json => pretty_json(&to_json(&diagnostics)),
+            OutputFormat::Sarif => pretty_json(&to_sarif(&diagnostics, workspace)),
+            OutputFormat::Github => to_github(&diagnostics, workspace),
+            OutputFormat::Checkstyle => to_checkstyle(&files, &diagnostics, workspace),
+            OutputFormat::Junit => to_junit(&files, &diagnostics, workspace),
+        };
+        print!("{output}");
+    }
+}
+
+fn pretty_json(value: &Value) -> String {
+    serde_json::to_string_pretty(value).expect("Output should serialize") + "\n"
+}
+
+/// The path of the given file, relative to the workspace if possible.
+fn display_path(uri: &Url, workspace: &Path) -> String {
+    uri.to_file_path().map_or_else(
+        |()| uri.to_string(),
+        |path| {
+            path.strip_prefix(workspace)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .replace('\\', "/")
+        },
+    )
+}
+
+/// Escape the given text for use in an XML attribute or text node.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#10;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const fn code_str(diagnostic: &Diagnostic) -> Option<&str> {
+    match &diagnostic.code {
+        Some(NumberOrString::String(code)) => Some(code.as_str()),
+        _ => None,
+    }
+}
+
+const fn severity_str(diagnostic: &Diagnostic) -> &'static str {
+    match diagnostic.severity {
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        Some(DiagnosticSeverity::INFORMATION) => "info",
+        Some(DiagnosticSeverity::HINT) => "hint",
+        _ => "error",
+    }
+}
+
+fn to_json(diagnostics: &[(Url, Diagnostic)]) -> Value {
+    diagnostics
+        .iter()
+        .map(|(uri, diagnostic)| {
+            json!({
+                "uri": uri,
+                "range": diagnostic.range,
+                "severity": severity_str(diagnostic),
+                "code": code_str(diagnostic),
+                "message": diagnostic.message,
+                "relatedInformation": diagnostic.related_information.iter().flatten().map(|info| {
+                    json!({
+                        "uri": info.location.uri,
+                        "range": info.location.range,
+                        "message": info.message,
+                    })
+                }).collect::<Vec<_>>(),
+            })
+        })
+        .collect()
+}
+
+/// The SARIF location of the given range, relative to the workspace if possible.
+fn sarif_location(uri: &Url, range: Range, workspace: &Path) -> Value {
+    let relative_path = uri
+        .to_file_path()
+        .is_ok_and(|path| path.starts_with(workspace))
+        .then(|| display_path(uri, workspace));
+    let artifact_location = relative_path.map_or_else(
+        || json!({ "uri": uri }),
+        |path| json!({ "uri": path, "uriBaseId": "%SRCROOT%" }),
+    );
+    json!({
+        "physicalLocation": {
+            "artifactLocation": artifact_location,
+            "region": {
+                "startLine": range.start.line + 1,
+                "startColumn": range.start.character + 1,
+                "endLine": range.end.line + 1,
+                "endColumn": range.end.character + 1,
+            },
+        },
+    })
+}
+
+fn to_sarif(diagnostics: &[(Url, Diagnostic)], workspace: &Path) -> Value {
+    let mut rules: Vec<_> = diagnostics
+        .iter()
+        .filter_map(|(_, diagnostic)| code_str(diagnostic))
+        .collect();
+    rules.sort_unstable();
+    rules.dedup();
+    let results: Vec<_> = diagnostics
+        .iter()
+        .map(|(uri, diagnostic)| {
+            let level = match diagnostic.severity {
+                Some(DiagnosticSeverity::WARNING) => "warning",
+                Some(DiagnosticSeverity::INFORMATION | DiagnosticSeverity::HINT) => "note",
+                _ => "error",
+            };
+            let related_locations: Vec<_> = diagnostic
+                .related_information
+                .iter()
+                .flatten()
+                .map(|info| {
+                    let mut location =
+                        sarif_location(&info.location.uri, info.location.range, workspace);
+                    location["message"] = json!({ "text": info.message });
+                    location
+                })
+                .collect();
+            json!({
+                "ruleId": code_str(diagnostic),
+                "level": level,
+                "message": { "text": diagnostic.message },
+                "locations": [sarif_location(uri, diagnostic.range, workspace)],
+                "relatedLocations": related_locations,
+            })
+        })
+        .collect();
+    let workspace_uri = Url::from_directory_path(workspace)
+        .map(String::from)
+        .unwrap_or_default();
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "ts_query_ls",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": rules.iter().map(|id| json!({ "id": id })).collect::<Vec<_>>(),
+                },
+            },
+            "originalUriBaseIds": {
+                "%SRCROOT%": { "uri": workspace_uri },
+            },
+            "columnKind": "utf16CodeUnits",
+            "results": results,
+        }],
+    })
+}
+
+/// Escape the given text for use in a GitHub Actions workflow command. Property values must
+/// additionally escape the property delimiters.
+fn github_escape(text: &str, property: bool) -> String {
+    let escaped = text
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+    if property {
+        escaped.replace(':', "%3A").replace(',', "%2C")
+    } else {
+        escaped
+    }
+}
+
+fn to_github(diagnostics: &[(Url, Diagnostic)], workspace: &Path) -> String {
+    let mut output = String::new();
+    for (uri, diagnostic) in diagnostics {
+        let command = match diagnostic.severity {
+            Some(DiagnosticSeverity::WARNING) => "warning",
+            Some(DiagnosticSeverity::INFORMATION | DiagnosticSeverity::HINT) => "notice",
+            _ => "error",
+        };
+        let range = diagnostic.range;
+        let _ = write!(
+            output,
+            "::{command} file={},line={},col={},endLine={},endColumn={}",
+            github_escape(&display_path(uri, workspace), true),
+            range.start.line + 1,
+            range.start.character + 1,
+            range.end.line + 1,
+            range.end.character + 1,
+        );
+        if let Some(code) = code_str(diagnostic) {
+            let _ = write!(output, ",title={}", github_escape(code, true));
+        }
+        let _ = writeln!(output, "::{}", github_escape(&diagnostic.message, false));
+    }
+    output
+}
+
+/// Group the diagnostics by file. Every checked file is included, even those without diagnostics.
+fn diagnostics_by_file<'a>(
+    files: &'a BTreeSet<Url>,
+    diagnostics: &'a [(Url, Diagnostic)],
+) -> Vec<(&'a Url, Vec<&'a Diagnostic>)> {
+    let uris: BTreeSet<_> = files
+        .iter()
+        .chain(diagnostics.iter().map(|(uri, _)| uri))
+        .collect();
+    uris.into_iter()
+        .map(|uri| {
+            let file_diagnostics = diagnostics
+                .iter()
+                .filter(|(diag_uri, _)| diag_uri == uri)
+                .map(|(_, diagnostic)| diagnostic)
+                .collect();
+            (uri, file_diagnostics)
+        })
+        .collect()
+}
+
+fn to_checkstyle(
+    files: &BTreeSet<Url>,
+    diagnostics: &[(Url, Diagnostic)],
+    workspace: &Path,
+) -> String {
+    let mut output = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    output.push_str("<checkstyle version=\"4.3\">\n");
+    for (uri, file_diagnostics) in diagnostics_by_file(files, diagnostics) {
+        let _ = writeln!(
+            output,
+            "  <file name=\"{}\">",
+            xml_escape(&display_path(uri, workspace))
+        );
+        for diagnostic in file_diagnostics {
+            let severity = match diagnostic.severity {
+                Some(DiagnosticSeverity::WARNING) => "warning",
+                Some(DiagnosticSeverity::INFORMATION | DiagnosticSeverity::HINT) => "info",
+                _ => "error",
+            };
+            let _ = writeln!(
+                output,
+                "    <error line=\"{}\" column=\"{}\" severity=\"{severity}\" message=\"{}\" \
+                 source=\"ts_query_ls.{}\"/>",
+                diagnostic.range.start.line + 1,
+                diagnostic.range.start.character + 1,
+                xml_escape(&diagnostic.message),
+                xml_escape(code_str(diagnostic).unwrap_or("diagnostic")),
+            );
+        }
+        output.push_str("  </file>\n");
+    }
+    output.push_str("</checkstyle>\n");
+    output
+}
+
+/// Each checked file is a test suite. A file's diagnostics are its failed test cases; a file
+/// without diagnostics has a single passing test case.
+fn to_junit(files: &BTreeSet<Url>, diagnostics: &[(Url, Diagnostic)], workspace: &Path) -> String {
+    let by_file = diagnostics_by_file(files, diagnostics);
+    let tests: usize = by_file.iter().map(|(_, diags)| diags.len().max(1)).sum();
+    let mut output = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        output,
+        "<testsuites name=\"ts_query_ls\" tests=\"{tests}\" failures=\"{}\">",
+        diagnostics.len()
+    );
+    for (uri, file_diagnostics) in by_file {
+        let path = xml_escape(&display_path(uri, workspace));
+        let _ = writeln!(
+            output,
+            "  <testsuite name=\"{path}\" tests=\"{}\" failures=\"{}\">",
+            file_diagnostics.len().max(1),
+            file_diagnostics.len()
+        );
+        if file_diagnostics.is_empty() {
+            let _ = writeln!(
+                output,
+                "    <testcase name=\"{path}\" classname=\"{path}\"/>"
+            );
+        }
+        for diagnostic in file_diagnostics {
+            let code = xml_escape(code_str(diagnostic).unwrap_or("diagnostic"));
+            let line = diagnostic.range.start.line + 1;
+            let column = diagnostic.range.start.character + 1;
+            let message = xml_escape(&diagnostic.message);
+            let _ = writeln!(
+                output,
+                "    <testcase name=\"{code} ({line}:{column})\" classname=\"{path}\">\n      \
+                 <failure message=\"{message}\" type=\"{code}\">{path}:{line}:{column}: \
+                 {message}</failure>\n    </testcase>"
+            );
+        }
+        output.push_str("  </testsuite>\n");
+    }
+    output.push_str("</testsuites>\n");
+    output
+}
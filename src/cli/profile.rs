@@ -13,14 +13,11 @@ use tree_sitter::{Parser, Query, QueryCursor, StreamingIterator as _};
 use crate::{
     LanguageData, Options, QUERY_LANGUAGE,
     handlers::did_open::init_language_data,
-    util::{self, get_scm_files},
+    util::{self, PATTERN_DEFINITION_QUERY, get_scm_files},
 };
 
 static LANGUAGE_CACHE: LazyLock<DashMap<String, Arc<LanguageData>>> = LazyLock::new(DashMap::new);
 
-static PATTERN_DEFINITION_QUERY: LazyLock<Query> =
-    LazyLock::new(|| Query::new(&QUERY_LANGUAGE, "(program (definition) @def)").unwrap());
-
 pub async fn profile_directories(directories: &[PathBuf], config: String, per_file: bool) {
     let Ok(options) = serde_json::from_str::<Options>(&config) else {
         eprintln!("Could not parse the provided configuration");
@@ -95,6 +92,7 @@ pub async fn profile_directories(directories: &[PathBuf], config: String, per_fi
         .into_iter()
         .flat_map(|r| r.unwrap_or_default())
         .collect::<Vec<_>>();
+    #[allow(clippy::unnecessary_sort_by)]
     results.sort_unstable_by(|a, b| a.2.cmp(&b.2));
     for (path, row, time) in results {
         let time = format!("{:.2}ms", time as f64 / 1000.0);
@@ -0,0 +1,68 @@
+use std::{fs, path::Path};
+
+use tower_lsp::lsp_types::Url;
+use ts_query_ls::Options;
+
+use crate::{handlers::execute_command::run_query, util};
+
+/// Run the query file against the source file, printing each match. Returns the exit code.
+pub fn run_query_file(
+    query_path: &Path,
+    source_path: &Path,
+    config: &str,
+    language: Option<String>,
+) -> i32 {
+    let Ok(options) = serde_json::from_str::<Options>(config) else {
+        eprintln!("Could not parse the provided configuration");
+        return 1;
+    };
+    let Ok(query_source) = fs::read_to_string(query_path) else {
+        eprintln!("Failed to read {}", query_path.display());
+        return 1;
+    };
+    let Ok(source) = fs::read_to_string(source_path) else {
+        eprintln!("Failed to read {}", source_path.display());
+        return 1;
+    };
+    let language_name = language.or_else(|| {
+        let uri = Url::from_file_path(query_path.canonicalize().ok()?).ok()?;
+        util::get_language_name(&uri, &options)
+    });
+    let Some(language_name) = language_name else {
+        eprintln!(
+            "Could not determine the language for {}",
+            query_path.display()
+        );
+        return 1;
+    };
+    let Some(language) = util::get_language(&language_name, &options) else {
+        eprintln!("Could not retrieve language {language_name:?}");
+        return 1;
+    };
+
+    let matches = match run_query(&language, &query_source, &source) {
+        Ok(matches) => matches,
+        Err(err) => {
+            eprintln!("Failed to run {}: {err}", query_path.display());
+            return 1;
+        }
+    };
+    for (i, match_) in matches.iter().enumerate() {
+        println!("Match {i} (pattern {}):", match_.pattern_index);
+        for capture in &match_.captures {
+            let start = capture.range.start;
+            let end = capture.range.end;
+            println!(
+                "  @{} ({}) {}:{}-{}:{} {:?}",
+                capture.name,
+                capture.kind,
+                start.line + 1,
+                start.character + 1,
+                end.line + 1,
+                end.character + 1,
+                capture.text
+            );
+        }
+    }
+    0
+}
@@ -0,0 +1,332 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use regex::Regex;
+use streaming_iterator::StreamingIterator as _;
+use tree_sitter::{Language, Node, Point, Query, QueryCursor, Tree};
+
+use crate::{
+    Options,
+    util::{get_file_uris, get_language, language_parser},
+};
+
+use super::read_query_modules;
+
+/// Matches the body of a highlight assertion comment, e.g. `// <- @keyword` or `# ^ !variable`.
+static ASSERTION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\S*\s*(\^|<-)\s*(!?)@?([\w.\-]+)").unwrap());
+
+/// An expectation about the highlight at a position in a sample source file.
+#[derive(Debug, PartialEq, Eq)]
+struct Assertion {
+    position: Point,
+    negative: bool,
+    capture: String,
+}
+
+/// A failed highlight assertion.
+#[derive(Debug, PartialEq, Eq)]
+struct AssertionFailure {
+    position: Point,
+    message: String,
+}
+
+/// Run the language's `highlights.scm` query against the given sample files (or all files in the
+/// given directories), checking their highlight assertion comments. Returns the exit code.
+pub fn test_highlights(
+    paths: &[PathBuf],
+    language_name: &str,
+    config: &str,
+    workspace: Option<PathBuf>,
+) -> i32 {
+    let Ok(options) = serde_json::from_str::<Options>(config) else {
+        eprintln!("Could not parse the provided configuration");
+        return 1;
+    };
+    let workspace = workspace
+        .unwrap_or_else(|| env::current_dir().expect("Failed to get current directory"))
+        .canonicalize()
+        .expect("Workspace path should be valid");
+    let Some(language) = get_language(language_name, &options) else {
+        eprintln!("Could not retrieve language {language_name:?}");
+        return 1;
+    };
+    let Some(query_source) = get_highlights_source(&workspace, &options, language_name) else {
+        eprintln!("Could not find a highlights query for language {language_name:?}");
+        return 1;
+    };
+    let query = match Query::new(&language, &query_source) {
+        Ok(query) => query,
+        Err(err) => {
+            eprintln!("Invalid highlights query for language {language_name:?}: {err}");
+            return 1;
+        }
+    };
+
+    let mut exit_code = 0;
+    let (mut passed, mut failed) = (0, 0);
+    let files = paths.iter().flat_map(|path| {
+        ignore::Walk::new(path)
+            .filter_map(Result::ok)
+            .filter(|e| e.file_type().is_some_and(|ft| ft.is_file()))
+            .map(ignore::DirEntry::into_path)
+    });
+    for path in files {
+        let Ok(source) = fs::read_to_string(&path) else {
+            eprintln!("Failed to read {}", path.display());
+            exit_code = 1;
+            continue;
+        };
+        let (assertion_count, failures) = match check_assertions(&language, &query, &source) {
+            Ok(results) => results,
+            Err(err) => {
+                eprintln!("Failed to parse {}: {err}", path.display());
+                exit_code = 1;
+                continue;
+            }
+        };
+        passed += assertion_count - failures.len();
+        failed += failures.len();
+        for failure in failures {
+            exit_code = 1;
+            eprintln!(
+                "Failure in \"{}\" on line {}, col {}:\n  {}",
+                path.display(),
+                failure.position.row + 1,
+                failure.position.column + 1,
+                failure.message
+            );
+        }
+    }
+    println!("{passed} assertions passed, {failed} failed");
+    exit_code
+}
+
+/// Get the text of the language's highlights query, with its imported modules prepended.
+fn get_highlights_source(
+    workspace: &Path,
+    options: &Options,
+    language_name: &str,
+) -> Option<String> {
+    let workspace_dirs = &[workspace.to_owned()];
+    let uri = get_file_uris(workspace_dirs, options, language_name, "highlights")
+        .into_iter()
+        .next()?;
+    Some(
+        read_query_modules(&uri, workspace_dirs, options)?
+            .into_iter()
+            .map(|(_, rope)| rope.to_string())
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )
+}
+
+/// Check the highlight assertions in the source text, returning the number of assertions and the
+/// failures among them.
+fn check_assertions(
+    language: &Language,
+    query: &Query,
+    source: &str,
+) -> Result<(usize, Vec<AssertionFailure>), String> {
+    let tree = language_parser(language)?
+        .parse(source, None)
+        .ok_or_else(|| String::from("Parsing failed"))?;
+    let assertions = get_assertions(&tree, source);
+
+    let line_starts: Vec<_> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let mut highlights = Vec::new();
+    let mut cursor = QueryCursor::new();
+    let capture_names = query.capture_names();
+    let mut captures = cursor.captures(query, tree.root_node(), source.as_bytes());
+    while let Some((match_, index)) = captures.next() {
+        let capture = match_.captures[*index];
+        let name = capture_names[capture.index as usize];
+        if !name.starts_with('_') {
+            highlights.push((capture.node, match_.pattern_index, name));
+        }
+    }
+
+    let failures = assertions
+        .iter()
+        .filter_map(|assertion| {
+            let byte = line_starts.get(assertion.position.row)? + assertion.position.column;
+            let actual = get_highlight_at(&highlights, byte);
+            let passed = (actual == Some(assertion.capture.as_str())) != assertion.negative;
+            if passed {
+                return None;
+            }
+            let actual = actual.map_or_else(|| String::from("no highlight"), |a| format!("@{a}"));
+            let message = if assertion.negative {
+                format!("Expected highlight other than @{}", assertion.capture)
+            } else {
+                format!("Expected @{}, found {actual}", assertion.capture)
+            };
+            Some(AssertionFailure {
+                position: assertion.position,
+                message,
+            })
+        })
+        .collect();
+    Ok((assertions.len(), failures))
+}
+
+/// Return the highlight at the given byte, resolved the same way as the tree-sitter CLI: the
+/// innermost captured node wins, followed by the earliest pattern.
+fn get_highlight_at<'a>(highlights: &[(Node, usize, &'a str)], byte: usize) -> Option<&'a str> {
+    let depth = |mut node: Node| {
+        let mut depth = 0;
+        while let Some(parent) = node.parent() {
+            node = parent;
+            depth += 1;
+        }
+        depth
+    };
+    highlights
+        .iter()
+        .filter(|(node, _, _)| node.byte_range().contains(&byte))
+        .min_by_key(|(node, pattern_index, _)| {
+            (
+                node.byte_range().len(),
+                std::cmp::Reverse(depth(*node)),
+                *pattern_index,
+            )
+        })
+        .map(|(_, _, name)| *name)
+}
+
+/// Find the assertion comments in the source file. An assertion applies to the nearest preceding
+/// line which does not itself contain an assertion. `<-` refers to the comment's starting column,
+/// and `^` refers to the caret's column.
+fn get_assertions(tree: &Tree, source: &str) -> Vec<Assertion> {
+    let mut comments = Vec::new();
+    let mut cursor = tree.walk();
+    let mut visited_children = false;
+    loop {
+        let node = cursor.node();
+        if !visited_children && node.kind().contains("comment") {
+            comments.push(node);
+        }
+        if !visited_children && cursor.goto_first_child() {
+            continue;
+        }
+        if cursor.goto_next_sibling() {
+            visited_children = false;
+        } else if cursor.goto_parent() {
+            visited_children = true;
+        } else {
+            break;
+        }
+    }
+
+    let mut assertions: Vec<Assertion> = Vec::new();
+    let mut assertion_rows = Vec::new();
+    for comment in comments {
+        let Some(caps) = comment
+            .utf8_text(source.as_bytes())
+            .ok()
+            .and_then(|text| ASSERTION_REGEX.captures(text))
+        else {
+            continue;
+        };
+        let start = comment.start_position();
+        let arrow = caps.get(1).unwrap();
+        let column = if arrow.as_str() == "^" {
+            start.column + arrow.start()
+        } else {
+            start.column
+        };
+        assertion_rows.push(start.row);
+        assertions.push(Assertion {
+            position: Point::new(start.row, column),
+            negative: !caps[2].is_empty(),
+            capture: caps[3].to_string(),
+        });
+    }
+    for assertion in &mut assertions {
+        let mut row = assertion.position.row;
+        while row > 0 {
+            row -= 1;
+            if !assertion_rows.contains(&row) {
+                break;
+            }
+        }
+        assertion.position.row = row;
+    }
+    assertions
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+    use tree_sitter::{Point, Query};
+
+    use crate::RUST_LANGUAGE;
+
+    use super::{AssertionFailure, check_assertions};
+
+    const HIGHLIGHTS: &str = r#"(function_item name: (identifier) @function)
+(identifier) @variable
+"fn" @keyword
+(integer_literal) @number
+"#;
+
+    #[rstest]
+    #[case(
+        r"fn main() {
+// <- @keyword
+// ^ @function
+    let x = 42;
+    //  ^ @variable
+    //      ^ @number
+    //      ^ !variable
+}",
+        5,
+        &[]
+    )]
+    #[case(
+        r"fn main() {
+//        ^ @keyword
+//   ^ !function
+// ^ @keyword
+    let x = 42;
+    //  ^ @number
+}",
+        4,
+        &[
+            (0, 10, "Expected @keyword, found no highlight"),
+            (0, 5, "Expected highlight other than @function"),
+            (0, 3, "Expected @keyword, found @function"),
+            (4, 8, "Expected @number, found @variable"),
+        ]
+    )]
+    fn highlight_assertions(
+        #[case] source: &str,
+        #[case] expected_count: usize,
+        #[case] expected_failures: &[(usize, usize, &str)],
+    ) {
+        // Arrange
+        let query = Query::new(&RUST_LANGUAGE, HIGHLIGHTS).unwrap();
+
+        // Act
+        let (count, failures) = check_assertions(&RUST_LANGUAGE, &query, source).unwrap();
+
+        // Assert
+        assert_eq!(count, expected_count);
+        assert_eq!(
+            failures,
+            expected_failures
+                .iter()
+                .map(|(row, column, message)| AssertionFailure {
+                    position: Point::new(*row, *column),
+                    message: (*message).to_string(),
+                })
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    env, fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    process,
+    time::SystemTime,
+};
+
+use tracing::{info, warn};
+
+use crate::parser_discovery::cache_home;
+
+/// The target the server was built for, which parsers compiled from grammar sources are built for
+/// as well.
+const BUILD_TARGET: &str = env!("BUILD_TARGET");
+
+/// The source files of a grammar which are compiled into its parser, relative to the grammar's
+/// directory. Only the parser itself is required.
+const SOURCE_FILES: [&str; 2] = ["src/parser.c", "src/scanner.c"];
+
+/// Whether the given directory is a grammar source directory containing a generated parser.
+pub fn is_grammar_directory(directory: &Path) -> bool {
+    directory.join(SOURCE_FILES[0]).is_file()
+}
+
+/// The latest modification time of the source files of the grammar in the given directory, which
+/// are all of the C sources and headers within its `src` directory (e.g. `src/tree_sitter/parser.h`).
+pub fn grammar_modified(directory: &Path) -> Option<SystemTime> {
+    let mut directories = vec![directory.join("src")];
+    let mut modified = None;
+    while let Some(directory) = directories.pop() {
+        let Ok(entries) = fs::read_dir(&directory) else {
+            continue;
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            if file_type.is_dir() {
+                directories.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "c" || ext == "h")
+                && let Ok(file_modified) = entry.metadata().and_then(|meta| meta.modified())
+            {
+                modified = modified.max(Some(file_modified));
+            }
+        }
+    }
+    modified
+}
+
+/// The directory in which parsers compiled from grammar sources are cached.
+pub fn parser_cache_directory() -> PathBuf {
+    cache_home()
+        .unwrap_or_else(env::temp_dir)
+        .join("ts_query_ls")
+        .join("parsers")
+}
+
+/// The path of the shared library in the cache directory which the parser of the grammar in the
+/// given directory is compiled into.
+pub fn library_path(name: &str, grammar_directory: &Path, cache_directory: &Path) -> PathBuf {
+    // Grammars of the same name in different directories must not share a library
+    let mut hasher = DefaultHasher::new();
+    grammar_directory
+        .canonicalize()
+        .unwrap_or_else(|_| grammar_directory.to_path_buf())
+        .hash(&mut hasher);
+    cache_directory.join(format!(
+        "{name}-{:016x}{}",
+        hasher.finish(),
+        env::consts::DLL_SUFFIX
+    ))
+}
+
+/// Whether the cached library of the grammar in the given directory is newer than the grammar's
+/// sources, i.e. it does not need to be compiled.
+pub fn is_grammar_compiled(name: &str, grammar_directory: &Path, cache_directory: &Path) -> bool {
+    let library_modified = fs::metadata(library_path(name, grammar_directory, cache_directory))
+        .and_then(|meta| meta.modified())
+        .ok();
+    library_modified.is_some_and(|library_modified| {
+        grammar_modified(grammar_directory).is_some_and(|modified| modified <= library_modified)
+    })
+}
+
+/// Compile the parser of the grammar in the given directory into a shared library within the cache
+/// directory, unless the cached library is newer than the grammar's sources. Returns the path of
+/// the shared library.
+pub fn compile_grammar(
+    name: &str,
+    grammar_directory: &Path,
+    cache_directory: &Path,
+) -> Option<PathBuf> {
+    if !is_grammar_directory(grammar_directory) {
+        return None;
+    }
+    let library_path = library_path(name, grammar_directory, cache_directory);
+    if is_grammar_compiled(name, grammar_directory, cache_directory) {
+        return Some(library_path);
+    }
+
+    info!(
+        "Compiling parser {name} from {}",
+        grammar_directory.display()
+    );
+    fs::create_dir_all(cache_directory).ok()?;
+    let source_directory = grammar_directory.join("src");
+    let sources = SOURCE_FILES
+        .iter()
+        .map(|file| grammar_directory.join(file))
+        .filter(|path| path.is_file());
+    let compiler = match cc::Build::new()
+        .cargo_metadata(false)
+        .cargo_warnings(false)
+        .target(BUILD_TARGET)
+        .host(BUILD_TARGET)
+        .opt_level(2)
+        .debug(false)
+        .std("c11")
+        .include(&source_directory)
+        .try_get_compiler()
+    {
+        Ok(compiler) => compiler,
+        Err(err) => {
+            warn!("No C compiler found to compile parser {name}: {err}");
+            return None;
+        }
+    };
+
+    // Compile to a temporary file first, so that a partially written library is never loaded
+    let temp_path = library_path.with_extension(format!("{}.tmp", process::id()));
+    let mut command = compiler.to_command();
+    if compiler.is_like_msvc() {
+        command.args(["/nologo", "/LD", "/utf-8"]);
+        command.arg(format!("/Fe{}", temp_path.display()));
+        command.arg(format!("/Fo{}\\", cache_directory.display()));
+    } else {
+        command.arg(if cfg!(target_os = "macos") {
+            "-dynamiclib"
+        } else {
+            "-shared"
+        });
+        command.arg("-o").arg(&temp_path);
+    }
+    command.args(sources);
+    let output = match command.output() {
+        Ok(output) => output,
+        Err(err) => {
+            warn!("Failed to run the C compiler for parser {name}: {err}");
+            return None;
+        }
+    };
+    if !output.status.success() {
+        warn!(
+            "Failed to compile parser {name}:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let _ = fs::remove_file(&temp_path);
+        return None;
+    }
+    if let Err(err) = fs::rename(&temp_path, &library_path) {
+        warn!("Failed to cache parser {name}: {err}");
+        let _ = fs::remove_file(&temp_path);
+        return None;
+    }
+    Some(library_path)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs,
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    use pretty_assertions::assert_eq;
+
+    use crate::test_helpers::helpers::TempDir;
+
+    use super::{compile_grammar, is_grammar_compiled, is_grammar_directory};
+
+    const GRAMMAR_DIRECTORY: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/grammar_sources/fake"
+    );
+
+    #[test]
+    fn compile_grammar_caches_library() {
+        // Arrange
+        let cache_directory = TempDir::new("compiler-test");
+        let grammar_directory = Path::new(GRAMMAR_DIRECTORY);
+        let json_directory = grammar_directory.with_file_name("json");
+
+        // Act
+        let is_compiled_before = is_grammar_compiled("fake", grammar_directory, &cache_directory);
+        let library_path = compile_grammar("fake", grammar_directory, &cache_directory).unwrap();
+        let modified = fs::metadata(&library_path).unwrap().modified().unwrap();
+        let cached_path = compile_grammar("fake", grammar_directory, &cache_directory).unwrap();
+        let json_library_path = compile_grammar("json", &json_directory, &cache_directory);
+
+        // Assert
+        assert!(is_grammar_directory(grammar_directory));
+        assert!(!is_compiled_before);
+        assert!(library_path.is_file());
+        assert!(is_grammar_compiled(
+            "fake",
+            grammar_directory,
+            &cache_directory
+        ));
+        // The cached library is reused while the grammar is unchanged
+        assert_eq!(cached_path, library_path);
+        assert_eq!(
+            fs::metadata(&cached_path).unwrap().modified().unwrap(),
+            modified
+        );
+        // Directories without a generated parser are not compiled
+        assert!(!is_grammar_directory(&json_directory));
+        assert_eq!(json_library_path, None);
+    }
+
+    #[test]
+    fn compile_grammar_after_header_change() {
+        // Arrange
+        let directory = TempDir::new("compiler-header-test");
+        let cache_directory = directory.join("cache");
+        let grammar_directory = directory.join("fake");
+        let header_directory = grammar_directory.join("src").join("tree_sitter");
+        fs::create_dir_all(&header_directory).unwrap();
+        for file in ["parser.c", "scanner.c", "scanner.h"] {
+            fs::copy(
+                Path::new(GRAMMAR_DIRECTORY).join("src").join(file),
+                grammar_directory.join("src").join(file),
+            )
+            .unwrap();
+        }
+        let header_path = header_directory.join("parser.h");
+        fs::write(&header_path, "").unwrap();
+        compile_grammar("fake", &grammar_directory, &cache_directory).unwrap();
+
+        // Act
+        fs::File::options()
+            .write(true)
+            .open(&header_path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_mins(1))
+            .unwrap();
+
+        // Assert
+        assert!(!is_grammar_compiled(
+            "fake",
+            &grammar_directory,
+            &cache_directory
+        ));
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+    time::SystemTime,
+};
+
+use dashmap::DashMap;
+use ropey::Rope;
+use streaming_iterator::StreamingIterator as _;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity};
+use tracing::warn;
+use tree_sitter::{Language, Node, Query, QueryCursor, Tree};
+
+use crate::{
+    handlers::diagnostic::DiagnosticCode,
+    util::{NodeUtil as _, PATTERN_DEFINITION_QUERY, TextProviderRope, language_parser},
+};
+
+/// A parsed sample source file.
+pub struct CorpusFile {
+    pub source: String,
+    pub tree: Tree,
+}
+
+/// A language name and corpus file path.
+type CorpusKey = (String, PathBuf);
+
+/// Parsed corpus files. Files are only re-parsed when their modification time changes.
+static CORPUS_CACHE: LazyLock<DashMap<CorpusKey, (SystemTime, Arc<CorpusFile>)>> =
+    LazyLock::new(DashMap::new);
+
+/// Forget the parsed corpus files of the given language, e.g. when its parser is reloaded.
+pub fn clear_corpus_cache(language_name: &str) {
+    CORPUS_CACHE.retain(|(name, _), _| name != language_name);
+}
+
+/// Resolve a configured corpus directory. Relative directories are resolved against the first
+/// workspace directory which contains them (or the first workspace directory, if none do).
+pub fn resolve_corpus_directory(directory: &str, workspace_dirs: &[PathBuf]) -> PathBuf {
+    let directory = Path::new(directory);
+    if directory.is_absolute() {
+        return directory.to_path_buf();
+    }
+    workspace_dirs
+        .iter()
+        .map(|workspace_dir| workspace_dir.join(directory))
+        .find(|path| path.is_dir())
+        .or_else(|| workspace_dirs.first().map(|dir| dir.join(directory)))
+        .unwrap_or_else(|| directory.to_path_buf())
+}
+
+/// The sorted paths of the files in the corpus directory (searched recursively).
+fn corpus_paths(directory: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<_> = ignore::Walk::new(directory)
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_some_and(|ft| ft.is_file()))
+        .map(ignore::DirEntry::into_path)
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// The modification time of every file in the corpus directory, which changes whenever the corpus
+/// does.
+pub fn corpus_modification_times(directory: &Path) -> Vec<(PathBuf, Option<SystemTime>)> {
+    corpus_paths(directory)
+        .into_iter()
+        .map(|path| {
+            let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
+            (path, modified)
+        })
+        .collect()
+}
+
+/// Parse every file in the corpus directory (searched recursively) with the given language.
+pub fn load_corpus(
+    language_name: &str,
+    language: &Language,
+    directory: &Path,
+) -> Vec<Arc<CorpusFile>> {
+    let paths = corpus_paths(directory);
+
+    // Forget the files of this corpus which no longer exist
+    CORPUS_CACHE.retain(|(name, path), _| {
+        name != language_name || !path.starts_with(directory) || paths.binary_search(path).is_ok()
+    });
+
+    let mut parser = None;
+    let mut files = Vec::with_capacity(paths.len());
+    for path in paths {
+        let Ok(modified) = fs::metadata(&path).and_then(|meta| meta.modified()) else {
+            continue;
+        };
+        let key = (language_name.to_string(), path);
+        if let Some(entry) = CORPUS_CACHE.get(&key)
+            && entry.0 == modified
+        {
+            files.push(entry.1.clone());
+            continue;
+        }
+        let Ok(source) = fs::read_to_string(&key.1) else {
+            continue;
+        };
+        if parser.is_none() {
+            match language_parser(language) {
+                Ok(new_parser) => parser = Some(new_parser),
+                Err(err) => {
+                    warn!("Failed to create parser for {language_name}: {err}");
+                    return Vec::new();
+                }
+            }
+        }
+        let Some(tree) = parser.as_mut().and_then(|p| p.parse(&source, None)) else {
+            continue;
+        };
+        let file = Arc::new(CorpusFile { source, tree });
+        CORPUS_CACHE.insert(key, (modified, file.clone()));
+        files.push(file);
+    }
+    files
+}
+
+/// Count how many times each top-level definition in the query matches across the corpus. The
+/// query must have been compiled from the given source.
+pub fn definition_match_counts<'t>(
+    root: Node<'t>,
+    rope: &Rope,
+    query: &Query,
+    corpus: &[Arc<CorpusFile>],
+) -> Vec<(Node<'t>, usize)> {
+    let mut pattern_counts = vec![0; query.pattern_count()];
+    let mut cursor = QueryCursor::new();
+    for file in corpus {
+        let mut matches = cursor.matches(query, file.tree.root_node(), file.source.as_bytes());
+        while let Some(match_) = matches.next() {
+            pattern_counts[match_.pattern_index] += 1;
+        }
+    }
+
+    let mut definitions: Vec<_> = cursor
+        .matches(&PATTERN_DEFINITION_QUERY, root, &TextProviderRope(rope))
+        .map_deref(|match_| match_.captures.iter().map(|cap| (cap.node, 0)))
+        .flatten()
+        .collect();
+    for (pattern_index, count) in pattern_counts.into_iter().enumerate() {
+        let start_byte = query.start_byte_for_pattern(pattern_index);
+        if let Some((_, total)) = definitions
+            .iter_mut()
+            .find(|(def, _)| def.byte_range().contains(&start_byte))
+        {
+            *total += count;
+        }
+    }
+    definitions
+}
+
+/// Report the top-level definitions in the query which never match the corpus. Nothing is reported
+/// for an empty corpus.
+pub fn get_dead_pattern_diagnostics(
+    tree: &Tree,
+    rope: &Rope,
+    language: &Language,
+    corpus: &[Arc<CorpusFile>],
+) -> Vec<Diagnostic> {
+    if corpus.is_empty() {
+        return Vec::new();
+    }
+    let Ok(query) = Query::new(language, &rope.to_string()) else {
+        return Vec::new();
+    };
+    definition_match_counts(tree.root_node(), rope, &query, corpus)
+        .into_iter()
+        .filter(|(_, count)| *count == 0)
+        .map(|(def, _)| Diagnostic {
+            message: String::from("This pattern never matches the corpus"),
+            severity: Some(DiagnosticSeverity::WARNING),
+            range: def.lsp_range(rope),
+            code: DiagnosticCode::DeadPattern.into(),
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use pretty_assertions::assert_eq;
+    use ropey::Rope;
+    use rstest::rstest;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    use crate::{Options, RUST_LANGUAGE, handlers::diagnostic::finish_diagnostics, util::parse};
+
+    use super::{get_dead_pattern_diagnostics, load_corpus, resolve_corpus_directory};
+
+    const CORPUS_DIR: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/example_source_files"
+    );
+
+    #[rstest]
+    #[case(r"(function_item) @function", CORPUS_DIR, &[])]
+    #[case(
+        r"(function_item) @function
+
+(struct_item
+  name: (type_identifier) @type)",
+        CORPUS_DIR,
+        &[Range::new(Position::new(2, 0), Position::new(3, 32))]
+    )]
+    #[case(
+        r"(struct_item) @type",
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/nonexistent"),
+        &[]
+    )]
+    #[case(
+        r"(function_item) @function
+
+; ts_query_ls-ignore: dead-pattern
+(struct_item
+  name: (type_identifier) @type)",
+        CORPUS_DIR,
+        &[]
+    )]
+    fn dead_pattern_diagnostics(
+        #[case] source: &str,
+        #[case] corpus_dir: &str,
+        #[case] expected: &[Range],
+    ) {
+        // Arrange
+        let rope = Rope::from(source);
+        let tree = parse(&rope, None);
+        let corpus = load_corpus("rust", &RUST_LANGUAGE, Path::new(corpus_dir));
+
+        // Act
+        let mut diagnostics = get_dead_pattern_diagnostics(&tree, &rope, &RUST_LANGUAGE, &corpus);
+        finish_diagnostics(&mut diagnostics, &tree, &rope, &Options::default(), true);
+
+        // Assert
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|diag| diag.range)
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case("/abs/corpus", "/abs/corpus")]
+    #[case(
+        "example_source_files",
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/example_source_files")
+    )]
+    #[case("missing", "/nonexistent/workspace/missing")]
+    fn corpus_directory_resolution(#[case] directory: &str, #[case] expected: &str) {
+        // Arrange
+        let workspace_dirs = [
+            PathBuf::from("/nonexistent/workspace"),
+            PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures")),
+        ];
+
+        // Act
+        let resolved = resolve_corpus_directory(directory, &workspace_dirs);
+
+        // Assert
+        assert_eq!(resolved, PathBuf::from(expected));
+    }
+}
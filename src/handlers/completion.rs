@@ -1,5 +1,10 @@
-use std::collections::HashSet;
+use std::{
+    collections::{BTreeSet, HashSet},
+    sync::{Arc, LazyLock},
+};
 
+use dashmap::DashMap;
+use ropey::Rope;
 use streaming_iterator::StreamingIterator;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::{
@@ -7,14 +12,15 @@ use tower_lsp::lsp_types::{
     Documentation, InsertTextFormat, MarkupContent, MarkupKind, Position, Range, TextEdit,
 };
 use tracing::warn;
-use tree_sitter::QueryCursor;
+use tree_sitter::{Node, Query, QueryCursor};
 use ts_query_ls::{PredicateParameterArity, PredicateParameterType};
 
 use crate::util::{
-    CAPTURES_QUERY, NodeUtil, PosUtil, TextProviderRope, get_current_capture_node,
-    get_language_name_raw, get_scm_files, node_is_or_has_ancestor, uri_to_basename,
+    CAPTURES_QUERY, NodeUtil, PosUtil, SUPPRESSION_REGEX, TextProviderRope,
+    get_current_capture_node, get_inherits_comments, get_language_name_raw, get_scm_files,
+    node_is_or_has_ancestor, uri_to_basename,
 };
-use crate::{Backend, LspClient, SymbolInfo};
+use crate::{Backend, LanguageData, LspClient, SymbolInfo, handlers::diagnostic::DiagnosticCode};
 
 pub async fn completion<C: LspClient>(
     backend: &Backend<C>,
@@ -23,7 +29,7 @@ pub async fn completion<C: LspClient>(
     let uri = &params.text_document_position.text_document.uri;
     let options = backend.options.read().await;
 
-    let Some(doc) = backend.document_map.get(uri) else {
+    let Some(doc) = backend.document_map.get(uri).map(|doc| doc.clone()) else {
         warn!("No document found for URI: {uri} when handling completion");
         return Ok(None);
     };
@@ -32,7 +38,9 @@ pub async fn completion<C: LspClient>(
     let language_data = doc
         .language_name
         .as_ref()
-        .and_then(|name| backend.language_map.get(name));
+        .and_then(|name| backend.language_map.get(name))
+        .as_deref()
+        .cloned();
 
     let mut position = params.text_document_position.position;
     if position.character > 0 {
@@ -46,13 +54,41 @@ pub async fn completion<C: LspClient>(
         .named_descendant_for_point_range(point, point)
         .unwrap();
 
-    // Import completions
     if current_node.kind() == "comment" {
-        if position.line != 0 {
+        // Diagnostic code completions
+        if let Some(directive) = SUPPRESSION_REGEX.find(&current_node.text(rope)) {
+            if (position.character as usize) + 1
+                < current_node.start_position().column + directive.end()
+            {
+                return Ok(None);
+            }
+            return Ok(Some(CompletionResponse::Array(
+                DiagnosticCode::ALL
+                    .iter()
+                    .filter(|code| !matches!(code, DiagnosticCode::UnusedSuppression))
+                    .map(|code| CompletionItem {
+                        label: code.as_str().to_string(),
+                        kind: Some(CompletionItemKind::ENUM_MEMBER),
+                        ..Default::default()
+                    })
+                    .collect(),
+            )));
+        }
+
+        // Import completions
+        if !get_inherits_comments(tree, options.import_options)
+            .any(|comment| comment.id() == current_node.id())
+        {
             return Ok(None);
         }
-        let Some(inherits) = current_node.text(rope).find("inherits: ") else {
-            let line_len = rope.line(0).len_utf16_cu() as u32;
+        let keyword = if options.import_options.allow_spaces {
+            "inherits:"
+        } else {
+            "inherits: "
+        };
+        let Some(inherits) = current_node.text(rope).find(keyword) else {
+            let line = position.line;
+            let line_len = rope.line(line as usize).len_utf16_cu() as u32;
             return Ok(Some(CompletionResponse::Array(vec![CompletionItem {
                 label: String::from("inherits: "),
                 documentation: Some(Documentation::MarkupContent(MarkupContent {
@@ -65,14 +101,16 @@ pub async fn completion<C: LspClient>(
                 kind: Some(CompletionItemKind::KEYWORD),
                 insert_text_format: Some(InsertTextFormat::SNIPPET),
                 text_edit: Some(CompletionTextEdit::Edit(TextEdit {
-                    range: Range::new(Position::new(0, 0), Position::new(0, line_len)),
+                    range: Range::new(Position::new(line, 0), Position::new(line, line_len)),
                     #[allow(clippy::literal_string_with_formatting_args)]
                     new_text: String::from("; inherits: ${1:foo}"),
                 })),
                 ..Default::default()
             }])));
         };
-        if (position.character as usize) < current_node.start_position().column + inherits + 9 {
+        if (position.character as usize)
+            < current_node.start_position().column + inherits + keyword.len() - 1
+        {
             return Ok(None);
         }
         let Ok(path) = uri.to_file_path() else {
@@ -219,6 +257,22 @@ pub async fn completion<C: LspClient>(
         let symbols = &language_data.symbols_vec;
         let supertypes = &language_data.supertype_map;
         let fields = &language_data.fields_vec;
+        // Only offer the children and fields which are valid within the enclosing node
+        let valid_children = match enclosing_node_kind(current_node, rope) {
+            Some((kind, field)) => get_valid_children(language_data.clone(), kind, field).await,
+            None => None,
+        };
+        let is_valid_symbol = |symbol: &SymbolInfo| {
+            symbol.label == "ERROR"
+                || valid_children
+                    .as_ref()
+                    .is_none_or(|children| children.nodes.contains(symbol))
+        };
+        let is_valid_field = |field: &String| {
+            valid_children
+                .as_ref()
+                .is_none_or(|children| children.fields.contains(field))
+        };
         let in_anon = node_is_or_has_ancestor(root, current_node, "string") && !in_predicate;
         let top_level = current_node.kind() == "program";
         let in_negated_field = current_node.kind() == "negated_field"
@@ -229,9 +283,9 @@ pub async fn completion<C: LspClient>(
                     .is_some_and(|p| p.kind() == "negated_field"));
 
         if in_negated_field {
-            for field in fields.clone() {
+            for field in fields.iter().filter(|field| is_valid_field(field)) {
                 completion_items.push(CompletionItem {
-                    label: field,
+                    label: field.clone(),
                     kind: Some(CompletionItemKind::FIELD),
                     ..Default::default()
                 });
@@ -239,7 +293,7 @@ pub async fn completion<C: LspClient>(
             return Ok(Some(CompletionResponse::Array(completion_items)));
         }
         if !top_level {
-            for symbol in symbols {
+            for symbol in symbols.iter().filter(|symbol| is_valid_symbol(symbol)) {
                 if (in_anon && !symbol.named) || (!in_anon && symbol.named) {
                     completion_items.push(CompletionItem {
                         label: symbol.label.clone(),
@@ -265,7 +319,7 @@ pub async fn completion<C: LspClient>(
                     ..Default::default()
                 });
             }
-            for field in fields {
+            for field in fields.iter().filter(|field| is_valid_field(field)) {
                 completion_items.push(CompletionItem {
                     label: format!("{field}: "),
                     kind: Some(CompletionItemKind::FIELD),
@@ -350,6 +404,120 @@ pub async fn completion<C: LspClient>(
     Ok(Some(CompletionResponse::Array(completion_items)))
 }
 
+/// The node kinds and field names which are valid within a node, or within one of its fields.
+struct ValidChildren {
+    nodes: BTreeSet<SymbolInfo>,
+    fields: BTreeSet<String>,
+}
+
+/// A language name, node kind, and field name.
+type ChildrenKey = (String, String, Option<String>);
+
+/// The valid children of each node kind (and field), as determined by probing the language.
+static PROBED_CHILDREN_CACHE: LazyLock<DashMap<ChildrenKey, Arc<ValidChildren>>> =
+    LazyLock::new(DashMap::new);
+
+/// Forget the probed children of the given language, e.g. after its parser is reloaded.
+pub fn clear_probed_children_cache(language_name: &str) {
+    PROBED_CHILDREN_CACHE.retain(|(name, _, _), _| name != language_name);
+}
+
+/// Get the node kinds and field names which are valid within the given node kind, or within the
+/// given field of it. These are read from the language's node types when they are loaded, and are
+/// otherwise determined by checking which patterns of the form `(kind field: (child))` the language
+/// accepts.
+async fn get_valid_children(
+    language_data: Arc<LanguageData>,
+    kind: String,
+    field: Option<String>,
+) -> Option<Arc<ValidChildren>> {
+    if let Some(node_types) = &language_data.node_types {
+        let nodes = match &field {
+            Some(field) => node_types.field_types(&kind, field)?,
+            None => node_types.child_types(&kind)?,
+        };
+        let fields = node_types.get(&kind)?.fields.keys().cloned().collect();
+        return Some(Arc::new(ValidChildren { nodes, fields }));
+    }
+    let symbol = SymbolInfo {
+        label: kind.clone(),
+        named: true,
+    };
+    if !language_data.symbols_set.contains(&symbol) {
+        return None;
+    }
+    let key = (language_data.name.clone(), kind, field);
+    if let Some(children) = PROBED_CHILDREN_CACHE.get(&key) {
+        return Some(children.clone());
+    }
+
+    // Probing compiles a query for every symbol and field of the language, which can be costly
+    let children = tokio::task::spawn_blocking(move || {
+        let children = Arc::new(probe_children(&language_data, &key.1, key.2.as_deref()));
+        PROBED_CHILDREN_CACHE.insert(key, children.clone());
+        children
+    })
+    .await
+    .ok()?;
+    Some(children)
+}
+
+/// Determine the valid children of the given node kind, or of the given field of it, by checking
+/// which patterns the language accepts.
+fn probe_children(language_data: &LanguageData, kind: &str, field: Option<&str>) -> ValidChildren {
+    let is_valid = |pattern: &str| Query::new(&language_data.language, pattern).is_ok();
+    let field_prefix = field.map_or_else(String::new, |field| format!("{field}: "));
+    let nodes = language_data
+        .symbols_vec
+        .iter()
+        .filter(|symbol| {
+            let child = if symbol.named {
+                format!("({})", symbol.label)
+            } else {
+                format!("\"{}\"", symbol.label)
+            };
+            is_valid(&format!("({kind} {field_prefix}{child})"))
+        })
+        .cloned()
+        .collect();
+    let fields = language_data
+        .fields_vec
+        .iter()
+        .filter(|field| is_valid(&format!("({kind} {field}: _)")))
+        .cloned()
+        .collect();
+    ValidChildren { nodes, fields }
+}
+
+/// Get the kind of the node pattern which encloses the given node, along with the field which the
+/// node is within, if any. Returns `None` for wildcards and top-level nodes.
+fn enclosing_node_kind(node: Node, rope: &Rope) -> Option<(String, Option<String>)> {
+    let mut node = node;
+    // When completing the name of a node or field, the context is that of its parent
+    if node.kind() == "identifier"
+        && let Some(parent) = node.parent()
+        && matches!(parent.kind(), "named_node" | "field_definition")
+        && parent.child_by_field_name("name") == Some(node)
+    {
+        node = parent.parent()?;
+    }
+    let mut field = None;
+    loop {
+        match node.kind() {
+            "named_node" => {
+                let name = node.child_by_field_name("name")?;
+                return (name.kind() == "identifier").then(|| (name.text(rope), field));
+            }
+            "field_definition" if field.is_none() => {
+                field = Some(node.child_by_field_name("name")?.text(rope));
+            }
+            "program" => return None,
+            _ => {}
+        }
+        node = node.parent()?;
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::{
@@ -366,11 +534,13 @@ mod test {
         TextEdit, Url, WorkDoneProgressParams, request::Completion,
     };
     use ts_query_ls::{
-        Options, Predicate, PredicateParameter, PredicateParameterArity, PredicateParameterType,
+        ImportOptions, Options, Predicate, PredicateParameter, PredicateParameterArity,
+        PredicateParameterType,
     };
 
-    use crate::test_helpers::helpers::{
-        QUERY_TEST_URI, RUST_TEST_URI, TestService, initialize_server,
+    use crate::{
+        handlers::diagnostic::DiagnosticCode,
+        test_helpers::helpers::{QUERY_TEST_URI, RUST_TEST_URI, TestService, initialize_server},
     };
 
     static NODE_COMPLETIONS: LazyLock<Vec<CompletionItem>> = LazyLock::new(|| {
@@ -483,6 +653,46 @@ mod test {
         ]
     });
 
+    static NODE_TYPES_OPTIONS: LazyLock<Options> = LazyLock::new(|| Options {
+        parser_install_directories: vec![String::from(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/node_types"
+        ))],
+        load_node_types: true,
+        ..Default::default()
+    });
+
+    /// The node completions with the given labels, followed by the given field completions.
+    fn filtered_completions(nodes: &[&str], fields: &[&str]) -> Vec<CompletionItem> {
+        NODE_COMPLETIONS
+            .iter()
+            .filter(|item| nodes.contains(&item.label.as_str()))
+            .cloned()
+            .chain(
+                FIELD_COMPLETIONS
+                    .iter()
+                    .map(|item| CompletionItem {
+                        label: format!("{}: ", item.label),
+                        ..item.clone()
+                    })
+                    .filter(|item| fields.contains(&item.label.as_str())),
+            )
+            .collect()
+    }
+
+    static DIAGNOSTIC_CODE_COMPLETIONS: LazyLock<Vec<CompletionItem>> = LazyLock::new(|| {
+        DiagnosticCode::ALL
+            .iter()
+            .map(|code| code.as_str())
+            .filter(|code| *code != "unused-suppression")
+            .map(|code| CompletionItem {
+                label: code.to_string(),
+                kind: Some(CompletionItemKind::ENUM_MEMBER),
+                ..Default::default()
+            })
+            .collect()
+    });
+
     static FIELD_COMPLETIONS: LazyLock<Vec<CompletionItem>> = LazyLock::new(|| {
         vec![
             CompletionItem {
@@ -919,6 +1129,63 @@ mod test {
             ..Default::default() },
         &[]
     )]
+    #[case(
+        &QUERY_TEST_URI,
+        r"; inherits: cpp
+; inherits: ",
+        Position { line: 1, character: 12 },
+        &Options {
+            import_options: ImportOptions {
+                allow_spaces: true,
+                multiple_directives: true,
+                optional_modules: false,
+            },
+            ..Default::default()
+        },
+        &[
+            CompletionItem {
+                label: String::from("cpp"),
+                kind: Some(CompletionItemKind::MODULE),
+                ..Default::default()
+            },
+            CompletionItem {
+                label: String::from("other"),
+                kind: Some(CompletionItemKind::MODULE),
+                ..Default::default()
+            },
+        ]
+    )]
+    #[case(
+        &QUERY_TEST_URI,
+        r"; inherits: cpp
+; inherits: ",
+        Position { line: 1, character: 12 },
+        &Options::default(),
+        &[]
+    )]
+    #[case(
+        &QUERY_TEST_URI,
+        r"(identifier) @constant
+
+;; ts_query_ls-ignore: invalid-node, ",
+        Position { line: 2, character: 37 },
+        &Options::default(),
+        &DIAGNOSTIC_CODE_COMPLETIONS
+    )]
+    #[case(
+        &QUERY_TEST_URI,
+        r"; ts_query_ls-ignore-file:",
+        Position { line: 0, character: 26 },
+        &Options::default(),
+        &DIAGNOSTIC_CODE_COMPLETIONS
+    )]
+    #[case(
+        &QUERY_TEST_URI,
+        r"; ts_query_ls-ignore-file:",
+        Position { line: 0, character: 20 },
+        &Options::default(),
+        &[]
+    )]
     #[case(
         &QUERY_TEST_URI,
         r"; inhe",
@@ -946,8 +1213,13 @@ the language information of the parent query). Queries will always inherit
 others of the same type (e.g. a `highlights.scm` will only import other
 `highlights.scm`, never an `injections.scm`).
 
-Note that the syntax is very sensitive; there must be _exactly one_ space after
-the `inherits:` keyword, and there must be no spaces in-between module names.
+By default, the syntax is very sensitive; there must be _exactly one_ space
+after the `inherits:` keyword, and there must be no spaces in-between module
+names. The `import_options` setting can relax this to match other editors'
+conventions: spaces around module names (`; inherits: foo, bar`), several
+`; inherits:` comments at the top of the file, and modules wrapped in
+parentheses (`; inherits: (foo)`), which are only inherited when the query is
+itself inherited (as in Neovim) and are not reported when missing.
 "),
                 })),
                 insert_text_format: Some(InsertTextFormat::SNIPPET),
@@ -960,6 +1232,55 @@ the `inherits:` keyword, and there must be no spaces in-between module names.
             },
         ]
     )]
+    #[case(
+        &QUERY_TEST_URI,
+        r"(named_node )",
+        Position { line: 0, character: 12 },
+        &NODE_TYPES_OPTIONS,
+        &filtered_completions(
+            &[
+                "ERROR", "identifier", "comment", "program", "definition", "quantifier",
+                "capture", "string", "list", "grouping", "missing_node", "anonymous_node",
+                "named_node", "field_definition", "negated_field", "predicate", "MISSING",
+            ],
+            &["name: ", "quantifier: ", "supertype: "],
+        )
+    )]
+    #[case(
+        &QUERY_TEST_URI,
+        r"(named_node name: (i))",
+        Position { line: 0, character: 20 },
+        &NODE_TYPES_OPTIONS,
+        &filtered_completions(
+            &["ERROR", "identifier", "string", "MISSING"],
+            &["name: ", "quantifier: ", "supertype: "],
+        )
+    )]
+    #[case(
+        &QUERY_TEST_URI,
+        r"(capture !)",
+        Position { line: 0, character: 10 },
+        &NODE_TYPES_OPTIONS,
+        &[CompletionItem {
+            label: String::from("name"),
+            kind: Some(CompletionItemKind::FIELD),
+            ..Default::default()
+        }]
+    )]
+    #[case(
+        &QUERY_TEST_URI,
+        r"(negated_field )",
+        Position { line: 0, character: 15 },
+        &Options::default(),
+        &filtered_completions(&["ERROR", "identifier", "comment", "MISSING"], &[])
+    )]
+    #[case(
+        &QUERY_TEST_URI,
+        r"(capture name: (i))",
+        Position { line: 0, character: 17 },
+        &Options::default(),
+        &filtered_completions(&["ERROR", "identifier", "comment", "MISSING"], &["name: "])
+    )]
     #[tokio::test(flavor = "current_thread")]
     async fn server_completions(
         #[case] uri: &Url,
@@ -1,5 +1,6 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
     sync::{Arc, LazyLock},
 };
 
@@ -16,97 +17,92 @@ use tower_lsp::{
     },
 };
 use tree_sitter::{
-    Language, Node, Query, QueryCursor, QueryError, QueryErrorKind, StreamingIterator as _,
+    Language, Node, Query, QueryCursor, QueryError, QueryErrorKind, StreamingIterator as _, Tree,
     TreeCursor,
 };
 use ts_query_ls::{
-    Options, ParameterConstraint, PredicateParameter, PredicateParameterArity,
-    PredicateParameterType, StringArgumentStyle,
+    CapturePrecedence, Options, ParameterConstraint, PredicateParameter, PredicateParameterArity,
+    PredicateParameterType, SeverityOverride, StringArgumentStyle,
 };
 
 use crate::{
     Backend, DocumentData, ImportedUri, LanguageData, LspClient, QUERY_LANGUAGE, SymbolInfo,
+    node_types::explain_structure_error,
+    shadowing::get_shadowed_patterns,
     util::{
-        CAPTURES_QUERY, NodeUtil as _, TextProviderRope, remove_unnecessary_escapes,
-        uri_to_basename,
+        ByteUtil as _, CAPTURES_QUERY, NodeUtil as _, TextProviderRope, parse_suppression,
+        remove_unnecessary_escapes, uri_to_basename,
     },
 };
 
 use super::code_action::CodeActions;
 
-pub enum DiagnosticCode {
+/// Define the diagnostic codes along with their names, and the list of every code.
+macro_rules! diagnostic_codes {
+    ($($variant:ident => $name:literal,)*) => {
+        #[derive(Clone, Copy)]
+        pub enum DiagnosticCode {
+            $($variant,)*
+        }
+
+        impl DiagnosticCode {
+            /// Every diagnostic code, used for completions.
+            pub const ALL: &[Self] = &[$(Self::$variant),*];
+
+            pub const fn as_str(self) -> &'static str {
+                match self {
+                    $(Self::$variant => $name,)*
+                }
+            }
+        }
+    };
+}
+
+diagnostic_codes! {
     // Errors
-    InvalidPatternStructure,
-    InvalidNode,
-    InvalidSubtype,
-    InvalidSupertype,
-    InvalidField,
-    InvalidSyntax,
-    MissingToken,
-    UndeclaredCapture,
+    InvalidPatternStructure => "invalid-pattern-structure",
+    InvalidNode => "invalid-node",
+    InvalidSubtype => "invalid-subtype",
+    InvalidSupertype => "invalid-supertype",
+    InvalidField => "invalid-field",
+    InvalidSyntax => "invalid-syntax",
+    MissingToken => "missing-token",
+    UndeclaredCapture => "undeclared-capture",
 
     // Warnings
-    NoLanguageObject,
-    NoLanguageName,
-    InvalidAbi,
-    InvalidCaptureName,
-    UnusedAuxiliaryCapture,
-    UnrecognizedPredicate,
-    UnrecognizedDirective,
-    UnnecessaryEscapeSequence,
-    UnnecessaryPattern,
-    ImportNameMissing,
-    ImportNotFound,
-    ParameterTypeMismatch,
-    InvalidNamedNode,
-    InvalidInteger,
-    InvalidEnumMember,
-    UnexpectedParameter,
-    MissingParameter,
+    NoLanguageObject => "no-language-object",
+    NoLanguageName => "no-language-name",
+    InvalidAbi => "invalid-abi",
+    InvalidCaptureName => "invalid-capture-name",
+    UnusedAuxiliaryCapture => "unused-auxiliary-capture",
+    UnrecognizedPredicate => "unrecognized-predicate",
+    UnrecognizedDirective => "unrecognized-directive",
+    UnnecessaryEscapeSequence => "unnecessary-escape-sequence",
+    UnnecessaryPattern => "unnecessary-pattern",
+    ImportNameMissing => "import-name-missing",
+    ImportNotFound => "import-not-found",
+    ParameterTypeMismatch => "parameter-type-mismatch",
+    InvalidNamedNode => "invalid-named-node",
+    InvalidInteger => "invalid-integer",
+    InvalidEnumMember => "invalid-enum-member",
+    UnexpectedParameter => "unexpected-parameter",
+    MissingParameter => "missing-parameter",
+    DeadPattern => "dead-pattern",
+    ShadowedPattern => "shadowed-pattern",
+    UnusedSuppression => "unused-suppression",
 
     // Hints
-    UnnecessaryQuotations,
-    UnquotedString,
-    RedundantAlternant,
+    UnnecessaryQuotations => "unnecessary-quotations",
+    UnquotedString => "unquoted-string",
+    RedundantAlternant => "redundant-alternant",
 
     // Special
-    ImportIssues,
+    ImportIssues => "import-issues",
 }
 
 impl From<DiagnosticCode> for Option<NumberOrString> {
     fn from(value: DiagnosticCode) -> Self {
-        let string_slice = match value {
-            DiagnosticCode::NoLanguageObject => "no-language-object",
-            DiagnosticCode::NoLanguageName => "no-language-name",
-            DiagnosticCode::InvalidAbi => "invalid-abi",
-            DiagnosticCode::InvalidPatternStructure => "invalid-pattern-structure",
-            DiagnosticCode::InvalidNode => "invalid-node",
-            DiagnosticCode::InvalidSubtype => "invalid-subtype",
-            DiagnosticCode::InvalidSupertype => "invalid-supertype",
-            DiagnosticCode::InvalidField => "invalid-field",
-            DiagnosticCode::InvalidSyntax => "invalid-syntax",
-            DiagnosticCode::MissingToken => "missing-token",
-            DiagnosticCode::UndeclaredCapture => "undeclared-capture",
-            DiagnosticCode::InvalidCaptureName => "invalid-capture-name",
-            DiagnosticCode::UnusedAuxiliaryCapture => "unused-auxiliary-capture",
-            DiagnosticCode::UnrecognizedPredicate => "unrecognized-predicate",
-            DiagnosticCode::UnrecognizedDirective => "unrecognized-directive",
-            DiagnosticCode::UnnecessaryEscapeSequence => "unnecessary-escape-sequence",
-            DiagnosticCode::UnnecessaryPattern => "unnecessary-pattern",
-            DiagnosticCode::UnnecessaryQuotations => "unnecessary-quotations",
-            DiagnosticCode::UnquotedString => "unquoted-string",
-            DiagnosticCode::ImportIssues => "import-issues",
-            DiagnosticCode::ImportNameMissing => "import-name-missing",
-            DiagnosticCode::ImportNotFound => "import-not-found",
-            DiagnosticCode::ParameterTypeMismatch => "parameter-type-mismatch",
-            DiagnosticCode::InvalidNamedNode => "invalid-named-node",
-            DiagnosticCode::InvalidInteger => "invalid-integer",
-            DiagnosticCode::InvalidEnumMember => "invalid-enum-member",
-            DiagnosticCode::UnexpectedParameter => "unexpected-parameter",
-            DiagnosticCode::MissingParameter => "missing-parameter",
-            DiagnosticCode::RedundantAlternant => "redundant-alternant",
-        };
-        Some(NumberOrString::String(String::from(string_slice)))
+        Some(NumberOrString::String(String::from(value.as_str())))
     }
 }
 
@@ -120,6 +116,8 @@ static DIAGNOSTICS_QUERY: LazyLock<Query> = LazyLock::new(|| {
     )
     .unwrap()
 });
+static COMMENTS_QUERY: LazyLock<Query> =
+    LazyLock::new(|| Query::new(&QUERY_LANGUAGE, "(comment) @comment").unwrap());
 static DEFINITIONS_QUERY: LazyLock<Query> =
     LazyLock::new(|| Query::new(&QUERY_LANGUAGE, "(program (definition) @def)").unwrap());
 static CAPTURE_DEFINITIONS_QUERY: LazyLock<Query> = LazyLock::new(|| {
@@ -188,6 +186,11 @@ pub async fn diagnostic<C: LspClient>(
 static QUERY_SCAN_CACHE: LazyLock<DashMap<(String, String), Option<usize>>> =
     LazyLock::new(DashMap::new);
 
+/// Forget the cached pattern diagnostics of the given language, e.g. after its parser is reloaded.
+pub fn clear_query_scan_cache(language_name: &str) {
+    QUERY_SCAN_CACHE.retain(|(name, _), _| name != language_name);
+}
+
 async fn create_diagnostic_report<C: LspClient>(
     backend: &Backend<C>,
     document: DocumentData,
@@ -201,8 +204,10 @@ async fn create_diagnostic_report<C: LspClient>(
         .cloned();
     let ignore_missing_language = false;
     let cache = true;
+    let tree = document.tree.clone();
+    let rope = document.rope.clone();
 
-    let items = get_diagnostics(
+    let mut items = get_diagnostics(
         uri,
         &backend.document_map,
         document,
@@ -212,6 +217,13 @@ async fn create_diagnostic_report<C: LspClient>(
         cache,
     )
     .await;
+    finish_diagnostics(
+        &mut items,
+        &tree,
+        &rope,
+        &*backend.options.read().await,
+        true,
+    );
 
     FullDocumentDiagnosticReport {
         result_id: None,
@@ -284,6 +296,7 @@ const ERROR_SEVERITY: Option<DiagnosticSeverity> = Some(DiagnosticSeverity::ERRO
 const WARNING_SEVERITY: Option<DiagnosticSeverity> = Some(DiagnosticSeverity::WARNING);
 const HINT_SEVERITY: Option<DiagnosticSeverity> = Some(DiagnosticSeverity::HINT);
 
+/// Collect the diagnostics of a document, which are then passed to [`finish_diagnostics`].
 pub async fn get_diagnostics(
     uri: &Url,
     document_map: &DashMap<Url, DocumentData>,
@@ -353,9 +366,106 @@ pub async fn get_diagnostics(
         }
     }
 
+    let precedence = options.diagnostic_options.capture_precedence;
+    let corpus_directory = language_data
+        .as_ref()
+        .and_then(|language_data| options.corpus_directories.get(&language_data.name))
+        .map(PathBuf::from);
+    drop(options);
+    if let Some(precedence) = precedence {
+        full_report.extend(
+            get_shadowed_pattern_diagnostics(
+                document_map,
+                uri,
+                language_data,
+                corpus_directory,
+                precedence,
+            )
+            .await,
+        );
+    }
+
     full_report
 }
 
+/// Report the patterns of a highlights query which are shadowed by other patterns of the query or
+/// its imported modules, according to the configured capture precedence.
+async fn get_shadowed_pattern_diagnostics(
+    document_map: &DashMap<Url, DocumentData>,
+    uri: &Url,
+    language_data: Option<Arc<LanguageData>>,
+    corpus_directory: Option<PathBuf>,
+    precedence: CapturePrecedence,
+) -> Vec<Diagnostic> {
+    let order = match precedence {
+        CapturePrecedence::FirstMatch => "an earlier",
+        CapturePrecedence::LastMatch => "a later",
+    };
+    get_shadowed_patterns(
+        document_map,
+        uri,
+        language_data,
+        corpus_directory,
+        precedence,
+    )
+    .await
+    .iter()
+    .map(|shadowed| Diagnostic {
+        message: format!("This pattern is shadowed by {order} pattern"),
+        severity: WARNING_SEVERITY,
+        range: shadowed.range,
+        code: DiagnosticCode::ShadowedPattern.into(),
+        related_information: Some(
+            shadowed
+                .shadowed_by
+                .iter()
+                .map(|location| DiagnosticRelatedInformation {
+                    location: location.clone(),
+                    message: String::from("Shadowing pattern"),
+                })
+                .collect(),
+        ),
+        ..Default::default()
+    })
+    .collect()
+}
+
+/// Get the codes of the configured severity overrides which are not diagnostic codes.
+pub fn unknown_severity_codes(options: &Options) -> impl Iterator<Item = &str> {
+    options
+        .diagnostic_options
+        .severity
+        .keys()
+        .map(String::as_str)
+        .filter(|code| DiagnosticCode::ALL.iter().all(|c| c.as_str() != *code))
+}
+
+/// Change the severity of diagnostics according to the configured overrides, removing those which
+/// are turned off.
+fn apply_severity_overrides(
+    diagnostics: &mut Vec<Diagnostic>,
+    overrides: &BTreeMap<String, SeverityOverride>,
+) {
+    if overrides.is_empty() {
+        return;
+    }
+    diagnostics.retain_mut(|diagnostic| {
+        let Some(NumberOrString::String(code)) = &diagnostic.code else {
+            return true;
+        };
+        let severity = match overrides.get(code) {
+            None => return true,
+            Some(SeverityOverride::Off) => return false,
+            Some(SeverityOverride::Error) => DiagnosticSeverity::ERROR,
+            Some(SeverityOverride::Warning) => DiagnosticSeverity::WARNING,
+            Some(SeverityOverride::Info) => DiagnosticSeverity::INFORMATION,
+            Some(SeverityOverride::Hint) => DiagnosticSeverity::HINT,
+        };
+        diagnostic.severity = Some(severity);
+        true
+    });
+}
+
 async fn get_diagnostics_recursively(
     uri: &Url,
     document_map: &DashMap<Url, DocumentData>,
@@ -385,6 +495,7 @@ async fn get_diagnostics_recursively(
         let Some(LanguageData {
             language,
             name: language_name,
+            node_types,
             ..
         }) = ld.as_deref()
         else {
@@ -407,14 +518,18 @@ async fn get_diagnostics_recursively(
                     get_pattern_diagnostic(&capture.node.text(&rope), &language.clone())
                 } {
                     let true_offset = offset + capture.node.start_byte();
+                    let node = tree
+                        .root_node()
+                        .named_descendant_for_byte_range(true_offset, true_offset);
                     diagnostics.push(Diagnostic {
-                        message: String::from("Invalid pattern structure"),
+                        message: node
+                            .zip(node_types.as_ref())
+                            .and_then(|(node, node_types)| {
+                                explain_structure_error(node, &rope, node_types)
+                            })
+                            .unwrap_or_else(|| String::from("Invalid pattern structure")),
                         severity: ERROR_SEVERITY,
-                        range: tree
-                            .root_node()
-                            .named_descendant_for_byte_range(true_offset, true_offset)
-                            .map(|node| node.lsp_range(&rope))
-                            .unwrap_or_default(),
+                        range: node.map(|node| node.lsp_range(&rope)).unwrap_or_default(),
                         code: DiagnosticCode::InvalidPatternStructure.into(),
                         ..Default::default()
                     });
@@ -778,6 +893,116 @@ async fn get_diagnostics_recursively(
     diagnostics
 }
 
+/// Apply the document's suppression comments and the configured severity overrides to its
+/// diagnostics. This must happen once all of the document's diagnostics have been collected.
+/// Unused suppressions are only reported if `report_unused` is set, i.e. not for imported modules.
+pub fn finish_diagnostics(
+    diagnostics: &mut Vec<Diagnostic>,
+    tree: &Tree,
+    rope: &Rope,
+    options: &Options,
+    report_unused: bool,
+) {
+    apply_suppressions(diagnostics, tree, rope, report_unused);
+    apply_severity_overrides(diagnostics, &options.diagnostic_options.severity);
+}
+
+/// Remove the diagnostics suppressed by `; ts_query_ls-ignore: <codes>` comments (which apply to
+/// the next node) and `; ts_query_ls-ignore-file: <codes>` comments, and report the suppressions
+/// which did not suppress anything if `report_unused` is set.
+fn apply_suppressions(
+    diagnostics: &mut Vec<Diagnostic>,
+    tree: &Tree,
+    rope: &Rope,
+    report_unused: bool,
+) {
+    struct SuppressedCode {
+        code: String,
+        range: Range,
+        /// The range of the suppressed node, or `None` if the whole file is suppressed.
+        scope: Option<Range>,
+        used: bool,
+    }
+
+    let mut suppressions = Vec::new();
+    let mut cursor = QueryCursor::new();
+    let provider = TextProviderRope(rope);
+    let mut matches = cursor.matches(&COMMENTS_QUERY, tree.root_node(), &provider);
+    while let Some(match_) = matches.next() {
+        for capture in match_.captures {
+            let node = capture.node;
+            let text = node.text(rope);
+            let Some(suppression) = parse_suppression(&text) else {
+                continue;
+            };
+            let scope = if suppression.file {
+                None
+            } else {
+                // Consecutive suppression comments apply to the same node
+                let mut next = node.next_named_sibling();
+                while let Some(sibling) = next.filter(|sibling| sibling.kind() == "comment") {
+                    next = sibling.next_named_sibling();
+                }
+                let end = node.lsp_range(rope).end;
+                Some(next.map_or_else(|| Range::new(end, end), |next| next.lsp_range(rope)))
+            };
+            for (offset, code) in suppression.codes {
+                let start = node.start_byte() + offset;
+                suppressions.push(SuppressedCode {
+                    code: code.to_string(),
+                    range: Range::new(
+                        start.to_lsp_pos(rope),
+                        (start + code.len()).to_lsp_pos(rope),
+                    ),
+                    scope,
+                    used: false,
+                });
+            }
+        }
+    }
+    if suppressions.is_empty() {
+        return;
+    }
+
+    diagnostics.retain(|diagnostic| {
+        let Some(NumberOrString::String(code)) = &diagnostic.code else {
+            return true;
+        };
+        let mut suppressed = false;
+        for suppression in &mut suppressions {
+            if suppression.code == *code
+                && suppression.scope.is_none_or(|scope| {
+                    scope.start <= diagnostic.range.start && diagnostic.range.start < scope.end
+                })
+            {
+                suppression.used = true;
+                suppressed = true;
+            }
+        }
+        !suppressed
+    });
+
+    if !report_unused {
+        return;
+    }
+    for suppression in suppressions.into_iter().filter(|s| !s.used) {
+        let code = suppression.code;
+        let message = if DiagnosticCode::ALL.iter().any(|c| c.as_str() == code) {
+            format!("Unused suppression for \"{code}\"")
+        } else {
+            format!("Unknown diagnostic code \"{code}\"")
+        };
+        diagnostics.push(Diagnostic {
+            message,
+            severity: WARNING_SEVERITY,
+            range: suppression.range,
+            code: DiagnosticCode::UnusedSuppression.into(),
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            ..Default::default()
+        });
+    }
+}
+
 async fn get_imported_query_diagnostics(
     document_map: &DashMap<Url, DocumentData>,
     options_arc: Arc<tokio::sync::RwLock<Options>>,
@@ -787,15 +1012,17 @@ async fn get_imported_query_diagnostics(
 ) -> Vec<Diagnostic> {
     let mut items = Vec::new();
     for ImportedUri {
+        line,
         start_col,
         end_col,
         name,
         uri,
+        optional,
     } in imported_uris
     {
         let range = Range {
-            start: Position::new(0, *start_col),
-            end: Position::new(0, *end_col),
+            start: Position::new(*line, *start_col),
+            end: Position::new(*line, *end_col),
         };
         if let Some(uri) = uri {
             if seen.contains(uri) {
@@ -804,7 +1031,9 @@ async fn get_imported_query_diagnostics(
             seen.insert(uri.clone());
             if let Some(document) = document_map.get(uri).map(|doc| doc.clone()) {
                 let mut severity = DiagnosticSeverity::HINT;
-                let inner_diags = get_diagnostics_recursively(
+                let tree = document.tree.clone();
+                let rope = document.rope.clone();
+                let mut inner_diags = get_diagnostics_recursively(
                     uri,
                     document_map,
                     document,
@@ -814,6 +1043,13 @@ async fn get_imported_query_diagnostics(
                     seen,
                 )
                 .await;
+                finish_diagnostics(
+                    &mut inner_diags,
+                    &tree,
+                    &rope,
+                    &*options_arc.read().await,
+                    false,
+                );
                 let inner_diags: Vec<DiagnosticRelatedInformation> = inner_diags
                     .into_iter()
                     .map(|diag| {
@@ -851,7 +1087,7 @@ async fn get_imported_query_diagnostics(
                 code: DiagnosticCode::ImportNameMissing.into(),
                 ..Default::default()
             });
-        } else {
+        } else if !optional {
             items.push(Diagnostic {
                 range,
                 severity: WARNING_SEVERITY,
@@ -1013,8 +1249,9 @@ mod test {
         request::DocumentDiagnosticRequest,
     };
     use ts_query_ls::{
-        DiagnosticOptions, Options, ParameterConstraint, Predicate, PredicateParameter,
-        PredicateParameterArity, PredicateParameterType, StringArgumentStyle,
+        CapturePrecedence, DiagnosticOptions, ImportOptions, Options, ParameterConstraint,
+        Predicate, PredicateParameter, PredicateParameterArity, PredicateParameterType,
+        SeverityOverride, StringArgumentStyle,
     };
 
     use crate::{
@@ -1043,6 +1280,30 @@ mod test {
         .unwrap()
     });
 
+    static QUERY_HIGHLIGHTS_URI: LazyLock<Url> =
+        LazyLock::new(|| Url::parse("file:///tmp/queries/query/highlights.scm").unwrap());
+
+    fn capture_precedence_options(capture_precedence: CapturePrecedence) -> Options {
+        Options {
+            diagnostic_options: DiagnosticOptions {
+                capture_precedence: Some(capture_precedence),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn node_types_options() -> Options {
+        Options {
+            parser_install_directories: vec![String::from(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/node_types"
+            ))],
+            load_node_types: true,
+            ..Default::default()
+        }
+    }
+
     #[rstest]
     #[case(
         &[(
@@ -1510,6 +1771,140 @@ mod test {
         }],
         None,
     )]
+    #[case(
+        &[(
+            QUERY_TEST_URI.clone(),
+            r"(identifier) @_capture",
+        )],
+        Options {
+            diagnostic_options: DiagnosticOptions {
+                severity: BTreeMap::from([(
+                    String::from("unused-auxiliary-capture"),
+                    SeverityOverride::Error,
+                )]),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        &[Diagnostic {
+            range: Range {
+                start: Position { line: 0, character: 13, },
+                end: Position { line: 0, character: 22, },
+            },
+            severity: ERROR_SEVERITY,
+            code: DiagnosticCode::UnusedAuxiliaryCapture.into(),
+            code_description: None,
+            source: None,
+            message: String::from("Unused `_`-prefixed capture (fix available)"),
+            related_information: None,
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            data: Some(CodeActions::Remove.into()),
+        }],
+        None,
+    )]
+    #[case(
+        &[(
+            QUERY_TEST_URI.clone(),
+            r"(identifier) @_capture",
+        )],
+        Options {
+            diagnostic_options: DiagnosticOptions {
+                severity: BTreeMap::from([(
+                    String::from("unused-auxiliary-capture"),
+                    SeverityOverride::Off,
+                )]),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        &[],
+        None,
+    )]
+    #[case(
+        &[(
+            QUERY_TEST_URI.clone(),
+            r"; ts_query_ls-ignore: unused-auxiliary-capture
+; format-ignore
+((identifier) @_capture)
+(identifier) @_other",
+        )],
+        Options::default(),
+        &[Diagnostic {
+            range: Range {
+                start: Position { line: 3, character: 13, },
+                end: Position { line: 3, character: 20, },
+            },
+            severity: WARNING_SEVERITY,
+            code: DiagnosticCode::UnusedAuxiliaryCapture.into(),
+            code_description: None,
+            source: None,
+            message: String::from("Unused `_`-prefixed capture (fix available)"),
+            related_information: None,
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            data: Some(CodeActions::Remove.into()),
+        }],
+        None,
+    )]
+    #[case(
+        &[(
+            QUERY_TEST_URI.clone(),
+            r"; ts_query_ls-ignore-file: unused-auxiliary-capture, invalid-node
+((identifier) @_capture)
+(identifier) @_other",
+        )],
+        Options::default(),
+        &[Diagnostic {
+            range: Range {
+                start: Position { line: 0, character: 53, },
+                end: Position { line: 0, character: 65, },
+            },
+            severity: WARNING_SEVERITY,
+            code: DiagnosticCode::UnusedSuppression.into(),
+            code_description: None,
+            source: None,
+            message: String::from(r#"Unused suppression for "invalid-node""#),
+            related_information: None,
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            data: None,
+        }],
+        None,
+    )]
+    #[case(
+        &[(
+            QUERY_TEST_URI.clone(),
+            r"; ts_query_ls-ignore: foo
+(identifier) @_capture",
+        )],
+        Options::default(),
+        &[Diagnostic {
+            range: Range {
+                start: Position { line: 1, character: 13, },
+                end: Position { line: 1, character: 22, },
+            },
+            severity: WARNING_SEVERITY,
+            code: DiagnosticCode::UnusedAuxiliaryCapture.into(),
+            code_description: None,
+            source: None,
+            message: String::from("Unused `_`-prefixed capture (fix available)"),
+            related_information: None,
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            data: Some(CodeActions::Remove.into()),
+        }, Diagnostic {
+            range: Range {
+                start: Position { line: 0, character: 22, },
+                end: Position { line: 0, character: 25, },
+            },
+            severity: WARNING_SEVERITY,
+            code: DiagnosticCode::UnusedSuppression.into(),
+            code_description: None,
+            source: None,
+            message: String::from(r#"Unknown diagnostic code "foo""#),
+            related_information: None,
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            data: None,
+        }],
+        None,
+    )]
     #[case(
         &[(
             QUERY_TEST_URI.clone(),
@@ -1903,6 +2298,27 @@ mod test {
         &[(
             QUERY_TEST_URI.clone(),
             r"; inherits: other
+(identifier) @capture",
+        )],
+        Options {
+            valid_captures: HashMap::from([(String::from("test"),
+                BTreeMap::from([(String::from("capture"), String::default())]))]),
+            diagnostic_options: DiagnosticOptions {
+                severity: BTreeMap::from([(
+                    String::from("unnecessary-pattern"),
+                    SeverityOverride::Off,
+                )]),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        &[],
+        None,
+    )]
+    #[case(
+        &[(
+            QUERY_TEST_URI.clone(),
+            r"; inherits: other
 (identifier) @capture",
         )],
         Options {
@@ -1960,6 +2376,44 @@ mod test {
         }],
         None,
     )]
+    #[case(
+        &[(
+            TEST_URI.clone(),
+            r"; inherits: css, (jsx)
+;; inherits:ecma
+(squid) @capture",
+        )],
+        Options {
+            valid_captures: HashMap::from([(String::from("test"),
+                BTreeMap::from([(String::from("capture"), String::default())]))]),
+            import_options: ImportOptions {
+                allow_spaces: true,
+                multiple_directives: true,
+                optional_modules: true,
+            },
+            ..Default::default()
+        },
+        &[Diagnostic {
+            message: String::from("Query module \"css\" not found"),
+            range: Range::new(Position::new(0, 12), Position::new(0, 15)),
+            severity: WARNING_SEVERITY,
+            code: DiagnosticCode::ImportNotFound.into(),
+            ..Default::default()
+        }, Diagnostic {
+            message: String::from("Query module \"ecma\" not found"),
+            range: Range::new(Position::new(1, 12), Position::new(1, 16)),
+            severity: WARNING_SEVERITY,
+            code: DiagnosticCode::ImportNotFound.into(),
+            ..Default::default()
+        }, Diagnostic {
+            message: String::from("Language object for \"js\" not found"),
+            range: Range::new(Position::new(0, 0), Position::new(0, 0)),
+            severity: WARNING_SEVERITY,
+            code: DiagnosticCode::NoLanguageObject.into(),
+            ..Default::default()
+        }],
+        None,
+    )]
     #[case(
         &[(Url::parse("file:///tmp/test.scm").unwrap(), "")],
         Options::default(),
@@ -2524,6 +2978,100 @@ mod test {
         ],
         None,
     )]
+    #[case(
+        &[(QUERY_TEST_URI.clone(), r"(identifier name: (identifier) @capture)")],
+        node_types_options(),
+        &[Diagnostic {
+            message: String::from("Field \"name\" is not valid on \"identifier\", which has no fields"),
+            range: Range::new(Position::new(0, 12), Position::new(0, 16)),
+            severity: ERROR_SEVERITY,
+            code: DiagnosticCode::InvalidPatternStructure.into(),
+            ..Default::default()
+        }],
+        None,
+    )]
+    #[case(
+        &[(QUERY_TEST_URI.clone(), r"(capture quantifier: (identifier) @capture)")],
+        node_types_options(),
+        &[Diagnostic {
+            message: String::from("Field \"quantifier\" is not valid on \"capture\"; valid fields: name"),
+            range: Range::new(Position::new(0, 9), Position::new(0, 19)),
+            severity: ERROR_SEVERITY,
+            code: DiagnosticCode::InvalidPatternStructure.into(),
+            ..Default::default()
+        }],
+        None,
+    )]
+    #[case(
+        &[(QUERY_TEST_URI.clone(), r"(negated_field (string) @capture)")],
+        node_types_options(),
+        &[Diagnostic {
+            message: String::from("Node \"string\" is not a valid child of \"negated_field\"; valid children: identifier"),
+            range: Range::new(Position::new(0, 15), Position::new(0, 32)),
+            severity: ERROR_SEVERITY,
+            code: DiagnosticCode::InvalidPatternStructure.into(),
+            ..Default::default()
+        }],
+        None,
+    )]
+    #[case(
+        &[(QUERY_TEST_URI.clone(), r"(capture name: (string) @capture)")],
+        node_types_options(),
+        &[Diagnostic {
+            message: String::from("Node \"string\" is not valid in field \"name\" of \"capture\"; valid types: identifier"),
+            range: Range::new(Position::new(0, 9), Position::new(0, 13)),
+            severity: ERROR_SEVERITY,
+            code: DiagnosticCode::InvalidPatternStructure.into(),
+            ..Default::default()
+        }],
+        None,
+    )]
+    #[case(
+        &[(QUERY_HIGHLIGHTS_URI.clone(), "(identifier) @variable\n\n(named_node name: (identifier) @function)")],
+        capture_precedence_options(CapturePrecedence::FirstMatch),
+        &[Diagnostic {
+            message: String::from("This pattern is shadowed by an earlier pattern"),
+            range: Range::new(Position::new(2, 0), Position::new(2, 41)),
+            severity: WARNING_SEVERITY,
+            code: DiagnosticCode::ShadowedPattern.into(),
+            related_information: Some(vec![DiagnosticRelatedInformation {
+                location: Location::new(
+                    QUERY_HIGHLIGHTS_URI.clone(),
+                    Range::new(Position::new(0, 0), Position::new(0, 22)),
+                ),
+                message: String::from("Shadowing pattern"),
+            }]),
+            ..Default::default()
+        }],
+        None,
+    )]
+    #[case(
+        &[(QUERY_HIGHLIGHTS_URI.clone(), "(identifier) @variable\n\n(named_node name: (identifier) @function)")],
+        capture_precedence_options(CapturePrecedence::LastMatch),
+        &[],
+        None,
+    )]
+    #[case(
+        &[(
+            QUERY_HIGHLIGHTS_URI.clone(),
+            "(identifier) @variable\n\n; ts_query_ls-ignore: shadowed-pattern\n(named_node name: (identifier) @function)",
+        )],
+        capture_precedence_options(CapturePrecedence::FirstMatch),
+        &[],
+        None,
+    )]
+    #[case(
+        &[(TEST_URI.clone(), "; ts_query_ls-ignore-file: no-language-object\n(identifier) @variable")],
+        Options::default(),
+        &[],
+        None,
+    )]
+    #[case(
+        &[(QUERY_TEST_URI.clone(), "(identifier) @variable\n\n(named_node name: (identifier) @function)")],
+        capture_precedence_options(CapturePrecedence::FirstMatch),
+        &[],
+        None,
+    )]
     #[tokio::test(flavor = "current_thread")]
     async fn server_diagnostics(
         #[case] documents: &[Document<'_>],
@@ -1,8 +1,8 @@
-use tower_lsp::lsp_types::{DidChangeTextDocumentParams, Position, Range};
+use tower_lsp::lsp_types::{DidChangeTextDocumentParams, Position, Range, Url};
 use tracing::warn;
 
 use crate::{
-    Backend, LspClient,
+    Backend, ImportedUri, LspClient,
     util::{ByteUtil, TextDocChangeUtil, edit_rope, get_imported_uris, parse, push_diagnostics},
 };
 
@@ -10,6 +10,12 @@ use super::did_open::populate_import_documents;
 
 pub async fn did_change<C: LspClient>(backend: &Backend<C>, params: DidChangeTextDocumentParams) {
     let uri = params.text_document.uri;
+    let multiple_directives = backend
+        .options
+        .read()
+        .await
+        .import_options
+        .multiple_directives;
     let Some(mut document) = backend.document_map.get_mut(&uri) else {
         warn!("No document found for URI: {uri} when handling did_change");
         return;
@@ -17,6 +23,18 @@ pub async fn did_change<C: LspClient>(backend: &Backend<C>, params: DidChangeTex
     let version = params.text_document.version;
     document.version = Some(version);
 
+    // Import directives can only be read from the comments at the top of the file, which end
+    // where the first other node starts. Changes below that line do not move it.
+    let directives_end = if multiple_directives {
+        let root = document.tree.root_node();
+        (0..root.child_count() as u32)
+            .filter_map(|i| root.child(i))
+            .find(|node| node.kind() != "comment")
+            .map_or(u32::MAX, |node| node.start_position().row as u32)
+    } else {
+        0
+    };
+
     let mut edits = vec![];
     let mut recalculate_imports = false;
     for change in &params.content_changes {
@@ -29,7 +47,9 @@ pub async fn did_change<C: LspClient>(backend: &Backend<C>, params: DidChangeTex
             Range { start, end }
         });
 
-        if range.start.line == 0 {
+        // Import directives are only read from the first line, unless multiple directives are
+        // allowed at the top of the file.
+        if range.start.line <= directives_end {
             recalculate_imports = true;
         }
 
@@ -56,37 +76,47 @@ pub async fn did_change<C: LspClient>(backend: &Backend<C>, params: DidChangeTex
         let uris = get_imported_uris(&workspace_uris, &options, &uri, &rope, &tree);
         populate_import_documents(&backend.document_map, &workspace_uris, &options, &uris);
 
-        if let Some(mut document) = backend.document_map.get_mut(&uri) {
-            // Remove previous import URIs.
-            for import_uri in document
-                .imported_uris
-                .iter()
-                .filter_map(|import| import.uri.clone().filter(|url| url != &uri))
-            {
-                backend
-                    .dependents
-                    .entry(import_uri)
-                    .or_default()
-                    .remove(&uri);
-            }
-            // Add new import URIs.
-            for import_uri in uris
-                .iter()
-                .filter_map(|import| import.uri.clone().filter(|url| url != &uri))
-            {
-                backend
-                    .dependents
-                    .entry(import_uri)
-                    .or_default()
-                    .insert(uri.clone());
-            }
-            document.imported_uris = uris;
-        }
+        set_imported_uris(backend, &uri, uris);
+    }
+
+    if let Some(document) = backend.document_map.get(&uri) {
+        backend.workspace_index.index_document(&uri, &document);
     }
 
     push_diagnostics(backend, uri).await;
 }
 
+/// Replace the imports of a tracked document, keeping the dependents map in sync.
+pub fn set_imported_uris<C: LspClient>(backend: &Backend<C>, uri: &Url, uris: Vec<ImportedUri>) {
+    let Some(mut document) = backend.document_map.get_mut(uri) else {
+        return;
+    };
+    // Remove previous import URIs.
+    for import_uri in document
+        .imported_uris
+        .iter()
+        .filter_map(|import| import.uri.clone().filter(|url| url != uri))
+    {
+        backend
+            .dependents
+            .entry(import_uri)
+            .or_default()
+            .remove(uri);
+    }
+    // Add new import URIs.
+    for import_uri in uris
+        .iter()
+        .filter_map(|import| import.uri.clone().filter(|url| url != uri))
+    {
+        backend
+            .dependents
+            .entry(import_uri)
+            .or_default()
+            .insert(uri.clone());
+    }
+    document.imported_uris = uris;
+}
+
 #[cfg(test)]
 mod test {
     use pretty_assertions::assert_eq;
@@ -96,9 +126,13 @@ mod test {
         VersionedTextDocumentIdentifier, notification::DidChangeTextDocument,
     };
 
+    use ts_query_ls::ImportOptions;
+
     use crate::{
         Options,
-        test_helpers::helpers::{TEST_URI, TestEdit, TestService, initialize_server},
+        test_helpers::helpers::{
+            QUERY_TEST_URI, TEST_URI, TestEdit, TestService, initialize_server,
+        },
     };
 
     #[rstest]
@@ -171,4 +205,41 @@ mod test {
         );
         assert_eq!(2, service.inner().client.get_notifications().len());
     }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn server_did_change_import_directive() {
+        // Arrange
+        let options = Options {
+            import_options: ImportOptions {
+                multiple_directives: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut service = initialize_server(
+            &[(QUERY_TEST_URI.clone(), "; inherits: cpp\n; inherits: \n")],
+            &options,
+        )
+        .await;
+
+        // Act
+        service
+            .notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier {
+                    uri: QUERY_TEST_URI.clone(),
+                    version: 1,
+                },
+                content_changes: vec![(&TestEdit::new("other", (1, 12), (1, 12))).into()],
+            })
+            .await;
+
+        // Assert
+        let doc = service.inner().document_map.get(&QUERY_TEST_URI).unwrap();
+        let imports: Vec<_> = doc
+            .imported_uris
+            .iter()
+            .map(|import| (import.name.as_str(), import.uri.is_some()))
+            .collect();
+        assert_eq!(imports, [("cpp", true), ("other", true)]);
+    }
 }
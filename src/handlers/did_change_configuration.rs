@@ -2,6 +2,8 @@ use tower_lsp::lsp_types::DidChangeConfigurationParams;
 
 use crate::{Backend, LspClient, util::set_configuration_options};
 
+use super::initialized::reregister_file_watchers;
+
 pub async fn did_change_configuration<C: LspClient>(
     backend: &Backend<C>,
     params: DidChangeConfigurationParams,
@@ -16,6 +18,7 @@ pub async fn did_change_configuration<C: LspClient>(
             .unwrap_or_default(),
     )
     .await;
+    reregister_file_watchers(backend).await;
 }
 
 #[cfg(test)]
@@ -0,0 +1,480 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use ropey::Rope;
+use tower_lsp::lsp_types::{DidChangeWatchedFilesParams, FileChangeType, Url};
+use tracing::info;
+
+use crate::{
+    Backend, DocumentData, LspClient,
+    parser_discovery::clear_discovered_parsers,
+    util::{
+        get_imported_uris, get_language_name, get_query_modules, parse, refresh_diagnostics,
+        reload_configuration_options,
+    },
+    workspace_index::{index_workspace, reindex_uri},
+};
+
+use super::{
+    did_change::set_imported_uris,
+    did_open::{
+        compile_grammars, populate_import_documents, populate_language_info, reload_parsers,
+    },
+    initialized::reregister_file_watchers,
+};
+
+pub async fn did_change_watched_files<C: LspClient>(
+    backend: &Backend<C>,
+    params: DidChangeWatchedFilesParams,
+) {
+    let mut changed_modules = HashSet::new();
+    let mut modules_created_or_deleted = false;
+    let mut changed_parsers = HashSet::new();
+    let mut config_changed = false;
+    let grammar_source_directories = backend
+        .options
+        .read()
+        .await
+        .grammar_source_directories
+        .clone();
+    for change in params.changes {
+        let uri = change.uri;
+        let Ok(path) = uri.to_file_path() else {
+            continue;
+        };
+        info!("ts_query_ls watched file changed: {uri}");
+        if path
+            .file_name()
+            .is_some_and(|name| name == ".tsqueryrc.json")
+        {
+            config_changed = true;
+        } else if path.extension().is_some_and(|ext| ext == "scm") {
+            // Documents open in the editor are kept up to date as they are edited
+            if backend
+                .document_map
+                .get(&uri)
+                .is_some_and(|document| document.version.is_some())
+            {
+                continue;
+            }
+            if change.typ == FileChangeType::DELETED {
+                backend.document_map.remove(&uri);
+                backend.workspace_index.remove(&uri);
+            } else {
+                reload_module(backend, &uri).await;
+                reindex_uri(backend, &uri).await;
+            }
+            modules_created_or_deleted |= change.typ != FileChangeType::CHANGED;
+            changed_modules.insert(uri);
+        } else if let Some(parser_name) = get_parser_name(&path) {
+            if change.typ != FileChangeType::CHANGED {
+                clear_discovered_parsers();
+            }
+            changed_parsers.insert(parser_name);
+        } else if let Some(grammar_name) = get_grammar_name(&path, &grammar_source_directories) {
+            changed_parsers.insert(grammar_name);
+        }
+    }
+
+    // Reloading the configuration also refreshes the imports and diagnostics of every open document,
+    // but changed parsers which are already loaded must still be reloaded
+    if config_changed {
+        reload_configuration(backend).await;
+    } else if modules_created_or_deleted {
+        // A created or deleted module may change how the imports of open documents resolve
+        refresh_imports(backend).await;
+    }
+
+    // Only reload parsers which are in use
+    let reloaded_parsers: Vec<_> = changed_parsers
+        .into_iter()
+        .filter(|parser_name| {
+            backend.language_map.contains_key(parser_name)
+                || backend.document_map.iter().any(|document| {
+                    document.version.is_some()
+                        && document.language_name.as_ref() == Some(parser_name)
+                })
+        })
+        .collect();
+    reload_parsers(backend, &reloaded_parsers).await;
+    if config_changed {
+        return;
+    }
+
+    let mut stale_documents = Vec::new();
+    if modules_created_or_deleted {
+        stale_documents.extend(
+            backend
+                .document_map
+                .iter()
+                .filter(|document| document.version.is_some() && !document.imported_uris.is_empty())
+                .map(|document| document.key().clone()),
+        );
+    }
+    // Every open document that (transitively) imports a changed module depends on it, including
+    // the parenthesized modules it imports directly, which are not combined with it
+    if !changed_modules.is_empty() {
+        let open_documents: Vec<_> = backend
+            .document_map
+            .iter()
+            .filter(|document| document.version.is_some())
+            .map(|document| {
+                let imported: Vec<_> = document
+                    .imported_uris
+                    .iter()
+                    .filter_map(|import| import.uri.clone())
+                    .collect();
+                (document.key().clone(), imported)
+            })
+            .collect();
+        for (uri, imported) in open_documents {
+            if !stale_documents.contains(&uri)
+                && (imported
+                    .iter()
+                    .any(|module_uri| changed_modules.contains(module_uri))
+                    || get_query_modules(&backend.document_map, &uri)
+                        .iter()
+                        .any(|(module_uri, _)| changed_modules.contains(module_uri)))
+            {
+                stale_documents.push(uri);
+            }
+        }
+    }
+    refresh_diagnostics(backend, stale_documents).await;
+}
+
+/// Get the name of the language provided by the given parser object, if it is one.
+fn get_parser_name(path: &Path) -> Option<String> {
+    let extension = path.extension()?;
+    if !["so", "dll", "dylib", "wasm"]
+        .iter()
+        .any(|parser_extension| extension == *parser_extension)
+    {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?;
+    Some(stem.strip_prefix("tree-sitter-").unwrap_or(stem).to_owned())
+}
+
+/// Get the name of the language whose grammar checkout contains the given parser source file (a C
+/// source or header within its `src` directory), if it is one.
+fn get_grammar_name(
+    path: &Path,
+    grammar_source_directories: &BTreeMap<String, String>,
+) -> Option<String> {
+    if !path.extension().is_some_and(|ext| ext == "c" || ext == "h") {
+        return None;
+    }
+    let source_directory = path
+        .ancestors()
+        .skip(1)
+        .find(|directory| directory.file_name().is_some_and(|name| name == "src"))?;
+    let grammar_directory = source_directory.parent()?;
+    if let Some((name, _)) = grammar_source_directories
+        .iter()
+        .find(|(_, directory)| Path::new(directory) == grammar_directory)
+    {
+        return Some(name.clone());
+    }
+    let directory_name = grammar_directory.file_name()?.to_str()?;
+    Some(
+        directory_name
+            .strip_prefix("tree-sitter-")
+            .unwrap_or(directory_name)
+            .to_owned(),
+    )
+}
+
+/// Re-read a module which is tracked (but not opened) from disk.
+async fn reload_module<C: LspClient>(backend: &Backend<C>, uri: &Url) {
+    if !backend.document_map.contains_key(uri) {
+        return;
+    }
+    let Some(contents) = uri
+        .to_file_path()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+    else {
+        backend.document_map.remove(uri);
+        return;
+    };
+    let options = backend.options.read().await;
+    let workspace_dirs = backend.workspace_paths.read().unwrap().clone();
+    let rope = Rope::from_str(&contents);
+    let tree = parse(&rope, None);
+    let imported_uris = get_imported_uris(&workspace_dirs, &options, uri, &rope, &tree);
+    backend.document_map.insert(
+        uri.clone(),
+        DocumentData {
+            rope,
+            tree,
+            language_name: None,
+            version: None,
+            imported_uris: imported_uris.clone(),
+        },
+    );
+    populate_import_documents(
+        &backend.document_map,
+        &workspace_dirs,
+        &options,
+        &imported_uris,
+    );
+}
+
+/// Re-resolve the imports of every open document.
+async fn refresh_imports<C: LspClient>(backend: &Backend<C>) {
+    let options = backend.options.read().await;
+    let workspace_dirs = backend.workspace_paths.read().unwrap().clone();
+    let open_documents: Vec<_> = backend
+        .document_map
+        .iter()
+        .filter(|document| document.version.is_some())
+        .map(|document| {
+            (
+                document.key().clone(),
+                document.rope.clone(),
+                document.tree.clone(),
+            )
+        })
+        .collect();
+    for (uri, rope, tree) in open_documents {
+        let imported_uris = get_imported_uris(&workspace_dirs, &options, &uri, &rope, &tree);
+        populate_import_documents(
+            &backend.document_map,
+            &workspace_dirs,
+            &options,
+            &imported_uris,
+        );
+        set_imported_uris(backend, &uri, imported_uris);
+    }
+}
+
+/// Re-read the configuration file and refresh everything derived from the options.
+async fn reload_configuration<C: LspClient>(backend: &Backend<C>) {
+    let workspace_dirs = backend.workspace_paths.read().unwrap().clone();
+    reload_configuration_options(backend, workspace_dirs).await;
+    reregister_file_watchers(backend).await;
+
+    let language_names: HashSet<_> = {
+        let options = backend.options.read().await;
+        for mut document in backend.document_map.iter_mut() {
+            if document.version.is_some() {
+                let language_name = get_language_name(document.key(), &options);
+                document.language_name = language_name;
+            }
+        }
+        backend
+            .document_map
+            .iter()
+            .filter_map(|document| document.language_name.clone())
+            .collect()
+    };
+    for language_name in language_names {
+        compile_grammars(backend, &language_name).await;
+        populate_language_info(backend, Some(language_name), &*backend.options.read().await);
+    }
+    refresh_imports(backend).await;
+    index_workspace(backend, None).await;
+
+    let open_documents = backend
+        .document_map
+        .iter()
+        .filter(|document| document.version.is_some())
+        .map(|document| document.key().clone())
+        .collect();
+    refresh_diagnostics(backend, open_documents).await;
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, sync::Arc};
+
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+    use tower_lsp::lsp_types::{
+        DidChangeWatchedFilesParams, FileChangeType, FileEvent, Url, WorkspaceSymbolParams,
+        notification::DidChangeWatchedFiles, request::WorkspaceSymbolRequest,
+    };
+
+    use crate::{
+        Options,
+        test_helpers::helpers::{
+            CPP_HIGHLIGHTS_WS_URI, RUST_TEST_URI, TEST_URI, TempDir, TestService, initialize_server,
+        },
+    };
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn server_did_change_watched_files() {
+        // Arrange
+        let mut service = initialize_server(&[], &Options::default()).await;
+        service
+            .request::<WorkspaceSymbolRequest>(WorkspaceSymbolParams::default())
+            .await;
+        let highlights_uri = Url::from_file_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/test_workspace/queries/other/highlights.scm"
+        ))
+        .unwrap();
+        let missing_uri = Url::from_file_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/test_workspace/queries/missing/highlights.scm"
+        ))
+        .unwrap();
+        assert!(
+            service
+                .inner()
+                .workspace_index
+                .get(&highlights_uri)
+                .is_some()
+        );
+
+        // Act
+        service
+            .notify::<DidChangeWatchedFiles>(DidChangeWatchedFilesParams {
+                changes: vec![
+                    FileEvent::new(highlights_uri.clone(), FileChangeType::DELETED),
+                    FileEvent::new(missing_uri.clone(), FileChangeType::CREATED),
+                ],
+            })
+            .await;
+
+        // Assert
+        let index = &service.inner().workspace_index;
+        assert!(index.get(&highlights_uri).is_none());
+        assert!(index.get(&missing_uri).is_none());
+        assert_eq!(index.uris().len(), 3);
+    }
+
+    #[rstest]
+    #[case(&TEST_URI, "; inherits: cpp\n\n(squid)", &CPP_HIGHLIGHTS_WS_URI, 1)]
+    #[case(&TEST_URI, "(squid)", &CPP_HIGHLIGHTS_WS_URI, 0)]
+    #[case(&RUST_TEST_URI, "(squid)", &Url::parse("file:///tmp/parsers/tree-sitter-rust.so").unwrap(), 1)]
+    #[case(&TEST_URI, "(squid)", &Url::parse("file:///tmp/parsers/rust.wasm").unwrap(), 0)]
+    #[case(&RUST_TEST_URI, "(squid)", &Url::parse("file:///tmp/tree-sitter-rust/src/scanner.c").unwrap(), 1)]
+    #[case(&RUST_TEST_URI, "(squid)", &Url::parse("file:///tmp/tree-sitter-rust/src/tree_sitter/parser.h").unwrap(), 1)]
+    #[case(&RUST_TEST_URI, "(squid)", &Url::parse("file:///tmp/tree-sitter-rust/scanner.c").unwrap(), 0)]
+    #[tokio::test(flavor = "current_thread")]
+    async fn server_did_change_watched_files_rediagnose(
+        #[case] uri: &Url,
+        #[case] source: &str,
+        #[case] changed_uri: &Url,
+        #[case] expected_diagnostics: usize,
+    ) {
+        // Arrange
+        let mut service = initialize_server(&[(uri.clone(), source)], &Options::default()).await;
+        let notification_count = service.inner().client.get_notifications().len();
+
+        // Act
+        service
+            .notify::<DidChangeWatchedFiles>(DidChangeWatchedFilesParams {
+                changes: vec![FileEvent::new(changed_uri.clone(), FileChangeType::CHANGED)],
+            })
+            .await;
+
+        // Assert
+        assert_eq!(
+            service.inner().client.get_notifications().len() - notification_count,
+            expected_diagnostics
+        );
+        assert!(service.inner().language_map.contains_key("rust") == (uri == &*RUST_TEST_URI));
+    }
+
+    #[rstest]
+    #[case(Some(r#"{ "parser_install_directories": ["/other/parsers"] }"#), &["/client/parsers", "/other/parsers"])]
+    #[case(None, &["/client/parsers"])]
+    #[tokio::test(flavor = "current_thread")]
+    async fn server_did_change_watched_files_reload_configuration(
+        #[case] new_config: Option<&str>,
+        #[case] expected: &[&str],
+    ) {
+        // Arrange
+        let directory = TempDir::new(&format!("config-test-{}", new_config.is_some()));
+        let config_path = directory.join(".tsqueryrc.json");
+        fs::write(
+            &config_path,
+            r#"{ "parser_install_directories": ["/file/parsers", "/client/parsers", "/file/parsers"] }"#,
+        )
+        .unwrap();
+        let config_uri = Url::from_file_path(&config_path).unwrap();
+        let options = Options {
+            parser_install_directories: vec![String::from("/client/parsers")],
+            ..Default::default()
+        };
+        let mut service = initialize_server(&[], &options).await;
+        *service.inner().workspace_paths.write().unwrap() = vec![directory.to_path_buf()];
+        service
+            .notify::<DidChangeWatchedFiles>(DidChangeWatchedFilesParams {
+                changes: vec![FileEvent::new(config_uri.clone(), FileChangeType::CREATED)],
+            })
+            .await;
+        assert_eq!(
+            service
+                .inner()
+                .options
+                .read()
+                .await
+                .parser_install_directories,
+            ["/client/parsers", "/file/parsers"]
+        );
+
+        // Act
+        let change_type = if let Some(new_config) = new_config {
+            fs::write(&config_path, new_config).unwrap();
+            FileChangeType::CHANGED
+        } else {
+            fs::remove_file(&config_path).unwrap();
+            FileChangeType::DELETED
+        };
+        service
+            .notify::<DidChangeWatchedFiles>(DidChangeWatchedFilesParams {
+                changes: vec![FileEvent::new(config_uri, change_type)],
+            })
+            .await;
+
+        // Assert
+        assert_eq!(
+            service
+                .inner()
+                .options
+                .read()
+                .await
+                .parser_install_directories,
+            expected
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn server_did_change_watched_files_reload_configuration_and_parser() {
+        // Arrange
+        let directory = TempDir::new("config-parser-test");
+        let config_path = directory.join(".tsqueryrc.json");
+        fs::write(&config_path, "{}").unwrap();
+        let mut service =
+            initialize_server(&[(RUST_TEST_URI.clone(), "(squid)")], &Options::default()).await;
+        *service.inner().workspace_paths.write().unwrap() = vec![directory.to_path_buf()];
+        let language_data = service.inner().language_map.get("rust").unwrap().clone();
+
+        // Act
+        service
+            .notify::<DidChangeWatchedFiles>(DidChangeWatchedFilesParams {
+                changes: vec![
+                    FileEvent::new(
+                        Url::from_file_path(&config_path).unwrap(),
+                        FileChangeType::CREATED,
+                    ),
+                    FileEvent::new(
+                        Url::parse("file:///tmp/parsers/tree-sitter-rust.so").unwrap(),
+                        FileChangeType::CHANGED,
+                    ),
+                ],
+            })
+            .await;
+
+        // Assert
+        let reloaded_language_data = service.inner().language_map.get("rust").unwrap().clone();
+        assert!(!Arc::ptr_eq(&language_data, &reloaded_language_data));
+    }
+}
@@ -1,12 +1,32 @@
 use tower_lsp::lsp_types::DidCloseTextDocumentParams;
 use tracing::{info, warn};
 
-use crate::{Backend, LspClient};
+use crate::{
+    Backend, LspClient, shadowing::forget_shadowed_patterns, workspace_index::reindex_uri,
+};
 
-pub fn did_close<C: LspClient>(backend: &Backend<C>, params: &DidCloseTextDocumentParams) {
+use super::inlay_hint::forget_compiled_query;
+
+pub async fn did_close<C: LspClient>(backend: &Backend<C>, params: &DidCloseTextDocumentParams) {
     let uri = &params.text_document.uri;
     info!("ts_query_ls did_close: {uri}");
     if backend.document_map.remove(uri).is_none() {
         warn!("Document with URI: {uri} was not being tracked");
     }
+    forget_compiled_query(uri);
+    forget_shadowed_patterns(uri);
+    // Discard any unsaved changes from the index, and forget documents outside of the workspace
+    let in_workspace = uri.to_file_path().is_ok_and(|path| {
+        backend
+            .workspace_paths
+            .read()
+            .unwrap()
+            .iter()
+            .any(|dir| path.starts_with(dir))
+    });
+    if in_workspace {
+        reindex_uri(backend, uri).await;
+    } else {
+        backend.workspace_index.remove(uri);
+    }
 }
@@ -6,13 +6,31 @@ use std::{
 
 use dashmap::DashMap;
 use ropey::Rope;
-use tower_lsp::lsp_types::{DidOpenTextDocumentParams, Url};
-use tracing::info;
+use tower_lsp::lsp_types::{
+    DidOpenTextDocumentParams, ProgressParams, ProgressParamsValue, Url, WorkDoneProgress,
+    WorkDoneProgressBegin, WorkDoneProgressEnd, notification::Progress,
+};
+use tracing::{info, warn};
 use tree_sitter::Language;
 
 use crate::{
     Backend, DocumentData, ImportedUri, LanguageData, LspClient, Options, SymbolInfo,
-    util::{get_imported_uris, get_language, get_language_name, parse, push_diagnostics},
+    compiler::{
+        compile_grammar, is_grammar_compiled, is_grammar_directory, parser_cache_directory,
+    },
+    corpus::clear_corpus_cache,
+    node_types::get_node_types,
+    shadowing::clear_shadowed_patterns_cache,
+    util::{
+        escape_node_kind, get_imported_uris, get_language_name, get_language_with_path,
+        get_work_done_token, grammar_directories, parse, parser_modified, push_diagnostics,
+        refresh_diagnostics,
+    },
+};
+
+use super::{
+    completion::clear_probed_children_cache, diagnostic::clear_query_scan_cache,
+    inlay_hint::clear_compiled_queries,
 };
 
 pub async fn did_open<C: LspClient>(backend: &Backend<C>, params: DidOpenTextDocumentParams) {
@@ -39,6 +57,10 @@ pub async fn did_open<C: LspClient>(backend: &Backend<C>, params: DidOpenTextDoc
         },
     );
 
+    if let Some(document) = backend.document_map.get(&uri) {
+        backend.workspace_index.index_document(&uri, &document);
+    }
+
     populate_import_documents(
         &backend.document_map,
         &workspace_uris,
@@ -57,12 +79,23 @@ pub async fn did_open<C: LspClient>(backend: &Backend<C>, params: DidOpenTextDoc
             .insert(uri.clone());
     }
 
-    populate_language_info(backend, language_name, &options);
+    drop(options);
+    if let Some(language_name) = &language_name {
+        compile_grammars(backend, language_name).await;
+    }
+    populate_language_info(
+        backend,
+        language_name.clone(),
+        &*backend.options.read().await,
+    );
+    if let Some(language_name) = language_name {
+        reload_stale_parser(backend, &language_name).await;
+    }
 
     push_diagnostics(backend, uri).await;
 }
 
-fn populate_language_info<C: LspClient>(
+pub fn populate_language_info<C: LspClient>(
     backend: &Backend<C>,
     language_name: Option<String>,
     options: &Options,
@@ -73,11 +106,153 @@ fn populate_language_info<C: LspClient>(
     if backend.language_map.contains_key(&language_name) {
         return;
     }
-    let Some(lang) = get_language(&language_name, options) else {
+    let Some(language_data) = load_language_data(&language_name, options) else {
         return;
     };
-    let language_data = init_language_data(lang, language_name.clone()).into();
-    backend.language_map.insert(language_name, language_data);
+    backend
+        .language_map
+        .insert(language_name, language_data.into());
+}
+
+/// Load the parser of the given language, along with its node types.
+fn load_language_data(language_name: &str, options: &Options) -> Option<LanguageData> {
+    let (lang, parser_path) = get_language_with_path(language_name, options)?;
+    let mut language_data = init_language_data(lang, language_name.to_owned());
+    language_data.node_types = get_node_types(language_name, options);
+    language_data.parser_file = parser_path.and_then(|path| {
+        let modified = parser_modified(&path)?;
+        Some((path, modified))
+    });
+    Some(language_data)
+}
+
+/// Compile the parser of the given language from its grammar checkout if the cached library is out
+/// of date, on a blocking thread and reporting progress, so that it can then be loaded from the
+/// cache. Nothing is compiled if the language is already loaded.
+pub async fn compile_grammars<C: LspClient>(backend: &Backend<C>, language_name: &str) {
+    if backend.language_map.contains_key(language_name) {
+        return;
+    }
+    compile_grammar_sources(backend, language_name).await;
+}
+
+/// Compile the parser of the given language from the first of its grammar checkouts which compiles,
+/// unless its cached library is up to date.
+async fn compile_grammar_sources<C: LspClient>(backend: &Backend<C>, language_name: &str) {
+    let grammar_directories = grammar_directories(language_name, &*backend.options.read().await);
+    let cache_directory = parser_cache_directory();
+    for grammar_directory in grammar_directories {
+        if !is_grammar_directory(&grammar_directory) {
+            continue;
+        }
+        // The first grammar which compiles is the one that is loaded
+        if is_grammar_compiled(language_name, &grammar_directory, &cache_directory) {
+            return;
+        }
+
+        let token = get_work_done_token(backend, None).await;
+        if let Some(token) = token.clone() {
+            backend
+                .client
+                .send_notification::<Progress>(ProgressParams {
+                    token,
+                    value: ProgressParamsValue::WorkDone(WorkDoneProgress::Begin(
+                        WorkDoneProgressBegin {
+                            title: format!("Compiling parser {language_name}"),
+                            message: Some(grammar_directory.display().to_string()),
+                            cancellable: Some(false),
+                            percentage: None,
+                        },
+                    )),
+                })
+                .await;
+        }
+        let name = language_name.to_owned();
+        let directory = cache_directory.clone();
+        let compiled = tokio::task::spawn_blocking(move || {
+            compile_grammar(&name, &grammar_directory, &directory).is_some()
+        })
+        .await
+        .unwrap_or_default();
+        if let Some(token) = token {
+            backend
+                .client
+                .send_notification::<Progress>(ProgressParams {
+                    token,
+                    value: ProgressParamsValue::WorkDone(WorkDoneProgress::End(
+                        WorkDoneProgressEnd {
+                            message: Some(String::from(if compiled {
+                                "Compiled"
+                            } else {
+                                "Failed to compile"
+                            })),
+                        },
+                    )),
+                })
+                .await;
+        }
+        if compiled {
+            return;
+        }
+    }
+}
+
+/// Reload the given parsers, forgetting everything derived from their previous versions, and
+/// re-diagnose the open documents of their languages. Returns the names of the parsers which were
+/// reloaded successfully.
+pub async fn reload_parsers<C: LspClient>(
+    backend: &Backend<C>,
+    parser_names: &[String],
+) -> Vec<String> {
+    let mut reloaded = Vec::new();
+    for parser_name in parser_names {
+        info!("Reloading parser {parser_name}");
+        compile_grammar_sources(backend, parser_name).await;
+        // The previous parser stays in use until the new one is loaded
+        let Some(language_data) = load_language_data(parser_name, &*backend.options.read().await)
+        else {
+            warn!("Unable to reload parser {parser_name}");
+            continue;
+        };
+        backend
+            .language_map
+            .insert(parser_name.clone(), language_data.into());
+        clear_query_scan_cache(parser_name);
+        clear_probed_children_cache(parser_name);
+        clear_corpus_cache(parser_name);
+        clear_shadowed_patterns_cache(parser_name);
+        clear_compiled_queries(parser_name);
+        reloaded.push(parser_name.clone());
+    }
+    let documents = backend
+        .document_map
+        .iter()
+        .filter(|document| {
+            document.version.is_some()
+                && document
+                    .language_name
+                    .as_ref()
+                    .is_some_and(|name| parser_names.contains(name))
+        })
+        .map(|document| document.key().clone())
+        .collect();
+    refresh_diagnostics(backend, documents).await;
+    reloaded
+}
+
+/// Reload the parser of the given language if its parser object (or grammar source) was modified
+/// since it was loaded.
+pub async fn reload_stale_parser<C: LspClient>(backend: &Backend<C>, language_name: &str) {
+    let stale = backend
+        .language_map
+        .get(language_name)
+        .and_then(|language_data| language_data.parser_file.clone())
+        .is_some_and(|(path, loaded_modified)| {
+            parser_modified(&path).is_some_and(|modified| modified != loaded_modified)
+        });
+    if stale {
+        reload_parsers(backend, &[language_name.to_owned()]).await;
+    }
 }
 
 pub fn init_language_data(language: Language, name: String) -> LanguageData {
@@ -99,15 +274,7 @@ pub fn init_language_data(language: Language, name: String) -> LanguageData {
         let label = if named {
             language.node_kind_for_id(i).unwrap().to_owned()
         } else {
-            language
-                .node_kind_for_id(i)
-                .unwrap()
-                .replace('\\', r"\\")
-                .replace('"', r#"\""#)
-                .replace('\n', r"\n")
-                .replace('\r', r"\r")
-                .replace('\t', r"\t")
-                .replace('\0', r"\0")
+            escape_node_kind(language.node_kind_for_id(i).unwrap())
         };
         let symbol_info = SymbolInfo { label, named };
         if supertype {
@@ -130,6 +297,17 @@ pub fn init_language_data(language: Language, name: String) -> LanguageData {
         symbols_set.insert(symbol_info.clone());
         symbols_vec.push(symbol_info);
     }
+    // Aliased symbols never appear in the parse table, since the parser itself only deals with the
+    // original symbol
+    let mut parsed_ids = HashSet::new();
+    for state in 1..language.parse_state_count() as u16 {
+        if let Some(lookahead) = language.lookahead_iterator(state) {
+            parsed_ids.extend(lookahead);
+        }
+    }
+    let alias_ids = (0..language.node_kind_count() as u16)
+        .filter(|id| !parsed_ids.contains(id))
+        .collect();
     // Field IDs go from 1 to nfields inclusive (extra index 0 maps to NULL)
     for i in 1..=language.field_count() as u16 {
         let field_name = language.field_name_for_id(i).unwrap().to_owned();
@@ -145,7 +323,10 @@ pub fn init_language_data(language: Language, name: String) -> LanguageData {
         fields_set,
         fields_vec,
         supertype_map,
+        alias_ids,
+        node_types: None,
         language,
+        parser_file: None,
     }
 }
 
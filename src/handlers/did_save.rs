@@ -3,7 +3,16 @@ use tracing::info;
 
 use crate::{Backend, LspClient};
 
-pub fn did_save<C: LspClient>(_: &Backend<C>, params: DidSaveTextDocumentParams) {
+use super::did_open::reload_stale_parser;
+
+pub async fn did_save<C: LspClient>(backend: &Backend<C>, params: DidSaveTextDocumentParams) {
     let uri = params.text_document.uri;
     info!("ts_query_ls saved document with URI: {uri}");
+    let language_name = backend
+        .document_map
+        .get(&uri)
+        .and_then(|document| document.language_name.clone());
+    if let Some(language_name) = language_name {
+        reload_stale_parser(backend, &language_name).await;
+    }
 }
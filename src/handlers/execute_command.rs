@@ -0,0 +1,487 @@
+use std::{fmt::Write as _, fs, path::PathBuf};
+
+use ropey::Rope;
+use serde::Serialize;
+use serde_json::Value;
+use streaming_iterator::StreamingIterator as _;
+use tower_lsp::{
+    jsonrpc::{self, Result},
+    lsp_types::{ExecuteCommandParams, Range, Url},
+};
+use tracing::warn;
+use tree_sitter::{Language, Query, QueryCursor};
+
+use crate::{
+    Backend, LspClient,
+    util::{NodeUtil as _, get_query_modules, language_parser, parse},
+};
+
+use super::did_open::reload_parsers;
+
+/// Run the given query document against a sample source file. Arguments are the URI of the query
+/// document and the URI (or path) of the source file.
+pub const RUN_QUERY_COMMAND: &str = "ts_query_ls.runQuery";
+
+/// Produce the fully flattened query of the given document, with its imported modules inlined in
+/// the order in which they are combined. The argument is the URI of the query document.
+pub const EXPAND_QUERY_COMMAND: &str = "ts_query_ls.expandQuery";
+
+/// Reload parser objects from disk, e.g. after a grammar was rebuilt, and re-diagnose the open
+/// documents of their languages. The optional arguments are the names of the parsers to reload; by
+/// default every loaded parser is reloaded. Returns the names of the reloaded parsers.
+pub const RELOAD_PARSERS_COMMAND: &str = "ts_query_ls.reloadParsers";
+
+/// The commands supported by the server.
+pub const COMMANDS: &[&str] = &[
+    RUN_QUERY_COMMAND,
+    EXPAND_QUERY_COMMAND,
+    RELOAD_PARSERS_COMMAND,
+];
+
+/// A single match produced by running a query against a source file.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryMatchResult {
+    /// The index of the pattern which produced this match.
+    pub pattern_index: usize,
+    /// The captures in this match.
+    pub captures: Vec<QueryCaptureResult>,
+}
+
+/// A single capture within a query match.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryCaptureResult {
+    /// The name of the capture, without the leading `@`.
+    pub name: String,
+    /// The kind of the captured node.
+    pub kind: String,
+    /// The range of the captured node in the source file.
+    pub range: Range,
+    /// The text of the captured node.
+    pub text: String,
+}
+
+/// The fully flattened query of a document, as a virtual document.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExpandedQuery {
+    /// The URI of the virtual document, e.g. `ts-query-ls://expanded/path/to/highlights.scm`.
+    pub uri: Url,
+    /// The text of the flattened query.
+    pub text: String,
+}
+
+pub async fn execute_command<C: LspClient>(
+    backend: &Backend<C>,
+    params: &ExecuteCommandParams,
+) -> Result<Option<Value>> {
+    match params.command.as_str() {
+        RUN_QUERY_COMMAND => run_query_command(backend, &params.arguments),
+        EXPAND_QUERY_COMMAND => expand_query_command(backend, &params.arguments),
+        RELOAD_PARSERS_COMMAND => reload_parsers_command(backend, &params.arguments).await,
+        command => Err(jsonrpc::Error::invalid_params(format!(
+            "Unknown command: {command}"
+        ))),
+    }
+}
+
+fn run_query_command<C: LspClient>(
+    backend: &Backend<C>,
+    arguments: &[Value],
+) -> Result<Option<Value>> {
+    let (Some(query_uri), Some(source_path)) = (
+        arguments
+            .first()
+            .and_then(Value::as_str)
+            .and_then(|arg| Url::parse(arg).ok()),
+        arguments.get(1).and_then(argument_to_path),
+    ) else {
+        return Err(jsonrpc::Error::invalid_params(
+            "Expected a query document URI and a source file path",
+        ));
+    };
+    let Some(doc) = backend.document_map.get(&query_uri) else {
+        warn!("No document found for URI: {query_uri} when running query");
+        return Err(jsonrpc::Error::invalid_params(format!(
+            "No document found for URI: {query_uri}"
+        )));
+    };
+    let query_source = doc.rope.to_string();
+    let Some(language_name) = doc.language_name.clone() else {
+        return Err(jsonrpc::Error::invalid_params(format!(
+            "Could not determine the language for {query_uri}"
+        )));
+    };
+    drop(doc);
+    let Some(language_data) = backend.language_map.get(&language_name) else {
+        return Err(jsonrpc::Error::invalid_params(format!(
+            "Language object for {language_name:?} not found"
+        )));
+    };
+    let source = fs::read_to_string(&source_path).map_err(|err| {
+        jsonrpc::Error::invalid_params(format!("Failed to read {}: {err}", source_path.display()))
+    })?;
+    let matches = run_query(&language_data.language, &query_source, &source)
+        .map_err(jsonrpc::Error::invalid_params)?;
+    Ok(Some(
+        serde_json::to_value(matches).map_err(|_| jsonrpc::Error::internal_error())?,
+    ))
+}
+
+fn expand_query_command<C: LspClient>(
+    backend: &Backend<C>,
+    arguments: &[Value],
+) -> Result<Option<Value>> {
+    let Some(query_uri) = arguments
+        .first()
+        .and_then(Value::as_str)
+        .and_then(|arg| Url::parse(arg).ok())
+    else {
+        return Err(jsonrpc::Error::invalid_params(
+            "Expected a query document URI",
+        ));
+    };
+    if !backend.document_map.contains_key(&query_uri) {
+        warn!("No document found for URI: {query_uri} when expanding query");
+        return Err(jsonrpc::Error::invalid_params(format!(
+            "No document found for URI: {query_uri}"
+        )));
+    }
+    let modules = get_query_modules(&backend.document_map, &query_uri);
+    let uri = Url::parse(&format!("ts-query-ls://expanded{}", query_uri.path()))
+        .map_err(|_| jsonrpc::Error::internal_error())?;
+    let expanded = ExpandedQuery {
+        uri,
+        text: expand_query(&modules),
+    };
+    Ok(Some(
+        serde_json::to_value(expanded).map_err(|_| jsonrpc::Error::internal_error())?,
+    ))
+}
+
+async fn reload_parsers_command<C: LspClient>(
+    backend: &Backend<C>,
+    arguments: &[Value],
+) -> Result<Option<Value>> {
+    let mut parser_names = Vec::new();
+    for argument in arguments {
+        let Some(parser_name) = argument.as_str() else {
+            return Err(jsonrpc::Error::invalid_params(
+                "Expected the names of the parsers to reload",
+            ));
+        };
+        parser_names.push(parser_name.to_owned());
+    }
+    if parser_names.is_empty() {
+        parser_names = backend
+            .language_map
+            .iter()
+            .map(|language| language.key().clone())
+            .collect();
+        parser_names.sort();
+    }
+    let reloaded = reload_parsers(backend, &parser_names).await;
+    Ok(Some(
+        serde_json::to_value(reloaded).map_err(|_| jsonrpc::Error::internal_error())?,
+    ))
+}
+
+fn argument_to_path(argument: &Value) -> Option<PathBuf> {
+    let argument = argument.as_str()?;
+    Url::parse(argument)
+        .ok()
+        .and_then(|uri| uri.to_file_path().ok())
+        .or_else(|| Some(PathBuf::from(argument)))
+}
+
+/// Run the query against the given source text, returning every match in document order.
+pub fn run_query(
+    language: &Language,
+    query_source: &str,
+    source: &str,
+) -> std::result::Result<Vec<QueryMatchResult>, String> {
+    let query = Query::new(language, query_source).map_err(|err| err.to_string())?;
+    let mut parser = language_parser(language)?;
+    let tree = parser
+        .parse(source, None)
+        .ok_or_else(|| String::from("Failed to parse the source file"))?;
+    let rope = Rope::from_str(source);
+    let capture_names = query.capture_names();
+
+    let mut results = Vec::new();
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(&query, tree.root_node(), source.as_bytes());
+    while let Some(match_) = matches.next() {
+        results.push(QueryMatchResult {
+            pattern_index: match_.pattern_index,
+            captures: match_
+                .captures
+                .iter()
+                .map(|capture| QueryCaptureResult {
+                    name: capture_names[capture.index as usize].to_string(),
+                    kind: capture.node.kind().to_string(),
+                    range: capture.node.lsp_range(&rope),
+                    text: capture.node.text(&rope),
+                })
+                .collect(),
+        });
+    }
+    Ok(results)
+}
+
+/// Flatten the given query modules, which are in the order in which they are combined, into a
+/// single query. Each pattern is annotated with its index in the combined query and its source
+/// location; comments are omitted.
+pub fn expand_query(modules: &[(Url, Rope)]) -> String {
+    let mut expanded = String::new();
+    let mut pattern_index = 0;
+    for (uri, rope) in modules {
+        let tree = parse(rope, None);
+        let source = uri
+            .to_file_path()
+            .map_or_else(|()| uri.to_string(), |path| path.display().to_string());
+        let mut cursor = tree.walk();
+        for node in tree.root_node().named_children(&mut cursor) {
+            if node.kind() == "comment" {
+                continue;
+            }
+            if !expanded.is_empty() {
+                expanded.push('\n');
+            }
+            let _ = writeln!(
+                expanded,
+                "; pattern {pattern_index} ({source}:{})\n{}",
+                node.start_position().row + 1,
+                node.text(rope)
+            );
+            pattern_index += 1;
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+    use serde_json::{Value, json};
+    use tower_lsp::lsp_types::{
+        ExecuteCommandParams, WorkDoneProgressParams, request::ExecuteCommand,
+    };
+    use ts_query_ls::ImportOptions;
+
+    use crate::{
+        Options,
+        test_helpers::helpers::{
+            CPP_HIGHLIGHTS_WS_URI, QUERY_TEST_URI, RUST_TEST_URI, TestService, initialize_server,
+        },
+    };
+
+    use super::{EXPAND_QUERY_COMMAND, RELOAD_PARSERS_COMMAND, RUN_QUERY_COMMAND};
+
+    const SAMPLE_PATH: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/example_source_files/sample.rs"
+    );
+
+    #[rstest]
+    #[case(
+        r"(function_item name: (identifier) @function)",
+        json!([
+            {
+                "patternIndex": 0,
+                "captures": [{
+                    "name": "function",
+                    "kind": "identifier",
+                    "range": {
+                        "start": { "line": 0, "character": 3 },
+                        "end": { "line": 0, "character": 7 },
+                    },
+                    "text": "main",
+                }],
+            },
+            {
+                "patternIndex": 0,
+                "captures": [{
+                    "name": "function",
+                    "kind": "identifier",
+                    "range": {
+                        "start": { "line": 4, "character": 3 },
+                        "end": { "line": 4, "character": 9 },
+                    },
+                    "text": "helper",
+                }],
+            },
+        ])
+    )]
+    #[case(
+        r"(let_declaration pattern: (identifier) @variable value: (integer_literal) @number)",
+        json!([
+            {
+                "patternIndex": 0,
+                "captures": [
+                    {
+                        "name": "variable",
+                        "kind": "identifier",
+                        "range": {
+                            "start": { "line": 1, "character": 8 },
+                            "end": { "line": 1, "character": 9 },
+                        },
+                        "text": "x",
+                    },
+                    {
+                        "name": "number",
+                        "kind": "integer_literal",
+                        "range": {
+                            "start": { "line": 1, "character": 12 },
+                            "end": { "line": 1, "character": 14 },
+                        },
+                        "text": "42",
+                    },
+                ],
+            },
+        ])
+    )]
+    #[tokio::test(flavor = "current_thread")]
+    async fn server_run_query_command(#[case] source: &str, #[case] expected: Value) {
+        // Arrange
+        let mut service =
+            initialize_server(&[(RUST_TEST_URI.clone(), source)], &Options::default()).await;
+
+        // Act
+        let result = service
+            .request::<ExecuteCommand>(ExecuteCommandParams {
+                command: RUN_QUERY_COMMAND.to_string(),
+                arguments: vec![json!(RUST_TEST_URI.to_string()), json!(SAMPLE_PATH)],
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            })
+            .await;
+
+        // Assert
+        assert_eq!(result, Some(expected));
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn server_expand_query_command() {
+        // Arrange
+        let source =
+            "; inherits: cpp\n\n; a comment\n(foo) @bar\n\n((baz) @baz\n  (#eq? @baz \"x\"))\n";
+        let mut service =
+            initialize_server(&[(QUERY_TEST_URI.clone(), source)], &Options::default()).await;
+
+        // Act
+        let result = service
+            .request::<ExecuteCommand>(ExecuteCommandParams {
+                command: EXPAND_QUERY_COMMAND.to_string(),
+                arguments: vec![json!(QUERY_TEST_URI.to_string())],
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            })
+            .await;
+
+        // Assert
+        let cpp_path = CPP_HIGHLIGHTS_WS_URI.to_file_path().unwrap();
+        assert_eq!(
+            result,
+            Some(json!({
+                "uri": "ts-query-ls://expanded/tmp/queries/query/test.scm",
+                "text": format!(
+                    "; pattern 0 ({}:3)\n(squid)\n\n\
+                     ; pattern 1 (/tmp/queries/query/test.scm:4)\n(foo) @bar\n\n\
+                     ; pattern 2 (/tmp/queries/query/test.scm:6)\n((baz) @baz\n  (#eq? @baz \"x\"))\n",
+                    cpp_path.display()
+                ),
+            }))
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn server_expand_query_command_parenthesized_import() {
+        // Arrange
+        let options = Options {
+            import_options: ImportOptions {
+                optional_modules: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut service = initialize_server(
+            &[(QUERY_TEST_URI.clone(), "; inherits: (cpp)\n(foo) @bar\n")],
+            &options,
+        )
+        .await;
+
+        // Act
+        let result = service
+            .request::<ExecuteCommand>(ExecuteCommandParams {
+                command: EXPAND_QUERY_COMMAND.to_string(),
+                arguments: vec![json!(QUERY_TEST_URI.to_string())],
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            })
+            .await;
+
+        // Assert
+        assert_eq!(
+            result,
+            Some(json!({
+                "uri": "ts-query-ls://expanded/tmp/queries/query/test.scm",
+                "text": "; pattern 0 (/tmp/queries/query/test.scm:2)\n(foo) @bar\n",
+            }))
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn server_reload_parsers_command() {
+        // Arrange
+        let mut service = initialize_server(
+            &[(RUST_TEST_URI.clone(), "(identifier) @variable")],
+            &Options::default(),
+        )
+        .await;
+        let notification_count = service.inner().client.get_notifications().len();
+
+        // Act
+        let result = service
+            .request::<ExecuteCommand>(ExecuteCommandParams {
+                command: RELOAD_PARSERS_COMMAND.to_string(),
+                arguments: Vec::new(),
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            })
+            .await;
+
+        // Assert
+        assert_eq!(result, Some(json!(["rust"])));
+        assert!(service.inner().language_map.contains_key("rust"));
+        assert_eq!(
+            service.inner().client.get_notifications().len(),
+            notification_count + 1
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn server_reload_parsers_command_keeps_unloadable_parser() {
+        // Arrange
+        let mut service = initialize_server(
+            &[(RUST_TEST_URI.clone(), "(identifier) @variable")],
+            &Options::default(),
+        )
+        .await;
+        let language_data = service.inner().language_map.get("rust").unwrap().clone();
+        service
+            .inner()
+            .language_map
+            .insert(String::from("squid"), language_data);
+
+        // Act
+        let result = service
+            .request::<ExecuteCommand>(ExecuteCommandParams {
+                command: RELOAD_PARSERS_COMMAND.to_string(),
+                arguments: vec![json!("squid")],
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            })
+            .await;
+
+        // Assert
+        assert_eq!(result, Some(json!([])));
+        assert!(service.inner().language_map.contains_key("squid"));
+    }
+}
@@ -1,22 +1,28 @@
-use tower_lsp::lsp_types::{GotoDefinitionParams, GotoDefinitionResponse, Location, Range};
+use std::{fs, path::Path};
+
+use regex::{Match, Regex};
+use tower_lsp::lsp_types::{
+    GotoDefinitionParams, GotoDefinitionResponse, Location, Position, Range, Url,
+};
 use tracing::{info, warn};
 use tree_sitter::QueryCursor;
 
 use crate::{
     Backend, LspClient,
     util::{
-        CAPTURES_QUERY, NodeUtil, PosUtil, TextProviderRope, get_current_capture_node,
-        get_imported_module_under_cursor, get_references,
+        CAPTURES_QUERY, NODE_REFERENCES_QUERY, NodeUtil, PosUtil, TextProviderRope, capture_at_pos,
+        get_current_capture_node, get_imported_module_under_cursor, get_references,
+        grammar_rule_name,
     },
 };
 
-pub fn goto_definition<C: LspClient>(
+pub async fn goto_definition<C: LspClient>(
     backend: &Backend<C>,
     params: &GotoDefinitionParams,
 ) -> Option<GotoDefinitionResponse> {
     info!("ts_query_ls goto_definition: {params:?}");
     let uri = &params.text_document_position_params.text_document.uri;
-    let Some(doc) = backend.document_map.get(uri) else {
+    let Some(doc) = backend.document_map.get(uri).map(|doc| doc.clone()) else {
         warn!("No document found for URI: {uri} when handling goto_definition");
         return None;
     };
@@ -34,7 +40,24 @@ pub fn goto_definition<C: LspClient>(
         });
     }
 
-    let current_node = get_current_capture_node(tree.root_node(), cur_pos.to_ts_point(rope))?;
+    let Some(current_node) = get_current_capture_node(tree.root_node(), cur_pos.to_ts_point(rope))
+    else {
+        // Jump to the grammar rule which defines the node kind under the cursor
+        let capture = capture_at_pos(
+            tree,
+            rope,
+            &NODE_REFERENCES_QUERY,
+            cur_pos.to_ts_point(rope),
+        )
+        .filter(|capture| {
+            NODE_REFERENCES_QUERY.capture_names()[capture.index as usize] == "node"
+        })?;
+        let options = backend.options.read().await;
+        let directory = options
+            .grammar_source_directories
+            .get(doc.language_name.as_ref()?)?;
+        return find_grammar_rule(Path::new(directory), &capture.node.text(rope)).map(Into::into);
+    };
 
     let query = &CAPTURES_QUERY;
     let mut cursor = QueryCursor::new();
@@ -58,8 +81,58 @@ pub fn goto_definition<C: LspClient>(
     Some(GotoDefinitionResponse::Array(defs))
 }
 
+/// Find the definition of the rule with the given name in the grammar's `grammar.js`, falling
+/// back to its `src/grammar.json`.
+fn find_grammar_rule(directory: &Path, name: &str) -> Option<Location> {
+    let json_regex = Regex::new(&format!(r#"^\s*"({})"\s*:\s*\{{"#, regex::escape(name))).ok()?;
+
+    let grammar_js = directory.join("grammar.js");
+    if let Some(location) = find_line(&grammar_js, 0, |line| {
+        grammar_rule_name(line).filter(|rule| rule.as_str() == name)
+    }) {
+        return Some(location);
+    }
+    let grammar_json = directory.join("src").join("grammar.json");
+    let contents = fs::read_to_string(&grammar_json).ok()?;
+    // Skip to the rule definitions, so that e.g. the `word` rule is not confused with the `word`
+    // property
+    let rules_line = contents
+        .lines()
+        .position(|line| line.trim_start().starts_with("\"rules\""))?;
+    find_line(&grammar_json, rules_line, |line| {
+        json_regex.captures(line)?.get(1)
+    })
+}
+
+/// Find the first line (starting from `skip`) of the file in which `find_name` finds a name,
+/// returning the location of that name.
+fn find_line(
+    path: &Path,
+    skip: usize,
+    find_name: impl Fn(&str) -> Option<Match<'_>>,
+) -> Option<Location> {
+    let contents = fs::read_to_string(path).ok()?;
+    let (line, name) = contents
+        .lines()
+        .enumerate()
+        .skip(skip)
+        .find_map(|(i, line)| Some((i, find_name(line)?)))?;
+    let text = contents.lines().nth(line)?;
+    let start = text[..name.start()].encode_utf16().count() as u32;
+    let end = start + name.as_str().encode_utf16().count() as u32;
+    Some(Location {
+        uri: Url::from_file_path(path.canonicalize().ok()?).ok()?,
+        range: Range {
+            start: Position::new(line as u32, start),
+            end: Position::new(line as u32, end),
+        },
+    })
+}
+
 #[cfg(test)]
 mod test {
+    use std::collections::BTreeMap;
+
     use pretty_assertions::assert_eq;
     use rstest::rstest;
     use tower_lsp::lsp_types::{
@@ -68,10 +141,13 @@ mod test {
         request::GotoDefinition,
     };
 
+    use ts_query_ls::ImportOptions;
+
     use crate::{
         Options,
         test_helpers::helpers::{
-            COMPLEX_FILE, SIMPLE_FILE, TEST_URI, TestService, initialize_server,
+            COMPLEX_FILE, CPP_HIGHLIGHTS_WS_URI, QUERY_TEST_URI, SIMPLE_FILE, TEST_URI,
+            TestService, initialize_server,
         },
     };
 
@@ -145,4 +221,135 @@ mod test {
         };
         assert_eq!(expected, refs);
     }
+
+    #[rstest]
+    #[case(Position { line: 0, character: 13 }, Some(CPP_HIGHLIGHTS_WS_URI.clone()))]
+    #[case(Position { line: 1, character: 13 }, None)]
+    #[case(
+        Position { line: 1, character: 18 },
+        Some(Url::from_file_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/test_workspace/queries/other/test.scm"
+        )).unwrap())
+    )]
+    #[case(Position { line: 1, character: 15 }, None)]
+    #[tokio::test(flavor = "current_thread")]
+    async fn goto_definition_import(#[case] position: Position, #[case] uri: Option<Url>) {
+        // Arrange
+        let options = Options {
+            import_options: ImportOptions {
+                allow_spaces: true,
+                multiple_directives: true,
+                optional_modules: true,
+            },
+            ..Default::default()
+        };
+        let source = "; inherits: (cpp)\n; inherits: foo, other\n(identifier) @capture";
+        let mut service = initialize_server(&[(QUERY_TEST_URI.clone(), source)], &options).await;
+
+        // Act
+        let location = service
+            .request::<GotoDefinition>(GotoDefinitionParams {
+                partial_result_params: PartialResultParams {
+                    partial_result_token: None,
+                },
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: QUERY_TEST_URI.clone(),
+                    },
+                    position,
+                },
+            })
+            .await;
+
+        // Assert
+        let expected = uri.map(|uri| {
+            GotoDefinitionResponse::Scalar(Location {
+                uri,
+                range: Range::default(),
+            })
+        });
+        assert_eq!(expected, location);
+    }
+
+    #[rstest]
+    #[case(
+        Position { line: 0, character: 3 },
+        "js",
+        Some(("js/grammar.js", ((8, 4), (8, 23))))
+    )]
+    #[case(
+        Position { line: 0, character: 30 },
+        "js",
+        Some(("js/grammar.js", ((14, 4), (14, 22))))
+    )]
+    #[case(
+        Position { line: 1, character: 1 },
+        "js",
+        Some(("js/grammar.js", ((16, 4), (16, 14))))
+    )]
+    #[case(
+        Position { line: 1, character: 1 },
+        "json",
+        Some(("json/src/grammar.json", ((20, 5), (20, 15))))
+    )]
+    #[case(Position { line: 0, character: 30 }, "json", None)]
+    #[case(Position { line: 0, character: 22 }, "js", None)]
+    #[tokio::test(flavor = "current_thread")]
+    async fn goto_definition_grammar_rule(
+        #[case] position: Position,
+        #[case] grammar_directory: &str,
+        #[case] location: Option<(&str, Coordinate)>,
+    ) {
+        // Arrange
+        let grammar_sources = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/grammar_sources/"
+        );
+        let options = Options {
+            grammar_source_directories: BTreeMap::from([(
+                String::from("cpp"),
+                format!("{grammar_sources}{grammar_directory}"),
+            )]),
+            ..Default::default()
+        };
+        let source = "(function_definition body: (compound_statement)) @fn\n(identifier)";
+        let mut service =
+            initialize_server(&[(CPP_HIGHLIGHTS_WS_URI.clone(), source)], &options).await;
+
+        // Act
+        let definition = service
+            .request::<GotoDefinition>(GotoDefinitionParams {
+                partial_result_params: PartialResultParams {
+                    partial_result_token: None,
+                },
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: CPP_HIGHLIGHTS_WS_URI.clone(),
+                    },
+                    position,
+                },
+            })
+            .await;
+
+        // Assert
+        let expected = location.map(|(path, r)| {
+            GotoDefinitionResponse::Scalar(Location {
+                uri: Url::from_file_path(format!("{grammar_sources}{path}")).unwrap(),
+                range: Range {
+                    start: Position {
+                        line: r.0.0,
+                        character: r.0.1,
+                    },
+                    end: Position {
+                        line: r.1.0,
+                        character: r.1.1,
+                    },
+                },
+            })
+        });
+        assert_eq!(expected, definition);
+    }
 }
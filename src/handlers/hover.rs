@@ -1,18 +1,31 @@
-use std::{collections::HashMap, sync::LazyLock};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+    time::SystemTime,
+};
 
+use dashmap::DashMap;
+use ropey::Rope;
 use tower_lsp::{
     jsonrpc::Result,
-    lsp_types::{Hover, HoverContents, HoverParams, MarkupContent, MarkupKind, Position, Range},
+    lsp_types::{
+        Hover, HoverContents, HoverParams, MarkupContent, MarkupKind, Position, Range, Url,
+    },
 };
 use tracing::warn;
-use tree_sitter::Query;
-use ts_query_ls::{ParameterConstraint, PredicateParameterType};
+use tree_sitter::{Node, Query};
+use ts_query_ls::{CapturePrecedence, Options, ParameterConstraint, PredicateParameterType};
 
 use crate::{
-    Backend, LspClient, QUERY_LANGUAGE, SymbolInfo,
+    Backend, LanguageData, LspClient, QUERY_LANGUAGE, SymbolInfo,
+    shadowing::get_shadowed_patterns,
     util::{
-        FORMAT_IGNORE_REGEX, INHERITS_REGEX, NodeUtil, PosUtil, capture_at_pos,
-        get_imported_module_under_cursor, remove_unnecessary_escapes, uri_to_basename,
+        FORMAT_IGNORE_REGEX, NodeUtil, PosUtil, SUPPRESSION_REGEX, capture_at_pos,
+        get_imported_module_under_cursor, grammar_rule_name, remove_unnecessary_escapes,
+        uri_to_basename,
     },
 };
 
@@ -27,6 +40,13 @@ static HOVER_QUERY: LazyLock<Query> = LazyLock::new(|| {
     .unwrap()
 });
 
+/// The node documentation read from each documentation source, along with the source's
+/// modification time at that point.
+static NODE_DOCUMENTATION_CACHE: LazyLock<DashMap<PathBuf, NodeDocumentation>> =
+    LazyLock::new(DashMap::new);
+
+type NodeDocumentation = (SystemTime, HashMap<String, String>);
+
 /// Create a static hashmap from doc name to doc file (found in "docs/<name>.md")
 macro_rules! include_docs_map {
     ($($name:literal),* $(,)?) => {
@@ -48,6 +68,7 @@ static DOCS: LazyLock<HashMap<&'static str, &'static str>> = include_docs_map!(
     "negation",
     "inherits",
     "format-ignore",
+    "ignore",
 );
 
 pub async fn hover<C: LspClient>(
@@ -69,7 +90,11 @@ pub async fn hover<C: LspClient>(
         .language_name
         .as_ref()
         .and_then(|name| backend.language_map.get(name));
-    let supertypes = language_data.as_ref().map(|ld| &ld.supertype_map);
+
+    let node_documentation = doc
+        .language_name
+        .as_ref()
+        .and_then(|name| options.node_documentation.get(name));
 
     let Some(capture) = capture_at_pos(tree, rope, &HOVER_QUERY, position.to_ts_point(rope)) else {
         return Ok(None);
@@ -91,117 +116,81 @@ pub async fn hover<C: LspClient>(
         }
         "capture" => {
             let options = backend.options.read().await;
-            if let Some(description) = uri_to_basename(uri).and_then(|base| {
+            let description = uri_to_basename(uri).and_then(|base| {
                 options
                     .valid_captures
                     .get(&base)
                     .and_then(|c| c.get(&capture_text[1..].to_string()))
-            }) {
-                let value = format!("## `{capture_text}`\n\n{description}");
-                Some(Hover {
+            });
+            let shadowing = shadowing_hover(
+                backend,
+                uri,
+                position,
+                language_data.as_deref().cloned(),
+                &options,
+            )
+            .await;
+            let value = match (description, shadowing) {
+                (None, None) => return Ok(None),
+                (Some(description), None) => format!("## `{capture_text}`\n\n{description}"),
+                (None, Some(shadowing)) => format!("## `{capture_text}`\n\n{shadowing}"),
+                (Some(description), Some(shadowing)) => {
+                    format!("## `{capture_text}`\n\n{description}\n\n---\n\n{shadowing}")
+                }
+            };
+            Some(Hover {
+                range,
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value,
+                }),
+            })
+        }
+        "identifier.node" => {
+            let symbol = SymbolInfo {
+                label: capture_text,
+                named: true,
+            };
+            let documentation = node_documentation
+                .and_then(|path| get_node_documentation(Path::new(path), &symbol));
+            language_data.and_then(|language_data| {
+                node_hover(
+                    &language_data,
+                    &symbol,
+                    pair_supertype(capture.node, rope),
+                    documentation,
+                )
+                .map(|value| Hover {
                     range,
                     contents: HoverContents::Markup(MarkupContent {
                         kind: MarkupKind::Markdown,
                         value,
                     }),
                 })
-            } else {
-                None
-            }
+            })
         }
-        "identifier.node" => {
-            let sym = SymbolInfo {
-                label: capture_text,
-                named: true,
+        "anonymous" => {
+            let symbol = SymbolInfo {
+                label: remove_unnecessary_escapes(&capture_text[1..capture_text.len() - 1]),
+                named: false,
             };
-            if let Some(subtypes) = supertypes.and_then(|supertypes| supertypes.get(&sym)) {
-                let value = if subtypes.is_empty() {
-                    String::from("Subtypes could not be determined (parser ABI < 15)")
-                } else {
-                    subtypes.iter().fold(
-                        format!("Subtypes of `({})`:\n\n```query", sym.label),
-                        |acc, subtype| format!("{acc}\n{subtype}"),
-                    ) + "\n```"
-                };
-                Some(Hover {
+            let documentation = node_documentation
+                .and_then(|path| get_node_documentation(Path::new(path), &symbol));
+            language_data.and_then(|language_data| {
+                node_hover(
+                    &language_data,
+                    &symbol,
+                    pair_supertype(capture.node, rope),
+                    documentation,
+                )
+                .map(|value| Hover {
                     range,
                     contents: HoverContents::Markup(MarkupContent {
                         kind: MarkupKind::Markdown,
                         value,
                     }),
                 })
-            } else if let Some(language) = language_data.as_ref().map(|ld| &ld.language) {
-                let syms = (0..language.node_kind_count() as u16)
-                    .filter(|&id| {
-                        if !(language.node_kind_is_visible(id)
-                            || language.node_kind_is_supertype(id))
-                            || !language.node_kind_is_named(id)
-                        {
-                            return false;
-                        }
-                        language
-                            .node_kind_for_id(id)
-                            .is_some_and(|kind| kind == sym.label)
-                    })
-                    .collect::<Vec<_>>();
-                if syms.is_empty() {
-                    None
-                } else {
-                    Some(Hover {
-                        contents: HoverContents::Markup(MarkupContent {
-                            kind: MarkupKind::Markdown,
-                            value: format!(
-                                "Symbol IDs: {}",
-                                syms.iter()
-                                    .map(ToString::to_string)
-                                    .collect::<Vec<_>>()
-                                    .join(", ")
-                            ),
-                        }),
-                        range,
-                    })
-                }
-            } else {
-                None
-            }
-        }
-        "anonymous" => {
-            if let Some(language) = language_data.as_ref().map(|ld| &ld.language) {
-                let string_content =
-                    remove_unnecessary_escapes(&capture_text[1..capture_text.len() - 1]);
-                let syms = (0..language.node_kind_count() as u16)
-                    .filter(|&id| {
-                        if !language.node_kind_is_visible(id)
-                            || language.node_kind_is_supertype(id)
-                            || language.node_kind_is_named(id)
-                        {
-                            return false;
-                        }
-                        language
-                            .node_kind_for_id(id)
-                            .is_some_and(|kind| kind == string_content)
-                    })
-                    .collect::<Vec<_>>();
-                if syms.is_empty() {
-                    None
-                } else {
-                    Some(Hover {
-                        contents: HoverContents::Markup(MarkupContent {
-                            kind: MarkupKind::Markdown,
-                            value: format!(
-                                "Symbol IDs: {}",
-                                syms.iter()
-                                    .map(ToString::to_string)
-                                    .collect::<Vec<_>>()
-                                    .join(", ")
-                            ),
-                        }),
-                        range,
-                    })
-                }
-            } else {
-                None
-            }
+            })
         }
         "field" => {
             if let Some(language) = language_data.as_ref().map(|ld| &ld.language) {
@@ -272,11 +261,15 @@ pub async fn hover<C: LspClient>(
             }
         }
         "comment" => {
-            if position.line == 0 && INHERITS_REGEX.is_match(&capture_text) {
+            if doc
+                .imported_uris
+                .iter()
+                .any(|import| import.line == position.line)
+            {
                 if let Some(module) = get_imported_module_under_cursor(&doc, position) {
                     let range = Some(Range::new(
-                        Position::new(0, module.start_col),
-                        Position::new(0, module.end_col),
+                        Position::new(module.line, module.start_col),
+                        Position::new(module.line, module.end_col),
                     ));
                     let hover_content = if let Some(import_doc) = module
                         .uri
@@ -304,40 +297,282 @@ pub async fn hover<C: LspClient>(
                     }),
                 }));
             }
-            if FORMAT_IGNORE_REGEX.is_match(&capture_text) {
-                Some(Hover {
-                    range,
-                    contents: HoverContents::Markup(MarkupContent {
-                        kind: MarkupKind::Markdown,
-                        value: (*DOCS.get("format-ignore").unwrap()).to_string(),
-                    }),
-                })
+            let doc_name = if FORMAT_IGNORE_REGEX.is_match(&capture_text) {
+                "format-ignore"
+            } else if SUPPRESSION_REGEX.is_match(&capture_text) {
+                "ignore"
             } else {
-                None
-            }
+                return Ok(None);
+            };
+            Some(Hover {
+                range,
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: (*DOCS.get(doc_name).unwrap()).to_string(),
+                }),
+            })
         }
         _ => None,
     })
 }
 
+/// Describe the patterns shadowing the pattern at the given position, if it is shadowed.
+async fn shadowing_hover<C: LspClient>(
+    backend: &Backend<C>,
+    uri: &Url,
+    position: Position,
+    language_data: Option<Arc<LanguageData>>,
+    options: &Options,
+) -> Option<String> {
+    let precedence = options.diagnostic_options.capture_precedence?;
+    let order = match precedence {
+        CapturePrecedence::FirstMatch => "an earlier",
+        CapturePrecedence::LastMatch => "a later",
+    };
+    let corpus_directory = language_data
+        .as_ref()
+        .and_then(|language_data| options.corpus_directories.get(&language_data.name))
+        .map(PathBuf::from);
+    let shadowed_patterns = get_shadowed_patterns(
+        &backend.document_map,
+        uri,
+        language_data,
+        corpus_directory,
+        precedence,
+    )
+    .await;
+    let shadowed = shadowed_patterns
+        .iter()
+        .find(|shadowed| shadowed.range.start <= position && position <= shadowed.range.end)?;
+    let mut value = format!("This pattern is shadowed by {order} pattern:\n");
+    for location in &shadowed.shadowed_by {
+        let path = location.uri.to_file_path().map_or_else(
+            |()| location.uri.to_string(),
+            |path| path.display().to_string(),
+        );
+        let _ = write!(value, "\n- `{path}:{}`", location.range.start.line + 1);
+    }
+    Some(value)
+}
+
+/// Get the supertype of the `supertype/subtype` pair whose subtype is the given node, if any.
+fn pair_supertype(node: Node, rope: &Rope) -> Option<SymbolInfo> {
+    let supertype = node.parent()?.child_by_field_name("supertype")?;
+    (supertype.id() != node.id()).then(|| SymbolInfo {
+        label: supertype.text(rope),
+        named: true,
+    })
+}
+
+/// Describe a grammar node: its documentation, along with the full tree of subtypes for supertypes,
+/// or whether each symbol with its name is named, visible or an alias otherwise. For the subtype of
+/// a `supertype/subtype` pair, the chain of supertypes leading to it is shown as well.
+fn node_hover(
+    language_data: &LanguageData,
+    symbol: &SymbolInfo,
+    supertype: Option<SymbolInfo>,
+    documentation: Option<String>,
+) -> Option<String> {
+    let language = &language_data.language;
+    let ids = (0..language.node_kind_count() as u16)
+        .filter(|&id| {
+            let is_supertype = language.node_kind_is_supertype(id);
+            (language.node_kind_is_visible(id) || is_supertype)
+                && (language.node_kind_is_named(id) || is_supertype) == symbol.named
+                && language
+                    .node_kind_for_id(id)
+                    .is_some_and(|kind| kind == symbol.label)
+        })
+        .collect::<Vec<_>>();
+    if ids.is_empty() {
+        return None;
+    }
+
+    let supertype_map = &language_data.supertype_map;
+    let subtype_path = supertype
+        .and_then(|supertype| subtype_path(supertype_map, &supertype, symbol, Vec::new()))
+        .map(|path| {
+            format!(
+                "\nSubtype path: {}\n",
+                path.iter()
+                    .map(|symbol| format!("`{symbol}`"))
+                    .collect::<Vec<_>>()
+                    .join(" → ")
+            )
+        });
+
+    if let Some(subtypes) = supertype_map.get(symbol) {
+        let mut value =
+            documentation.map_or_else(String::new, |documentation| documentation + "\n\n");
+        if subtypes.is_empty() {
+            value += "Subtypes could not be determined (parser ABI < 15)\n";
+        } else {
+            let _ = write!(value, "Subtypes of `{symbol}`:\n\n```query\n");
+            write_subtype_tree(&mut value, supertype_map, symbol, &mut vec![symbol]);
+            value += "```\n";
+        }
+        value += subtype_path.as_deref().unwrap_or_default();
+        return Some(value.trim_end().to_owned());
+    }
+
+    let mut value = format!("## `{symbol}`\n\n");
+    if let Some(documentation) = documentation {
+        value += &documentation;
+        value += "\n\n";
+    }
+    for id in ids {
+        let mut properties = vec![
+            if symbol.named { "named" } else { "anonymous" },
+            if language.node_kind_is_visible(id) {
+                "visible"
+            } else {
+                "hidden"
+            },
+        ];
+        if language_data.alias_ids.contains(&id) {
+            properties.push("alias");
+        }
+        let _ = writeln!(value, "- Symbol ID {id}: {}", properties.join(", "));
+    }
+    value += subtype_path.as_deref().unwrap_or_default();
+    Some(value.trim_end().to_owned())
+}
+
+/// Get the documentation of a node from the given source: either a JSON file mapping node kinds to
+/// markdown, or a `grammar.js` (or the directory containing it), whose rule comments are used.
+fn get_node_documentation(path: &Path, symbol: &SymbolInfo) -> Option<String> {
+    let grammar_js = if path.is_dir() {
+        Some(path.join("grammar.js"))
+    } else if path.extension().is_some_and(|ext| ext == "js") {
+        Some(path.to_path_buf())
+    } else {
+        None
+    };
+    // Only named nodes are defined by rules
+    if grammar_js.is_some() && !symbol.named {
+        return None;
+    }
+    let is_grammar = grammar_js.is_some();
+    let source = grammar_js.unwrap_or_else(|| path.to_path_buf());
+    let modified = fs::metadata(&source)
+        .and_then(|meta| meta.modified())
+        .ok()?;
+    if let Some(cached) = NODE_DOCUMENTATION_CACHE.get(&source)
+        && cached.0 == modified
+    {
+        return cached.1.get(&symbol.label).cloned();
+    }
+
+    let contents = fs::read_to_string(&source).ok()?;
+    let documentation = if is_grammar {
+        grammar_rule_comments(&contents)
+    } else {
+        serde_json::from_str::<HashMap<String, String>>(&contents).unwrap_or_else(|err| {
+            warn!("Failed to parse {}: {err}", source.display());
+            HashMap::new()
+        })
+    };
+    let label_documentation = documentation.get(&symbol.label).cloned();
+    NODE_DOCUMENTATION_CACHE.insert(source, (modified, documentation));
+    label_documentation
+}
+
+/// Collect the comments (line or block) directly preceding each rule of a `grammar.js`, keyed by
+/// rule name.
+fn grammar_rule_comments(contents: &str) -> HashMap<String, String> {
+    let mut comments = HashMap::new();
+    let mut comment = Vec::new();
+    let mut in_block = false;
+    // Skip to the rule definitions, so that e.g. the `word` property is not mistaken for a rule
+    for line in contents
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("rules"))
+    {
+        let trimmed = line.trim();
+        if in_block || trimmed.starts_with("/*") {
+            in_block = !trimmed.ends_with("*/");
+            let text = trimmed
+                .trim_end_matches("*/")
+                .trim_start_matches("/*")
+                .trim_start_matches('*');
+            comment.push(text.strip_prefix(' ').unwrap_or(text).trim_end());
+        } else if let Some(text) = trimmed.strip_prefix("//") {
+            comment.push(text.strip_prefix(' ').unwrap_or(text));
+        } else {
+            if let Some(name) = grammar_rule_name(line) {
+                let text = comment.join("\n").trim().to_owned();
+                if !text.is_empty() {
+                    comments.insert(name.as_str().to_owned(), text);
+                }
+            }
+            comment.clear();
+        }
+    }
+    comments
+}
+
+/// Find the chain of supertypes leading from the given supertype to the subtype, inclusive.
+fn subtype_path(
+    supertype_map: &HashMap<SymbolInfo, BTreeSet<SymbolInfo>>,
+    supertype: &SymbolInfo,
+    subtype: &SymbolInfo,
+    mut path: Vec<SymbolInfo>,
+) -> Option<Vec<SymbolInfo>> {
+    if path.contains(supertype) {
+        return None;
+    }
+    path.push(supertype.clone());
+    let subtypes = supertype_map.get(supertype)?;
+    if subtypes.contains(subtype) {
+        path.push(subtype.clone());
+        return Some(path);
+    }
+    subtypes
+        .iter()
+        .find_map(|nested| subtype_path(supertype_map, nested, subtype, path.clone()))
+}
+
+/// Write the subtypes of the given supertype, one per line, with the subtypes of nested supertypes
+/// indented beneath them.
+fn write_subtype_tree<'a>(
+    value: &mut String,
+    supertype_map: &'a HashMap<SymbolInfo, BTreeSet<SymbolInfo>>,
+    supertype: &SymbolInfo,
+    ancestors: &mut Vec<&'a SymbolInfo>,
+) {
+    let Some(subtypes) = supertype_map.get(supertype) else {
+        return;
+    };
+    for subtype in subtypes {
+        let _ = writeln!(value, "{}{subtype}", "  ".repeat(ancestors.len() - 1));
+        if !ancestors.contains(&subtype) {
+            ancestors.push(subtype);
+            write_subtype_tree(value, supertype_map, subtype, ancestors);
+            ancestors.pop();
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::collections::{BTreeMap, HashMap};
 
     use ts_query_ls::{
-        Options, ParameterConstraint, Predicate, PredicateParameter, PredicateParameterArity,
-        PredicateParameterType,
+        CapturePrecedence, DiagnosticOptions, Options, ParameterConstraint, Predicate,
+        PredicateParameter, PredicateParameterArity, PredicateParameterType,
     };
 
     use pretty_assertions::assert_eq;
     use rstest::rstest;
     use tower_lsp::lsp_types::{
         Hover, HoverContents, HoverParams, MarkupContent, MarkupKind, Position, Range,
-        TextDocumentIdentifier, TextDocumentPositionParams, WorkDoneProgressParams,
+        TextDocumentIdentifier, TextDocumentPositionParams, Url, WorkDoneProgressParams,
         request::HoverRequest,
     };
 
-    use crate::test_helpers::helpers::{QUERY_TEST_URI, TestService, initialize_server};
+    use crate::test_helpers::helpers::{
+        QUERY_TEST_URI, RUST_TEST_URI, TestService, initialize_server,
+    };
 
     const SOURCE: &str = r"(ERROR) @error (definition) @node
 
@@ -513,7 +748,7 @@ An error node", BTreeMap::from([(String::from("error"), String::from("An error n
         start: Position::new(0, 0),
         end: Position::new(0, 17)
     },
-    "## Inheriting queries\n\n```query\n; inherits: foo,bar\n```\n\nQueries can inherit other queries if they have an `; inherits:` comment as the\nfirst line of the query file. The language server will then act as though the\ntext of the inherited query files was placed at the top of the document, and\nwill provide diagnostics for the text in those queries as well (calculated with\nthe language information of the parent query). Queries will always inherit\nothers of the same type (e.g. a `highlights.scm` will only import other\n`highlights.scm`, never an `injections.scm`).\n\nNote that the syntax is very sensitive; there must be _exactly one_ space after\nthe `inherits:` keyword, and there must be no spaces in-between module names.\n", BTreeMap::default())]
+    "## Inheriting queries\n\n```query\n; inherits: foo,bar\n```\n\nQueries can inherit other queries if they have an `; inherits:` comment as the\nfirst line of the query file. The language server will then act as though the\ntext of the inherited query files was placed at the top of the document, and\nwill provide diagnostics for the text in those queries as well (calculated with\nthe language information of the parent query). Queries will always inherit\nothers of the same type (e.g. a `highlights.scm` will only import other\n`highlights.scm`, never an `injections.scm`).\n\nBy default, the syntax is very sensitive; there must be _exactly one_ space\nafter the `inherits:` keyword, and there must be no spaces in-between module\nnames. The `import_options` setting can relax this to match other editors'\nconventions: spaces around module names (`; inherits: foo, bar`), several\n`; inherits:` comments at the top of the file, and modules wrapped in\nparentheses (`; inherits: (foo)`), which are only inherited when the query is\nitself inherited (as in Neovim) and are not reported when missing.\n", BTreeMap::default())]
     #[case("
 ;;; inherits: foo", Position { line: 1, character: 2 }, Range {
         start: Position::new(0, 0),
@@ -525,6 +760,14 @@ An error node", BTreeMap::from([(String::from("error"), String::from("An error n
         end: Position::new(0, 17)
     },
     "## `; format-ignore`\n\nThe formatter will ignore nodes that are preceeded by a comment starting with\n`format-ignore`.\n\n```query\n((call_expression\n  function: (identifier) @function.builtin)\n  ; format-ignore\n  (#any-of? @function.builtin\n    \"printf\"   \"printf_s\"\n    \"vprintf\"  \"vprintf_s\"\n    \"scanf\"    \"scanf_s\"\n    \"vscanf\"   \"vscanf_s\"\n    \"wprintf\"  \"wprintf_s\"\n    \"vwprintf\" \"vwprintf_s\"\n    \"wscanf\"   \"wscanf_s\"\n    \"vwscanf\"  \"vwscanf_s\"\n    \"cscanf\"   \"_cscanf\"\n    \"printw\"\n    \"scanw\"))\n```\n", BTreeMap::default())]
+    #[case("(foo) @bar ; ts_query_ls-ignore-file: invalid-node", Position { line: 0, character: 20 }, Range {
+        start: Position::new(0, 11),
+        end: Position::new(0, 50)
+    },
+    include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/docs/ignore.md"
+    )), BTreeMap::default())]
     #[case("; inherits: cpp", Position { line: 0, character: 13 }, Range {
         start: Position::new(0, 12),
         end: Position::new(0, 15)
@@ -538,35 +781,45 @@ An error node", BTreeMap::from([(String::from("error"), String::from("An error n
     #[case("(named_node)", Position { line: 0, character: 4 }, Range {
         start: Position::new(0, 1),
         end: Position::new(0, 11)
-    }, "Symbol IDs: 40", BTreeMap::default())]
+    }, "## `(named_node)`\n\n- Symbol ID 40: named, visible", BTreeMap::default())]
     #[case("(MISSING identifier)", Position { line: 0, character: 12 }, Range {
         start: Position::new(0, 9),
         end: Position::new(0, 19)
-    }, "Symbol IDs: 6, 7", BTreeMap::default())]
+    }, "## `(identifier)`\n\n- Symbol ID 6: named, visible\n- Symbol ID 7: named, visible", BTreeMap::default())]
     #[case("(definition/named_node)", Position { line: 0, character: 12 }, Range {
         start: Position::new(0, 12),
         end: Position::new(0, 22)
-    }, "Symbol IDs: 40", BTreeMap::default())]
+    }, "## `(named_node)`\n\n- Symbol ID 40: named, visible\n\nSubtype path: `(definition)` → `(named_node)`", BTreeMap::default())]
     #[case("\"MISSING\"", Position { line: 0, character: 0 }, Range {
         start: Position::new(0, 0),
         end: Position::new(0, 9)
-    }, "Symbol IDs: 18", BTreeMap::default())]
+    }, r#"## `"MISSING"`
+
+- Symbol ID 18: anonymous, visible"#, BTreeMap::default())]
     #[case(r#""MISSING""#, Position { line: 0, character: 2 }, Range {
         start: Position::new(0, 0),
         end: Position::new(0, 9)
-    }, "Symbol IDs: 18", BTreeMap::default())]
+    }, r#"## `"MISSING"`
+
+- Symbol ID 18: anonymous, visible"#, BTreeMap::default())]
     #[case(r#"(MISSING "MISSING")"#, Position { line: 0, character: 12 }, Range {
         start: Position::new(0, 9),
         end: Position::new(0, 18)
-    }, "Symbol IDs: 18", BTreeMap::default())]
+    }, r#"## `"MISSING"`
+
+- Symbol ID 18: anonymous, visible"#, BTreeMap::default())]
     #[case(r#"(definition/"MISSING")"#, Position { line: 0, character: 14 }, Range {
         start: Position::new(0, 12),
         end: Position::new(0, 21)
-    }, "Symbol IDs: 18", BTreeMap::default())]
+    }, r#"## `"MISSING"`
+
+- Symbol ID 18: anonymous, visible"#, BTreeMap::default())]
     #[case(r#""MIS\SING""#, Position { line: 0, character: 4 }, Range {
         start: Position::new(0, 0),
         end: Position::new(0, 10)
-    }, "Symbol IDs: 18", BTreeMap::default())]
+    }, r#"## `"MISSING"`
+
+- Symbol ID 18: anonymous, visible"#, BTreeMap::default())]
     #[case("(missing_node name: (identifier) @variable !type)", Position { line: 0, character: 15 }, Range {
         start: Position::new(0, 14),
         end: Position::new(0, 18)
@@ -655,4 +908,244 @@ An error node", BTreeMap::from([(String::from("error"), String::from("An error n
         };
         assert_eq!(expected, tokens,);
     }
+
+    #[rstest]
+    #[case(
+        "(_pattern)",
+        Position::new(0, 3),
+        Range::new(Position::new(0, 1), Position::new(0, 9)),
+        r#"Subtypes of `(_pattern)`:
+
+```query
+"_"
+(_literal_pattern)
+  (boolean_literal)
+  (char_literal)
+  (float_literal)
+  (integer_literal)
+  (negative_literal)
+  (raw_string_literal)
+  (string_literal)
+(captured_pattern)
+(const_block)
+(generic_pattern)
+(identifier)
+(macro_invocation)
+(mut_pattern)
+(or_pattern)
+(range_pattern)
+(ref_pattern)
+(reference_pattern)
+(remaining_field_pattern)
+(scoped_identifier)
+(slice_pattern)
+(struct_pattern)
+(tuple_pattern)
+(tuple_struct_pattern)
+```"#
+    )]
+    #[case(
+        "(_pattern/string_literal)",
+        Position::new(0, 12),
+        Range::new(Position::new(0, 10), Position::new(0, 24)),
+        "## `(string_literal)`\n\n- Symbol ID 311: named, visible\n\nSubtype path: `(_pattern)` → `(_literal_pattern)` → `(string_literal)`"
+    )]
+    #[case(
+        "(type_identifier)",
+        Position::new(0, 3),
+        Range::new(Position::new(0, 1), Position::new(0, 16)),
+        "## `(type_identifier)`\n\n- Symbol ID 354: named, visible, alias"
+    )]
+    #[tokio::test(flavor = "current_thread")]
+    async fn hover_grammar_node(
+        #[case] source: &str,
+        #[case] position: Position,
+        #[case] range: Range,
+        #[case] hover_content: &str,
+    ) {
+        // Arrange
+        let mut service =
+            initialize_server(&[(RUST_TEST_URI.clone(), source)], &Options::default()).await;
+
+        // Act
+        let hover = service
+            .request::<HoverRequest>(HoverParams {
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: RUST_TEST_URI.clone(),
+                    },
+                    position,
+                },
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            })
+            .await;
+
+        // Assert
+        assert_eq!(
+            Some(Hover {
+                range: Some(range),
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: String::from(hover_content),
+                }),
+            }),
+            hover
+        );
+    }
+
+    #[rstest]
+    #[case(
+        "rust.json",
+        "(function_item)",
+        Position::new(0, 3),
+        Range::new(Position::new(0, 1), Position::new(0, 14)),
+        "## `(function_item)`\n\nA function definition, e.g. `fn main() {}`.\n\n- Symbol ID 188: named, visible"
+    )]
+    #[case(
+        "rust.json",
+        "(function_item \"fn\")",
+        Position::new(0, 16),
+        Range::new(Position::new(0, 15), Position::new(0, 19)),
+        "## `\"fn\"`\n\nThe keyword introducing a function definition.\n\n- Symbol ID 96: anonymous, visible"
+    )]
+    #[case(
+        "rust.json",
+        "(identifier)",
+        Position::new(0, 3),
+        Range::new(Position::new(0, 1), Position::new(0, 11)),
+        "## `(identifier)`\n\n- Symbol ID 1: named, visible"
+    )]
+    #[case(
+        "grammar.js",
+        "(function_item)",
+        Position::new(0, 3),
+        Range::new(Position::new(0, 1), Position::new(0, 14)),
+        "## `(function_item)`\n\nA function definition, e.g. `fn main() {}`.\n\nThe body is a `block`.\n\n- Symbol ID 188: named, visible"
+    )]
+    #[case(
+        "",
+        "(identifier)",
+        Position::new(0, 3),
+        Range::new(Position::new(0, 1), Position::new(0, 11)),
+        "## `(identifier)`\n\nAn identifier, such as the name of a function.\nRaw identifiers are included.\n\n- Symbol ID 1: named, visible"
+    )]
+    #[case(
+        "",
+        "(block)",
+        Position::new(0, 3),
+        Range::new(Position::new(0, 1), Position::new(0, 6)),
+        "## `(block)`\n\n- Symbol ID 293: named, visible"
+    )]
+    #[tokio::test(flavor = "current_thread")]
+    async fn hover_node_documentation(
+        #[case] documentation: &str,
+        #[case] source: &str,
+        #[case] position: Position,
+        #[case] range: Range,
+        #[case] hover_content: &str,
+    ) {
+        // Arrange
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/node_documentation/"
+        );
+        let mut service = initialize_server(
+            &[(RUST_TEST_URI.clone(), source)],
+            &Options {
+                node_documentation: BTreeMap::from([(
+                    String::from("rust"),
+                    format!("{path}{documentation}"),
+                )]),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        // Act
+        let hover = service
+            .request::<HoverRequest>(HoverParams {
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: RUST_TEST_URI.clone(),
+                    },
+                    position,
+                },
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            })
+            .await;
+
+        // Assert
+        assert_eq!(
+            Some(Hover {
+                range: Some(range),
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: String::from(hover_content),
+                }),
+            }),
+            hover
+        );
+    }
+
+    #[rstest]
+    #[case(
+        Position::new(2, 33),
+        Range::new(Position::new(2, 31), Position::new(2, 40)),
+        BTreeMap::default(),
+        "## `@function`\n\nThis pattern is shadowed by an earlier pattern:\n\n- `/tmp/queries/query/highlights.scm:1`"
+    )]
+    #[case(Position::new(2, 33), Range::new(Position::new(2, 31), Position::new(2, 40)),
+    BTreeMap::from([(String::from("function"), String::from("A function"))]),
+    "## `@function`\n\nA function\n\n---\n\nThis pattern is shadowed by an earlier pattern:\n\n- `/tmp/queries/query/highlights.scm:1`")]
+    #[case(Position::new(0, 15), Range::default(), BTreeMap::default(), "")]
+    #[tokio::test(flavor = "current_thread")]
+    async fn hover_shadowed_capture(
+        #[case] position: Position,
+        #[case] range: Range,
+        #[case] captures: BTreeMap<String, String>,
+        #[case] hover_content: &str,
+    ) {
+        // Arrange
+        let uri = Url::parse("file:///tmp/queries/query/highlights.scm").unwrap();
+        let mut service = initialize_server(
+            &[(
+                uri.clone(),
+                "(identifier) @variable\n\n(named_node name: (identifier) @function)",
+            )],
+            &Options {
+                valid_captures: HashMap::from([(String::from("highlights"), captures)]),
+                diagnostic_options: DiagnosticOptions {
+                    capture_precedence: Some(CapturePrecedence::FirstMatch),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await;
+
+        // Act
+        let hover = service
+            .request::<HoverRequest>(HoverParams {
+                text_document_position_params: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri },
+                    position,
+                },
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            })
+            .await;
+
+        // Assert
+        let expected = if hover_content.is_empty() {
+            None
+        } else {
+            Some(Hover {
+                range: Some(range),
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: String::from(hover_content),
+                }),
+            })
+        };
+        assert_eq!(expected, hover);
+    }
 }
@@ -75,6 +75,7 @@ mod test {
     use crate::{
         Backend, Options, SERVER_CAPABILITIES,
         test_helpers::helpers::{MockClient, TestService},
+        workspace_index::WorkspaceIndex,
     };
 
     #[tokio::test(flavor = "current_thread")]
@@ -87,7 +88,9 @@ mod test {
             language_map: DashMap::default(),
             workspace_paths: Arc::default(),
             dependents: DashMap::default(),
+            workspace_index: WorkspaceIndex::default(),
             options: Arc::default(),
+            client_options: Arc::default(),
         })
         .finish();
         unsafe { env::set_var("HOME", "/home/jdoe") };
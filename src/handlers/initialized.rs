@@ -0,0 +1,147 @@
+use std::path::PathBuf;
+
+use tower_lsp::lsp_types::{
+    DidChangeWatchedFilesRegistrationOptions, FileSystemWatcher, GlobPattern, OneOf, Registration,
+    RegistrationParams, RelativePattern, Unregistration, UnregistrationParams, Url,
+    request::{RegisterCapability, UnregisterCapability},
+};
+use tracing::{error, info};
+
+use crate::{
+    Backend, LspClient,
+    compiler::is_grammar_directory,
+    parser_discovery::{is_recursive_parser_directory, parser_directories},
+    util::get_work_done_token,
+    workspace_index::index_workspace,
+};
+
+pub async fn initialized<C: LspClient>(backend: &Backend<C>) {
+    info!("ts_query_ls initialized notification received");
+    register_file_watchers(backend).await;
+    let token = get_work_done_token(backend, None).await;
+    index_workspace(backend, token).await;
+}
+
+const WATCHED_FILES_REGISTRATION_ID: &str = "ts_query_ls-watched-files";
+const WATCHED_FILES_METHOD: &str = "workspace/didChangeWatchedFiles";
+/// Glob matching the parser objects within a parser install directory.
+const PARSER_OBJECTS_GLOB: &str = "*.{so,dll,dylib,wasm}";
+/// Glob matching the parser sources within a grammar checkout.
+const GRAMMAR_SOURCES_GLOB: &str = "src/**/*.{c,h}";
+
+/// Watch the query files and configuration files of the workspace, as well as the parser objects
+/// in the parser install directories and the parser sources of grammar checkouts, so that the
+/// server stays up to date with changes made outside of the editor.
+async fn register_file_watchers<C: LspClient>(backend: &Backend<C>) {
+    let Some(watched_files) = backend
+        .client_capabilities
+        .read()
+        .await
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.did_change_watched_files)
+        .filter(|capability| capability.dynamic_registration.unwrap_or_default())
+    else {
+        return;
+    };
+    let relative_pattern_support = watched_files.relative_pattern_support.unwrap_or_default();
+    let mut watchers = vec![
+        FileSystemWatcher {
+            glob_pattern: GlobPattern::String(String::from("**/*.scm")),
+            kind: None,
+        },
+        FileSystemWatcher {
+            glob_pattern: GlobPattern::String(String::from("**/.tsqueryrc.json")),
+            kind: None,
+        },
+    ];
+    let options = backend.options.read().await;
+    let parser_directories = parser_directories(&options);
+    let grammar_directories: Vec<_> = options
+        .grammar_source_directories
+        .values()
+        .map(PathBuf::from)
+        .chain(parser_directories.iter().cloned())
+        .filter(|directory| is_grammar_directory(directory))
+        .collect();
+    // Parsers may be nested within the directories which are searched recursively
+    let parser_globs: Vec<_> = parser_directories
+        .into_iter()
+        .map(|directory| {
+            let glob = if is_recursive_parser_directory(&directory, &options) {
+                format!("**/{PARSER_OBJECTS_GLOB}")
+            } else {
+                String::from(PARSER_OBJECTS_GLOB)
+            };
+            (directory, glob)
+        })
+        .collect();
+    drop(options);
+    for (directory, glob) in parser_globs
+        .iter()
+        .map(|(directory, glob)| (directory, glob.as_str()))
+        .chain(
+            grammar_directories
+                .iter()
+                .map(|directory| (directory, GRAMMAR_SOURCES_GLOB)),
+        )
+    {
+        let glob_pattern = match Url::from_directory_path(directory) {
+            Ok(base_uri) if relative_pattern_support => GlobPattern::Relative(RelativePattern {
+                base_uri: OneOf::Right(base_uri),
+                pattern: glob.to_owned(),
+            }),
+            _ => GlobPattern::String(format!(
+                "{}/{glob}",
+                directory.to_string_lossy().trim_end_matches(['/', '\\'])
+            )),
+        };
+        watchers.push(FileSystemWatcher {
+            glob_pattern,
+            kind: None,
+        });
+    }
+    let register_options = DidChangeWatchedFilesRegistrationOptions { watchers };
+    if let Err(error) = backend
+        .client
+        .send_request::<RegisterCapability>(RegistrationParams {
+            registrations: vec![Registration {
+                id: String::from(WATCHED_FILES_REGISTRATION_ID),
+                method: String::from(WATCHED_FILES_METHOD),
+                register_options: serde_json::to_value(register_options).ok(),
+            }],
+        })
+        .await
+    {
+        error!("Failed to register file watchers: {error}");
+    }
+}
+
+/// Replace the registered file watchers, e.g. after the parser install directories changed.
+pub async fn reregister_file_watchers<C: LspClient>(backend: &Backend<C>) {
+    let dynamic_registration = backend
+        .client_capabilities
+        .read()
+        .await
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.did_change_watched_files)
+        .and_then(|capability| capability.dynamic_registration)
+        .unwrap_or_default();
+    if !dynamic_registration {
+        return;
+    }
+    if let Err(error) = backend
+        .client
+        .send_request::<UnregisterCapability>(UnregistrationParams {
+            unregisterations: vec![Unregistration {
+                id: String::from(WATCHED_FILES_REGISTRATION_ID),
+                method: String::from(WATCHED_FILES_METHOD),
+            }],
+        })
+        .await
+    {
+        error!("Failed to unregister file watchers: {error}");
+    }
+    register_file_watchers(backend).await;
+}
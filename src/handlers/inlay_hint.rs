@@ -0,0 +1,199 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+    sync::{Arc, LazyLock},
+};
+
+use dashmap::DashMap;
+use tower_lsp::{
+    jsonrpc::Result,
+    lsp_types::{InlayHint, InlayHintLabel, InlayHintParams, Url},
+};
+use tracing::warn;
+use tree_sitter::Query;
+
+use crate::{
+    Backend, LspClient,
+    corpus::{definition_match_counts, load_corpus},
+    util::{NodeUtil, PosUtil},
+};
+
+/// A compiled query, along with the hash of the text and the language it was compiled for.
+type CompiledQuery = (u64, String, Arc<Query>);
+
+/// The query compiled from each document, so that it is not recompiled on every request.
+static COMPILED_QUERIES: LazyLock<DashMap<Url, CompiledQuery>> = LazyLock::new(DashMap::new);
+
+/// Forget the compiled query of a document, e.g. when it is closed.
+pub fn forget_compiled_query(uri: &Url) {
+    COMPILED_QUERIES.remove(uri);
+}
+
+/// Forget the compiled queries of the given language, e.g. when its parser is reloaded.
+pub fn clear_compiled_queries(language_name: &str) {
+    COMPILED_QUERIES.retain(|_, (_, name, _)| name != language_name);
+}
+
+pub async fn inlay_hint<C: LspClient>(
+    backend: &Backend<C>,
+    params: &InlayHintParams,
+) -> Result<Option<Vec<InlayHint>>> {
+    let uri = params.text_document.uri.clone();
+    let Some(doc) = backend.document_map.get(&uri) else {
+        warn!("No document found for URI: {uri} when retrieving inlay hints");
+        return Ok(None);
+    };
+    let Some(language_name) = doc.language_name.clone() else {
+        return Ok(None);
+    };
+    let rope = doc.rope.clone();
+    let tree = doc.tree.clone();
+    drop(doc);
+    let Some(corpus_dir) = backend
+        .options
+        .read()
+        .await
+        .corpus_directories
+        .get(&language_name)
+        .map(PathBuf::from)
+    else {
+        return Ok(None);
+    };
+    let Some(language_data) = backend.language_map.get(&language_name).as_deref().cloned() else {
+        return Ok(None);
+    };
+    let range = params.range;
+
+    let handle = tokio::task::spawn_blocking(move || {
+        // Documents which are not open have no version, so the text itself is compared
+        let mut hasher = DefaultHasher::new();
+        rope.hash(&mut hasher);
+        let text_hash = hasher.finish();
+        let cached_query = COMPILED_QUERIES
+            .get(&uri)
+            .filter(|entry| entry.0 == text_hash && entry.1 == language_name)
+            .map(|entry| entry.2.clone());
+        let query = if let Some(query) = cached_query {
+            query
+        } else {
+            let query = Arc::new(Query::new(&language_data.language, &rope.to_string()).ok()?);
+            COMPILED_QUERIES.insert(uri, (text_hash, language_name.clone(), query.clone()));
+            query
+        };
+        let corpus = load_corpus(&language_name, &language_data.language, &corpus_dir);
+        let start = range.start.to_ts_point(&rope);
+        let end = range.end.to_ts_point(&rope);
+        let hints = definition_match_counts(tree.root_node(), &rope, &query, &corpus)
+            .into_iter()
+            .filter(|(def, _)| def.end_position() >= start && def.start_position() <= end)
+            .map(|(def, count)| InlayHint {
+                position: def.lsp_range(&rope).end,
+                label: InlayHintLabel::String(if count == 1 {
+                    String::from("1 match")
+                } else {
+                    format!("{count} matches")
+                }),
+                kind: None,
+                text_edits: None,
+                tooltip: None,
+                padding_left: Some(true),
+                padding_right: None,
+                data: None,
+            })
+            .collect::<Vec<_>>();
+        Some(hints)
+    });
+
+    Ok(handle.await.ok().flatten().or_else(|| Some(Vec::new())))
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+    use tower_lsp::lsp_types::{
+        InlayHintLabel, InlayHintParams, Position, Range, TextDocumentIdentifier,
+        WorkDoneProgressParams, request::InlayHintRequest,
+    };
+
+    use crate::{
+        Options,
+        test_helpers::helpers::{RUST_TEST_URI, TestService, initialize_server},
+    };
+
+    const CORPUS_DIR: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/example_source_files"
+    );
+
+    #[rstest]
+    #[case(
+        r"(function_item) @function
+(struct_item) @type
+[
+  (integer_literal)
+  (float_literal)
+] @number",
+        Range::new(Position::new(0, 0), Position::new(5, 0)),
+        &[((0, 25), "2 matches"), ((1, 19), "0 matches"), ((5, 9), "1 match")]
+    )]
+    #[case(
+        r"(function_item) @function
+(struct_item) @type
+[
+  (integer_literal)
+  (float_literal)
+] @number",
+        Range::new(Position::new(1, 0), Position::new(1, 5)),
+        &[((1, 19), "0 matches")]
+    )]
+    #[tokio::test(flavor = "current_thread")]
+    async fn server_inlay_hints(
+        #[case] source: &str,
+        #[case] range: Range,
+        #[case] expected: &[((u32, u32), &str)],
+    ) {
+        // Arrange
+        let mut service = initialize_server(
+            &[(RUST_TEST_URI.clone(), source)],
+            &Options {
+                corpus_directories: BTreeMap::from([(
+                    String::from("rust"),
+                    String::from(CORPUS_DIR),
+                )]),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        // Act
+        let hints = service
+            .request::<InlayHintRequest>(InlayHintParams {
+                text_document: TextDocumentIdentifier {
+                    uri: RUST_TEST_URI.clone(),
+                },
+                range,
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            })
+            .await;
+
+        // Assert
+        let actual: Vec<_> = hints
+            .unwrap()
+            .into_iter()
+            .map(|hint| {
+                let InlayHintLabel::String(label) = hint.label else {
+                    panic!("Unexpected label parts");
+                };
+                ((hint.position.line, hint.position.character), label)
+            })
+            .collect();
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(pos, label)| (*pos, (*label).to_string()))
+            .collect();
+        assert_eq!(actual, expected);
+    }
+}
@@ -3,68 +3,120 @@ use tracing::warn;
 use tree_sitter::QueryCursor;
 
 use crate::LspClient;
-use crate::util::{CAPTURES_QUERY, NodeUtil, PosUtil};
+use crate::util::{
+    CAPTURES_QUERY, NODE_REFERENCES_QUERY, NodeUtil, PosUtil, capture_at_pos, uri_to_basename,
+};
+use crate::workspace_index::{PREDICATES_QUERY, ensure_workspace_index};
 use crate::{
     Backend,
-    util::{TextProviderRope, get_current_capture_node, get_references},
+    util::{TextProviderRope, get_current_capture_node, get_node_references, get_references},
 };
 
-pub fn references<C: LspClient>(
+pub async fn references<C: LspClient>(
     backend: &Backend<C>,
     params: &ReferenceParams,
 ) -> Option<Vec<Location>> {
     let uri = &params.text_document_position.text_document.uri;
 
-    let Some(doc) = backend.document_map.get(uri) else {
+    let Some(doc) = backend.document_map.get(uri).map(|doc| doc.clone()) else {
         warn!("No document found for URI: {uri} when handling references");
         return None;
     };
     let rope = &doc.rope;
     let tree = &doc.tree;
     let cur_pos = params.text_document_position.position.to_ts_point(rope);
-    let current_node = get_current_capture_node(tree.root_node(), cur_pos)?;
+    let token = params.work_done_progress_params.work_done_token.clone();
+    let Some(current_node) = get_current_capture_node(tree.root_node(), cur_pos) else {
+        // List the uses of the predicate or directive under the cursor across the workspace
+        if let Some(capture) = capture_at_pos(tree, rope, &PREDICATES_QUERY, cur_pos) {
+            let predicate = capture.node.parent()?;
+            let name = predicate.named_child(0)?.text(rope) + &predicate.named_child(1)?.text(rope);
+            ensure_workspace_index(backend, token).await;
+            return Some(backend.workspace_index.predicate_references(&name));
+        }
+
+        // List the uses of the node kind or field name under the cursor across the workspace
+        let capture = capture_at_pos(tree, rope, &NODE_REFERENCES_QUERY, cur_pos)?;
+        let capture_name = NODE_REFERENCES_QUERY.capture_names()[capture.index as usize];
+        let name = capture.node.text(rope);
+        return Some(
+            get_node_references(backend, uri, &doc, capture_name, &name)
+                .await
+                .into_iter()
+                .flat_map(|references| {
+                    let uri = references.uri;
+                    references.ranges.into_iter().map(move |range| Location {
+                        uri: uri.clone(),
+                        range,
+                    })
+                })
+                .collect(),
+        );
+    };
 
     let include_def = params.context.include_declaration;
     let query = &CAPTURES_QUERY;
     let mut cursor = QueryCursor::new();
     let provider = TextProviderRope(rope);
 
-    Some(
-        get_references(
-            &tree.root_node(),
-            &current_node,
-            query,
-            &mut cursor,
-            &provider,
-            rope,
-        )
-        .filter_map(|node| {
-            if include_def || node.parent().is_some_and(|p| p.kind() == "parameters") {
-                Some(Location {
-                    uri: uri.clone(),
-                    range: node.lsp_range(rope),
-                })
-            } else {
-                None
-            }
-        })
-        .collect(),
+    let mut locations: Vec<_> = get_references(
+        &tree.root_node(),
+        &current_node,
+        query,
+        &mut cursor,
+        &provider,
+        rope,
     )
+    .filter_map(|node| {
+        if include_def || node.parent().is_some_and(|p| p.kind() == "parameters") {
+            Some(Location {
+                uri: uri.clone(),
+                range: node.lsp_range(rope),
+            })
+        } else {
+            None
+        }
+    })
+    .collect();
+
+    // Follow the references within the pattern by the uses of the capture in the other query files
+    // of the same type across the workspace (e.g. every `highlights.scm`). Captures of this file
+    // are scoped to their pattern, and `_`-prefixed captures are private to it.
+    let name = current_node.text(rope);
+    let name = name.trim_start_matches('@');
+    if !name.starts_with('_')
+        && let Some(query_type) = uri_to_basename(uri)
+    {
+        ensure_workspace_index(backend, token).await;
+        locations.extend(
+            backend
+                .workspace_index
+                .capture_references(&query_type, name, include_def)
+                .into_iter()
+                .filter(|location| location.uri != *uri),
+        );
+    }
+
+    Some(locations)
 }
 
 #[cfg(test)]
 mod test {
+    use std::sync::LazyLock;
+
     use pretty_assertions::assert_eq;
     use rstest::rstest;
     use tower_lsp::lsp_types::{
         Location, PartialResultParams, Position, Range, ReferenceContext, ReferenceParams,
-        TextDocumentIdentifier, TextDocumentPositionParams, WorkDoneProgressParams,
+        TextDocumentIdentifier, TextDocumentPositionParams, Url, WorkDoneProgressParams,
         request::References,
     };
 
     use crate::{
         Options,
-        test_helpers::helpers::{COMPLEX_FILE, TEST_URI, TestService, initialize_server},
+        test_helpers::helpers::{
+            COMPLEX_FILE, CPP_HIGHLIGHTS_WS_URI, TEST_URI, TestService, initialize_server,
+        },
     };
 
     type Coordinate = ((u32, u32), (u32, u32));
@@ -94,7 +146,7 @@ mod test {
 function: (identifier) @function)",
         Position { line: 0, character: 1 },
         true,
-        &[]
+        &[((0, 1), (0, 16))]
     )]
     #[case(
         &COMPLEX_FILE,
@@ -163,4 +215,182 @@ function: (identifier) @function)",
         };
         assert_eq!(expected, refs);
     }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn capture_references_across_workspace() {
+        // Arrange
+        let uri = Url::parse("file:///tmp/queries/js/highlights.scm").unwrap();
+        let other_highlights_uri = Url::from_file_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/test_workspace/queries/other/highlights.scm"
+        ))
+        .unwrap();
+        let source = "(identifier) @variable\n(property_identifier) @variable";
+        let mut service = initialize_server(&[(uri.clone(), source)], &Options::default()).await;
+
+        // Act
+        let refs = service
+            .request::<References>(ReferenceParams {
+                context: ReferenceContext {
+                    include_declaration: true,
+                },
+                partial_result_params: PartialResultParams {
+                    partial_result_token: None,
+                },
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                text_document_position: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier { uri: uri.clone() },
+                    position: Position::new(0, 15),
+                },
+            })
+            .await;
+
+        // Assert
+        assert_eq!(
+            refs,
+            Some(vec![
+                Location::new(uri, Range::new(Position::new(0, 13), Position::new(0, 22))),
+                Location::new(
+                    other_highlights_uri,
+                    Range::new(Position::new(5, 2), Position::new(5, 11))
+                ),
+            ])
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn predicate_references() {
+        // Arrange
+        let source = r#"((identifier) @a (#eq? @a "x"))
+((identifier) @b (#eq? @b "y") (#set! foo))"#;
+        let mut service =
+            initialize_server(&[(TEST_URI.clone(), source)], &Options::default()).await;
+
+        // Act
+        let refs = service
+            .request::<References>(ReferenceParams {
+                context: ReferenceContext {
+                    include_declaration: true,
+                },
+                partial_result_params: PartialResultParams {
+                    partial_result_token: None,
+                },
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                text_document_position: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: TEST_URI.clone(),
+                    },
+                    position: Position::new(0, 20),
+                },
+            })
+            .await;
+
+        // Assert
+        assert_eq!(
+            refs,
+            Some(vec![
+                Location::new(
+                    TEST_URI.clone(),
+                    Range::new(Position::new(0, 18), Position::new(0, 22))
+                ),
+                Location::new(
+                    TEST_URI.clone(),
+                    Range::new(Position::new(1, 18), Position::new(1, 22))
+                ),
+            ])
+        );
+    }
+
+    static CPP_FOLDS_WS_URI: LazyLock<Url> = LazyLock::new(|| {
+        Url::from_file_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/test_workspace/queries/cpp/folds.scm"
+        ))
+        .unwrap()
+    });
+    static OTHER_TEST_WS_URI: LazyLock<Url> = LazyLock::new(|| {
+        Url::from_file_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/test_workspace/queries/other/test.scm"
+        ))
+        .unwrap()
+    });
+
+    #[rstest]
+    #[case(
+        Position { line: 1, character: 5 },
+        &[
+            (&CPP_FOLDS_WS_URI, ((0, 1), (0, 20))),
+            (&CPP_HIGHLIGHTS_WS_URI, ((1, 1), (1, 20))),
+            (&CPP_HIGHLIGHTS_WS_URI, ((2, 8), (2, 27))),
+        ]
+    )]
+    #[case(
+        Position { line: 3, character: 3 },
+        &[
+            (&CPP_HIGHLIGHTS_WS_URI, ((3, 1), (3, 11))),
+            (&OTHER_TEST_WS_URI, ((2, 1), (2, 11))),
+        ]
+    )]
+    #[case(
+        Position { line: 2, character: 30 },
+        &[
+            (&CPP_HIGHLIGHTS_WS_URI, ((1, 21), (1, 25))),
+            (&CPP_HIGHLIGHTS_WS_URI, ((2, 29), (2, 33))),
+        ]
+    )]
+    #[tokio::test(flavor = "current_thread")]
+    async fn node_kind_references(
+        #[case] position: Position,
+        #[case] locations: &[(&LazyLock<Url>, Coordinate)],
+    ) {
+        // Arrange
+        let source = r"; inherits: other
+(function_definition name: (identifier) @name)
+(ERROR (function_definition !name))
+(definition) @definition";
+        let mut service = initialize_server(
+            &[(CPP_HIGHLIGHTS_WS_URI.clone(), source)],
+            &Options::default(),
+        )
+        .await;
+
+        // Act
+        let refs = service
+            .request::<References>(ReferenceParams {
+                context: ReferenceContext {
+                    include_declaration: true,
+                },
+                partial_result_params: PartialResultParams {
+                    partial_result_token: None,
+                },
+                work_done_progress_params: WorkDoneProgressParams::default(),
+                text_document_position: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: CPP_HIGHLIGHTS_WS_URI.clone(),
+                    },
+                    position,
+                },
+            })
+            .await;
+
+        // Assert
+        let expected = locations
+            .iter()
+            .map(|(uri, r)| Location {
+                uri: (**uri).clone(),
+                range: Range {
+                    start: Position {
+                        line: r.0.0,
+                        character: r.0.1,
+                    },
+                    end: Position {
+                        line: r.1.0,
+                        character: r.1.1,
+                    },
+                },
+            })
+            .collect();
+        assert_eq!(Some(expected), refs);
+    }
 }
@@ -1,3 +1,6 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
 use tower_lsp::{
     jsonrpc::{self, Result},
     lsp_types::{
@@ -9,31 +12,36 @@ use tracing::warn;
 use tree_sitter::QueryCursor;
 
 use crate::{
-    Backend, LspClient,
+    Backend, DocumentData, LspClient,
     util::{
-        CAPTURES_QUERY, NodeUtil, PosUtil, TextProviderRope, get_current_capture_node,
-        get_references,
+        CAPTURES_QUERY, NODE_REFERENCES_QUERY, NodeUtil, PosUtil, TextProviderRope, capture_at_pos,
+        get_current_capture_node, get_node_references, get_references,
     },
 };
 
 use super::diagnostic::IDENTIFIER_REGEX;
 
-pub fn rename<C: LspClient>(
+/// Valid names of node kinds and fields, as defined by a grammar.
+static NODE_NAME_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap());
+
+/// Node kinds which are built into the query language rather than defined by a grammar.
+const BUILTIN_NODE_KINDS: [&str; 3] = ["ERROR", "MISSING", "_"];
+
+pub async fn rename<C: LspClient>(
     backend: &Backend<C>,
     params: &RenameParams,
 ) -> Result<Option<WorkspaceEdit>> {
     let uri = &params.text_document_position.text_document.uri;
-    let Some(doc) = backend.document_map.get(uri) else {
+    let Some(doc) = backend.document_map.get(uri).map(|doc| doc.clone()) else {
         warn!("No document found for URI: {uri} when handling rename");
         return Ok(None);
     };
     let rope = &doc.rope;
     let tree = &doc.tree;
-    let Some(current_node) = get_current_capture_node(
-        tree.root_node(),
-        params.text_document_position.position.to_ts_point(rope),
-    ) else {
-        return Ok(None);
+    let point = params.text_document_position.position.to_ts_point(rope);
+    let Some(current_node) = get_current_capture_node(tree.root_node(), point) else {
+        return rename_node_kind(backend, params, &doc).await;
     };
     let query = &CAPTURES_QUERY;
     let mut cursor = QueryCursor::new();
@@ -80,20 +88,81 @@ pub fn rename<C: LspClient>(
     }))
 }
 
+/// Rename the node kind or field name under the cursor in every query file of the document's
+/// language, and in every query module they inherit.
+async fn rename_node_kind<C: LspClient>(
+    backend: &Backend<C>,
+    params: &RenameParams,
+    doc: &DocumentData,
+) -> Result<Option<WorkspaceEdit>> {
+    let uri = &params.text_document_position.text_document.uri;
+    let point = params
+        .text_document_position
+        .position
+        .to_ts_point(&doc.rope);
+    let Some(capture) = capture_at_pos(&doc.tree, &doc.rope, &NODE_REFERENCES_QUERY, point) else {
+        return Ok(None);
+    };
+    let capture_name = NODE_REFERENCES_QUERY.capture_names()[capture.index as usize];
+    let old_name = capture.node.text(&doc.rope);
+    let new_name = params.new_name.as_str();
+    if BUILTIN_NODE_KINDS.contains(&old_name.as_str()) {
+        return Err(jsonrpc::Error::invalid_params(format!(
+            "The built-in node \"{old_name}\" cannot be renamed"
+        )));
+    }
+    if !NODE_NAME_REGEX.is_match(new_name) || BUILTIN_NODE_KINDS.contains(&new_name) {
+        return Err(jsonrpc::Error::invalid_params(
+            "New name is not a valid node name",
+        ));
+    }
+
+    let document_edits = get_node_references(backend, uri, doc, capture_name, &old_name)
+        .await
+        .into_iter()
+        .map(|references| TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri: references.uri,
+                version: references.version,
+            },
+            edits: references
+                .ranges
+                .into_iter()
+                .map(|range| {
+                    OneOf::Left(TextEdit {
+                        range,
+                        new_text: new_name.to_owned(),
+                    })
+                })
+                .collect(),
+        })
+        .collect();
+
+    Ok(Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Edits(document_edits)),
+        changes: None,
+        change_annotations: None,
+    }))
+}
+
 #[cfg(test)]
 mod test {
+    use std::sync::LazyLock;
+
     use pretty_assertions::assert_eq;
     use rstest::rstest;
     use tower_lsp::lsp_types::{
         DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier, Position, RenameParams,
-        TextDocumentEdit, TextDocumentIdentifier, TextDocumentPositionParams,
+        TextDocumentEdit, TextDocumentIdentifier, TextDocumentPositionParams, Url,
         WorkDoneProgressParams, WorkspaceEdit, request::Rename,
     };
 
+    use super::rename;
     use crate::{
         Options,
         test_helpers::helpers::{
-            COMPLEX_FILE, SIMPLE_FILE, TEST_URI, TestEdit, TestService, initialize_server,
+            COMPLEX_FILE, CPP_HIGHLIGHTS_WS_URI, SIMPLE_FILE, TEST_URI, TestEdit, TestService,
+            initialize_server,
         },
     };
 
@@ -170,4 +239,145 @@ mod test {
         };
         assert_eq!(expected, rename_edits);
     }
+
+    static CPP_FOLDS_WS_URI: LazyLock<Url> = LazyLock::new(|| {
+        Url::from_file_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/test_workspace/queries/cpp/folds.scm"
+        ))
+        .unwrap()
+    });
+    static OTHER_TEST_WS_URI: LazyLock<Url> = LazyLock::new(|| {
+        Url::from_file_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/test_workspace/queries/other/test.scm"
+        ))
+        .unwrap()
+    });
+
+    #[rstest]
+    #[case(
+        Position { line: 1, character: 5 },
+        "function_item",
+        &[
+            (&CPP_FOLDS_WS_URI, None, vec![TestEdit::new("function_item", (0, 1), (0, 20))]),
+            (&CPP_HIGHLIGHTS_WS_URI, Some(0), vec![
+                TestEdit::new("function_item", (1, 1), (1, 20)),
+                TestEdit::new("function_item", (2, 8), (2, 27)),
+            ]),
+        ]
+    )]
+    #[case(
+        Position { line: 3, character: 3 },
+        "program",
+        &[
+            (&CPP_HIGHLIGHTS_WS_URI, Some(0), vec![TestEdit::new("program", (3, 1), (3, 11))]),
+            (&OTHER_TEST_WS_URI, None, vec![TestEdit::new("program", (2, 1), (2, 11))]),
+        ]
+    )]
+    #[case(
+        Position { line: 2, character: 30 },
+        "declarator",
+        &[
+            (&CPP_HIGHLIGHTS_WS_URI, Some(0), vec![
+                TestEdit::new("declarator", (1, 21), (1, 25)),
+                TestEdit::new("declarator", (2, 29), (2, 33)),
+            ]),
+        ]
+    )]
+    #[tokio::test(flavor = "current_thread")]
+    async fn server_rename_node_kind(
+        #[case] cursor_position: Position,
+        #[case] new_name: &str,
+        #[case] edits: &[(&LazyLock<Url>, Option<i32>, Vec<TestEdit>)],
+    ) {
+        // Arrange
+        let source = r"; inherits: other
+(function_definition name: (identifier) @name)
+(ERROR (function_definition !name))
+(definition) @definition";
+        let mut service = initialize_server(
+            &[(CPP_HIGHLIGHTS_WS_URI.clone(), source)],
+            &Options::default(),
+        )
+        .await;
+
+        // Act
+        let rename_edits = service
+            .request::<Rename>(RenameParams {
+                text_document_position: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: CPP_HIGHLIGHTS_WS_URI.clone(),
+                    },
+                    position: cursor_position,
+                },
+                new_name: new_name.to_string(),
+                work_done_progress_params: WorkDoneProgressParams {
+                    work_done_token: None,
+                },
+            })
+            .await;
+
+        // Assert
+        let expected = WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Edits(
+                edits
+                    .iter()
+                    .map(|(uri, version, edits)| TextDocumentEdit {
+                        text_document: OptionalVersionedTextDocumentIdentifier {
+                            uri: (**uri).clone(),
+                            version: *version,
+                        },
+                        edits: edits.iter().map(|e| OneOf::Left(e.into())).collect(),
+                    })
+                    .collect(),
+            )),
+            ..Default::default()
+        };
+        assert_eq!(Some(expected), rename_edits);
+    }
+
+    #[rstest]
+    #[case(Position { line: 1, character: 5 }, "1function")]
+    #[case(Position { line: 1, character: 5 }, "function.item")]
+    #[case(Position { line: 1, character: 5 }, "function-item")]
+    #[case(Position { line: 1, character: 5 }, "ERROR")]
+    #[case(Position { line: 2, character: 29 }, "@name")]
+    #[case(Position { line: 2, character: 2 }, "error")]
+    #[tokio::test(flavor = "current_thread")]
+    async fn server_rename_node_kind_invalid(
+        #[case] cursor_position: Position,
+        #[case] new_name: &str,
+    ) {
+        // Arrange
+        let source = r"; inherits: other
+(function_definition name: (identifier) @name)
+(ERROR (function_definition !name))";
+        let service = initialize_server(
+            &[(CPP_HIGHLIGHTS_WS_URI.clone(), source)],
+            &Options::default(),
+        )
+        .await;
+
+        // Act
+        let rename_edits = rename(
+            service.inner(),
+            &RenameParams {
+                text_document_position: TextDocumentPositionParams {
+                    text_document: TextDocumentIdentifier {
+                        uri: CPP_HIGHLIGHTS_WS_URI.clone(),
+                    },
+                    position: cursor_position,
+                },
+                new_name: new_name.to_string(),
+                work_done_progress_params: WorkDoneProgressParams {
+                    work_done_token: None,
+                },
+            },
+        )
+        .await;
+
+        // Assert
+        assert!(rename_edits.is_err());
+    }
 }
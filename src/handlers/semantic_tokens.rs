@@ -9,7 +9,7 @@ use tree_sitter::{Query, QueryCursor, StreamingIterator};
 
 use crate::{
     Backend, LspClient, QUERY_LANGUAGE, SymbolInfo,
-    util::{FORMAT_IGNORE_REGEX, INHERITS_REGEX, NodeUtil, PosUtil, TextProviderRope},
+    util::{FORMAT_IGNORE_REGEX, NodeUtil, PosUtil, TextProviderRope, parse_suppression},
 };
 
 static SEM_TOK_QUERY: LazyLock<Query> = LazyLock::new(|| {
@@ -110,8 +110,37 @@ fn get_semantic_tokens<C: LspClient>(
                         prev_col = start_col;
                     }
                 }
-                // Highlight special comments (inherits, format ignore)
+                // Highlight special comments (inherits, format ignore, suppressions)
                 "comment" => {
+                    if let Some(suppression) = parse_suppression(&node_text) {
+                        // Directives and diagnostic codes are ASCII, so we can index them by
+                        // byte count.
+                        let directive = (suppression.directive.start, suppression.directive.len());
+                        let parts = std::iter::once((directive, 3)).chain(
+                            suppression
+                                .codes
+                                .iter()
+                                .map(|(offset, code)| ((*offset, code.len()), 4)),
+                        );
+                        let mut delta_line = delta_line;
+                        let mut delta_start = delta_start;
+                        let mut prev_offset = 0;
+                        for ((offset, length), token_type) in parts {
+                            tokens.push(SemanticToken {
+                                delta_line,
+                                delta_start: delta_start + (offset - prev_offset) as u32,
+                                length: length as u32,
+                                token_type,
+                                token_modifiers_bitset: 0,
+                            });
+                            delta_line = 0;
+                            delta_start = 0;
+                            prev_offset = offset;
+                        }
+                        prev_line = start_row;
+                        prev_col = start_col + prev_offset as u32;
+                        continue;
+                    }
                     if let Some(fmt_ignore) = FORMAT_IGNORE_REGEX
                         .captures(&node_text)
                         .and_then(|c| c.get(1))
@@ -129,44 +158,40 @@ fn get_semantic_tokens<C: LspClient>(
                         prev_col = start_col + offset;
                         continue;
                     }
-                    if start_row != 0 {
+                    let mut modules = doc
+                        .imported_uris
+                        .iter()
+                        .filter(|import| import.line == start_row)
+                        .peekable();
+                    if modules.peek().is_none() {
                         continue;
                     }
-                    let Some(mods) = INHERITS_REGEX.captures(&node_text).and_then(|c| c.get(1))
-                    else {
+                    let Some(offset) = node_text.find("inherits:") else {
                         continue;
                     };
-                    let offset = mods.start() as u32;
-                    let mods = mods.as_str().split(',');
 
                     // Add a token for `inherits:`
                     const INHERITS_LEN: u32 = 9;
-                    let mut delta_start = delta_start + offset - INHERITS_LEN - 1;
+                    let mut start_col = start_col + offset as u32;
                     tokens.push(SemanticToken {
-                        delta_line: 0,
-                        delta_start,
+                        delta_line,
+                        delta_start: delta_start + offset as u32,
                         length: INHERITS_LEN,
                         token_type: 3,
                         token_modifiers_bitset: 0,
                     });
-                    delta_start = INHERITS_LEN + 1;
 
-                    let mut start_col = start_col + offset;
-                    let mut delta_line = delta_line;
-                    for module in mods {
+                    for module in modules {
                         // We assert that modules are valid ASCII characters, so we can index them
                         // by byte count.
-                        let length = module.len() as u32;
                         tokens.push(SemanticToken {
-                            delta_line,
-                            delta_start,
-                            length,
+                            delta_line: 0,
+                            delta_start: module.start_col - start_col,
+                            length: module.end_col - module.start_col,
                             token_type: 2,
                             token_modifiers_bitset: 0,
                         });
-                        start_col += length + 1;
-                        delta_start = length + 1;
-                        delta_line = 0;
+                        start_col = module.start_col;
                     }
                     prev_line = start_row;
                     prev_col = start_col;
@@ -212,6 +237,7 @@ mod test {
 
 ;;;format-ignore
 (foo)
+(definition) @node ; ts_query_ls-ignore: invalid-node,  foo
         ";
         let mut service =
             initialize_server(&[(QUERY_TEST_URI.clone(), source)], &Options::default()).await;
@@ -306,6 +332,35 @@ mod test {
                     token_type: 3,
                     token_modifiers_bitset: 0,
                 },
+                SemanticToken {
+                    delta_line: 2,
+                    delta_start: 1,
+                    length: 10,
+                    token_type: 0,
+                    token_modifiers_bitset: 0,
+                },
+                // ts_query_ls-ignore: invalid-node,  foo
+                SemanticToken {
+                    delta_line: 0,
+                    delta_start: 20,
+                    length: 18,
+                    token_type: 3,
+                    token_modifiers_bitset: 0,
+                },
+                SemanticToken {
+                    delta_line: 0,
+                    delta_start: 20,
+                    length: 12,
+                    token_type: 4,
+                    token_modifiers_bitset: 0,
+                },
+                SemanticToken {
+                    delta_line: 0,
+                    delta_start: 15,
+                    length: 3,
+                    token_type: 4,
+                    token_modifiers_bitset: 0,
+                },
             ],
         }));
         assert_eq!(expected_tokens, actual_tokens);
@@ -1,7 +1,8 @@
 use tracing::info;
 
-use crate::{Backend, LspClient};
+use crate::{Backend, LspClient, util::remove_shared_library_copies};
 
 pub fn shutdown<C: LspClient>(_backend: &Backend<C>) {
     info!("ts_query_ls shutdown");
+    remove_shared_library_copies();
 }
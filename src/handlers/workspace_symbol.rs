@@ -1,130 +1,68 @@
-use std::fs;
-
-use ropey::Rope;
 use tower_lsp::{
     jsonrpc::Result,
-    lsp_types::{
-        Location, ProgressParams, ProgressParamsValue, SymbolInformation, SymbolKind, Url,
-        WorkDoneProgress, WorkDoneProgressBegin, WorkDoneProgressEnd, WorkDoneProgressReport,
-        WorkspaceSymbolParams, notification::Progress,
-    },
+    lsp_types::{Location, SymbolInformation, SymbolKind, WorkspaceSymbolParams},
 };
-use tree_sitter::{QueryCursor, StreamingIterator};
 
 use crate::{
     Backend, LspClient,
-    util::{
-        CAPTURES_QUERY, NodeUtil, TextProviderRope, get_scm_files, get_work_done_token,
-        is_subsequence, parse,
-    },
+    util::{get_work_done_token, is_subsequence},
+    workspace_index::ensure_workspace_index,
 };
 
 pub async fn symbol<C: LspClient>(
     backend: &Backend<C>,
     params: WorkspaceSymbolParams,
 ) -> Result<Option<Vec<SymbolInformation>>> {
-    let mut symbols = Vec::new();
     let query = params.query;
-    let token =
-        get_work_done_token(backend, params.work_done_progress_params.work_done_token).await;
-
-    let dirs = backend
-        .workspace_paths
-        .read()
-        .as_deref()
-        .cloned()
-        .unwrap_or_default();
-
-    let files = get_scm_files(&dirs).collect::<Vec<_>>();
-    let file_count = files.len();
-    let file_count_div_100 = file_count as f64 * 0.01;
-    let mut num_processed_files = 0;
-    let mut progress_percent = 0;
-
-    if let Some(token) = token.clone() {
-        backend
-            .client
-            .send_notification::<Progress>(ProgressParams {
-                token,
-                value: ProgressParamsValue::WorkDone(WorkDoneProgress::Begin(
-                    WorkDoneProgressBegin {
-                        title: "Finding workspace symbols".into(),
-                        percentage: Some(0),
-                        message: Some(format!("0/{file_count} files indexed")),
-                        cancellable: Some(false),
-                    },
-                )),
-            })
-            .await;
+    if !backend.workspace_index.is_built() {
+        let token =
+            get_work_done_token(backend, params.work_done_progress_params.work_done_token).await;
+        ensure_workspace_index(backend, token).await;
     }
 
-    for path in files {
-        let container_name = path
-            .file_name()
-            .and_then(|f| f.to_str())
-            .map(ToString::to_string);
-        if let (Ok(content), Ok(uri)) = (fs::read_to_string(&path), Url::from_file_path(path)) {
-            let rope = &Rope::from_str(&content);
-            let tree = parse(rope, None);
-
-            let provider = &TextProviderRope(rope);
-            let mut cursor = QueryCursor::new();
-            let mut matches = cursor.matches(&CAPTURES_QUERY, tree.root_node(), provider);
-
-            while let Some(match_) = matches.next() {
-                for capture in match_.captures {
-                    let capture_node = capture.node;
-                    let node_text = capture_node.text(rope);
-
-                    if is_subsequence(&query, &node_text) {
-                        symbols.push(SymbolInformation {
-                            name: node_text,
-                            kind: SymbolKind::VARIABLE,
-                            location: Location::new(uri.clone(), capture_node.lsp_range(rope)),
-                            container_name: container_name.clone(),
-                            tags: None,
-                            #[allow(deprecated)]
-                            deprecated: None,
-                        });
-                    }
-                }
-            }
-        }
-        num_processed_files += 1;
-        let percentage = (num_processed_files as f64 / file_count_div_100).floor() as u32;
-        if percentage > progress_percent + 4 {
-            progress_percent = percentage;
-            if let Some(token) = token.clone() {
-                backend
-                    .client
-                    .send_notification::<Progress>(ProgressParams {
-                        token,
-                        value: ProgressParamsValue::WorkDone(WorkDoneProgress::Report(
-                            WorkDoneProgressReport {
-                                percentage: Some(progress_percent),
-                                message: Some(format!(
-                                    "{num_processed_files}/{file_count} files indexed"
-                                )),
-                                cancellable: Some(false),
-                            },
-                        )),
-                    })
-                    .await;
+    let index = &backend.workspace_index;
+    let mut symbols = Vec::new();
+    for uri in index.uris() {
+        let Some(file) = index.get(&uri) else {
+            continue;
+        };
+        let container_name = uri.to_file_path().ok().and_then(|path| {
+            path.file_name()
+                .and_then(|f| f.to_str())
+                .map(ToString::to_string)
+        });
+        let captures = file.captures.iter().map(|capture| {
+            (
+                format!("@{}", capture.name),
+                SymbolKind::VARIABLE,
+                capture.range,
+            )
+        });
+        let node_kinds = file
+            .node_kinds
+            .iter()
+            .map(|node| (format!("({})", node.name), SymbolKind::STRUCT, node.range));
+        let predicates = file.predicates.iter().map(|predicate| {
+            (
+                format!("#{}", predicate.name),
+                SymbolKind::FUNCTION,
+                predicate.range,
+            )
+        });
+        for (name, kind, range) in captures.chain(node_kinds).chain(predicates) {
+            if is_subsequence(&query, &name) {
+                symbols.push(SymbolInformation {
+                    name,
+                    kind,
+                    location: Location::new(uri.clone(), range),
+                    container_name: container_name.clone(),
+                    tags: None,
+                    #[allow(deprecated)]
+                    deprecated: None,
+                });
             }
         }
     }
-
-    if let Some(token) = token.clone() {
-        backend
-            .client
-            .send_notification::<Progress>(ProgressParams {
-                token,
-                value: ProgressParamsValue::WorkDone(WorkDoneProgress::End(WorkDoneProgressEnd {
-                    message: Some(format!("{file_count}/{file_count} files indexed")),
-                })),
-            })
-            .await;
-    }
     Ok(Some(symbols))
 }
 
@@ -142,7 +80,7 @@ mod test {
 
     use crate::{
         Options,
-        test_helpers::helpers::{MockRequest, TestService, initialize_server},
+        test_helpers::helpers::{MockRequest, QUERY_TEST_URI, TestService, initialize_server},
     };
 
     #[tokio::test(flavor = "current_thread")]
@@ -176,63 +114,119 @@ mod test {
             "/tests/fixtures/test_workspace/queries/other/highlights.scm"
         ))
         .unwrap();
+        let cpp_test_uri = Url::from_file_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/test_workspace/queries/cpp/test.scm"
+        ))
+        .unwrap();
+        let other_test_uri = Url::from_file_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/test_workspace/queries/other/test.scm"
+        ))
+        .unwrap();
         #[allow(deprecated)]
+        let symbol = |name: &str, kind: SymbolKind, uri: &Url, range: Range| SymbolInformation {
+            name: name.to_owned(),
+            kind,
+            location: Location::new(uri.clone(), range),
+            deprecated: None,
+            tags: None,
+            container_name: uri
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .map(ToOwned::to_owned),
+        };
         let expected_tokens = Some(WorkspaceSymbolResponse::Flat(vec![
-            SymbolInformation {
-                name: String::from("@fold.region"),
-                kind: SymbolKind::VARIABLE,
-                location: Location {
-                    uri: cpp_folds_uri.clone(),
-                    range: make_range(0, 22, 0, 34),
-                },
-                deprecated: None,
-                tags: None,
-                container_name: Some(String::from("folds.scm")),
-            },
-            SymbolInformation {
-                name: String::from("@fold.imports"),
-                kind: SymbolKind::VARIABLE,
-                location: Location {
-                    uri: cpp_folds_uri,
-                    range: make_range(2, 20, 2, 33),
-                },
-                deprecated: None,
-                tags: None,
-                container_name: Some(String::from("folds.scm")),
-            },
-            SymbolInformation {
-                name: String::from("@variable"),
-                kind: SymbolKind::VARIABLE,
-                location: Location {
-                    uri: other_highlights_uri.clone(),
-                    range: make_range(5, 2, 5, 11),
-                },
-                deprecated: None,
-                tags: None,
-                container_name: Some(String::from("highlights.scm")),
-            },
-            SymbolInformation {
-                name: String::from("@function"),
-                kind: SymbolKind::VARIABLE,
-                location: Location {
-                    uri: other_highlights_uri.clone(),
-                    range: make_range(7, 11, 7, 20),
-                },
-                deprecated: None,
-                tags: None,
-                container_name: Some(String::from("highlights.scm")),
-            },
-            SymbolInformation {
-                name: String::from("@constant"),
-                kind: SymbolKind::VARIABLE,
-                location: Location {
-                    uri: other_highlights_uri,
-                    range: make_range(13, 2, 13, 11),
-                },
-                deprecated: None,
-                tags: None,
-                container_name: Some(String::from("highlights.scm")),
-            },
+            symbol(
+                "@fold.region",
+                SymbolKind::VARIABLE,
+                &cpp_folds_uri,
+                make_range(0, 22, 0, 34),
+            ),
+            symbol(
+                "@fold.imports",
+                SymbolKind::VARIABLE,
+                &cpp_folds_uri,
+                make_range(2, 20, 2, 33),
+            ),
+            symbol(
+                "(function_definition)",
+                SymbolKind::STRUCT,
+                &cpp_folds_uri,
+                make_range(0, 1, 0, 20),
+            ),
+            symbol(
+                "(import_statement)",
+                SymbolKind::STRUCT,
+                &cpp_folds_uri,
+                make_range(2, 1, 2, 17),
+            ),
+            symbol(
+                "(squid)",
+                SymbolKind::STRUCT,
+                &cpp_test_uri,
+                make_range(2, 1, 2, 6),
+            ),
+            symbol(
+                "@variable",
+                SymbolKind::VARIABLE,
+                &other_highlights_uri,
+                make_range(5, 2, 5, 11),
+            ),
+            symbol(
+                "@function",
+                SymbolKind::VARIABLE,
+                &other_highlights_uri,
+                make_range(7, 11, 7, 20),
+            ),
+            symbol(
+                "@constant",
+                SymbolKind::VARIABLE,
+                &other_highlights_uri,
+                make_range(13, 2, 13, 11),
+            ),
+            symbol(
+                "(identifier)",
+                SymbolKind::STRUCT,
+                &other_highlights_uri,
+                make_range(3, 3, 3, 13),
+            ),
+            symbol(
+                "(function)",
+                SymbolKind::STRUCT,
+                &other_highlights_uri,
+                make_range(4, 3, 4, 11),
+            ),
+            symbol(
+                "(function)",
+                SymbolKind::STRUCT,
+                &other_highlights_uri,
+                make_range(7, 1, 7, 9),
+            ),
+            symbol(
+                "(number)",
+                SymbolKind::STRUCT,
+                &other_highlights_uri,
+                make_range(10, 3, 10, 9),
+            ),
+            symbol(
+                "(boolean)",
+                SymbolKind::STRUCT,
+                &other_highlights_uri,
+                make_range(11, 3, 11, 10),
+            ),
+            symbol(
+                "(character)",
+                SymbolKind::STRUCT,
+                &other_highlights_uri,
+                make_range(12, 3, 12, 12),
+            ),
+            symbol(
+                "(definition)",
+                SymbolKind::STRUCT,
+                &other_test_uri,
+                make_range(2, 1, 2, 11),
+            ),
         ]));
         assert_eq!(expected_tokens, actual_tokens);
         assert_eq!(
@@ -262,4 +256,41 @@ mod test {
             })
         ));
     }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn workspace_symbol_predicates() {
+        // Arrange
+        let mut service = initialize_server(
+            &[(
+                QUERY_TEST_URI.clone(),
+                "((identifier) @variable (#eq? @variable \"self\"))",
+            )],
+            &Options::default(),
+        )
+        .await;
+
+        // Act
+        let actual_tokens = service
+            .request::<WorkspaceSymbolRequest>(WorkspaceSymbolParams {
+                query: String::from("#eq"),
+                partial_result_params: PartialResultParams::default(),
+                work_done_progress_params: WorkDoneProgressParams::default(),
+            })
+            .await;
+
+        // Assert
+        #[allow(deprecated)]
+        let expected_tokens = Some(WorkspaceSymbolResponse::Flat(vec![SymbolInformation {
+            name: String::from("#eq?"),
+            kind: SymbolKind::FUNCTION,
+            location: Location::new(
+                QUERY_TEST_URI.clone(),
+                Range::new(Position::new(0, 25), Position::new(0, 29)),
+            ),
+            deprecated: None,
+            tags: None,
+            container_name: Some(String::from("test.scm")),
+        }]));
+        assert_eq!(expected_tokens, actual_tokens);
+    }
 }
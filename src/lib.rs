@@ -108,12 +108,25 @@ where
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub struct Options {
     /// A list of strings representing directories to search for parsers, of the form
-    /// `<lang>.(so|dll|dylib)` or `tree-sitter-<lang>.wasm`.
+    /// `<lang>.(so|dll|dylib)` or `tree-sitter-<lang>.wasm`. A directory may also be a grammar
+    /// checkout named `<lang>` or `tree-sitter-<lang>` containing `src/parser.c`, in which case
+    /// the parser is compiled from source.
     ///
     /// Supports environment variable expansion of the form `${VAR}`.
     #[serde(default, deserialize_with = "deserialize_and_expand")]
     pub parser_install_directories: Vec<String>,
 
+    /// Whether to discover parsers in the directories where editors and the tree-sitter CLI
+    /// install them, i.e. Neovim's `site/parser`, nvim-treesitter's install directory, Helix's
+    /// `runtime/grammars`, Zed's extension grammars, and `~/.cache/tree-sitter/lib`. These are
+    /// searched recursively, after the `parser_install_directories`.
+    #[serde(default)]
+    pub parser_discovery: bool,
+
+    /// Whether to also search the subdirectories of the `parser_install_directories` for parsers.
+    #[serde(default)]
+    pub recursive_parser_search: bool,
+
     /// A map of parser aliases.
     #[serde(default)]
     pub parser_aliases: BTreeMap<String, String>,
@@ -147,9 +160,47 @@ pub struct Options {
     #[serde(default)]
     pub formatting_options: FormattingOptions,
 
+    /// Options related to `; inherits:` import directives
+    #[serde(default)]
+    pub import_options: ImportOptions,
+
     /// An inclusive range of ABI versions supported by your tool. The end of the range must be
     /// greater than or equal to the start.
     pub supported_abi_versions: Option<std::ops::RangeInclusive<u32>>,
+
+    /// A map from language name to a directory of sample source files for that language. Queries
+    /// are run against these files to report how often each pattern matches. Relative directories
+    /// are resolved against the workspace.
+    ///
+    /// Supports environment variable expansion of the form `${VAR}`.
+    #[serde(default, deserialize_with = "deserialize_and_expand_map")]
+    pub corpus_directories: BTreeMap<String, String>,
+
+    /// A map from language name to the directory of its grammar's source, which contains a
+    /// `grammar.js` or `src/grammar.json` file. Used to jump to the grammar rule of a node. If the
+    /// directory contains a generated `src/parser.c` (and optionally `src/scanner.c`), the
+    /// language's parser is compiled from it, and recompiled whenever it changes. The compiled
+    /// parser takes precedence over the language's parser in the parser install directories.
+    ///
+    /// Supports environment variable expansion of the form `${VAR}`.
+    #[serde(default, deserialize_with = "deserialize_and_expand_map")]
+    pub grammar_source_directories: BTreeMap<String, String>,
+
+    /// Whether to load each language's `node-types.json`, which is used to explain invalid pattern
+    /// structures and to only complete the children and fields that are valid within a node. The
+    /// file is searched for next to the parser, as `<lang>.node-types.json` or
+    /// `tree-sitter-<lang>.node-types.json`, and in the grammar source directory as
+    /// `src/node-types.json`.
+    #[serde(default)]
+    pub load_node_types: bool,
+
+    /// A map from language name to the documentation of its nodes, which is shown when hovering
+    /// over them. This is either a JSON file mapping node kinds to markdown, or a `grammar.js` (or
+    /// the directory containing it), in which case the comments preceding each rule are used.
+    ///
+    /// Supports environment variable expansion of the form `${VAR}`.
+    #[serde(default, deserialize_with = "deserialize_and_expand_map")]
+    pub node_documentation: BTreeMap<String, String>,
 }
 
 impl Default for Options {
@@ -161,9 +212,16 @@ impl Default for Options {
             valid_captures: HashMap::default(),
             diagnostic_options: DiagnosticOptions::default(),
             formatting_options: FormattingOptions::default(),
+            import_options: ImportOptions::default(),
             parser_aliases: BTreeMap::default(),
             parser_install_directories: Vec::default(),
+            parser_discovery: false,
+            recursive_parser_search: false,
             supported_abi_versions: Option::default(),
+            corpus_directories: BTreeMap::default(),
+            grammar_source_directories: BTreeMap::default(),
+            load_node_types: false,
+            node_documentation: BTreeMap::default(),
         }
     }
 }
@@ -175,7 +233,7 @@ fn prefixes_schema(gen_: &mut schemars::r#gen::SchemaGenerator) -> schemars::sch
 }
 
 /// Options related to diagnostics
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub struct DiagnosticOptions {
     /// The style for predicate string arguments
@@ -185,6 +243,16 @@ pub struct DiagnosticOptions {
     /// (default `true`)
     #[serde(default = "default_true")]
     pub warn_unused_underscore_captures: bool,
+    /// A map from diagnostic code (e.g. `unused-auxiliary-capture`) to the severity it should be
+    /// reported with, overriding its default severity. Diagnostics which are turned `off` are not
+    /// reported. The `check` and `lint` commands only fail on errors and warnings.
+    #[serde(default)]
+    pub severity: BTreeMap<String, SeverityOverride>,
+    /// Which pattern takes precedence when several patterns of a highlights query (including its
+    /// imported modules) capture the same node. When set, patterns whose captures are all shadowed
+    /// by other patterns are reported.
+    #[serde(default)]
+    pub capture_precedence: Option<CapturePrecedence>,
 }
 
 impl Default for DiagnosticOptions {
@@ -192,10 +260,39 @@ impl Default for DiagnosticOptions {
         Self {
             string_argument_style: StringArgumentStyle::default(),
             warn_unused_underscore_captures: true,
+            severity: BTreeMap::default(),
+            capture_precedence: None,
         }
     }
 }
 
+/// Which pattern takes precedence when several patterns capture the same node
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum CapturePrecedence {
+    /// The earliest pattern takes precedence, as in Helix and `tree-sitter-highlight`
+    FirstMatch,
+    /// The latest pattern takes precedence, as in Neovim
+    LastMatch,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SeverityOverride {
+    /// Report the diagnostic as an error
+    Error,
+    /// Report the diagnostic as a warning
+    Warning,
+    /// Report the diagnostic as information
+    Info,
+    /// Report the diagnostic as a hint
+    Hint,
+    /// Do not report the diagnostic
+    Off,
+}
+
 /// Options related to formatting
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default, Copy)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
@@ -205,6 +302,28 @@ pub struct FormattingOptions {
     pub dot_prefix_predicates: bool,
 }
 
+/// Options related to `; inherits:` import directives
+///
+/// By default, only a directive on the first line of a query is recognized, with exactly one space
+/// after `inherits:` and no spaces between module names.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Default, Copy)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct ImportOptions {
+    /// Whether to allow whitespace after `inherits:` and around the commas separating module
+    /// names, as in Helix's `; inherits: foo, bar`.
+    #[serde(default)]
+    pub allow_spaces: bool,
+    /// Whether to recognize import directives in every comment at the top of a query, rather than
+    /// only on its first line. Modules are imported in the order in which they appear.
+    #[serde(default)]
+    pub multiple_directives: bool,
+    /// Whether to allow module names wrapped in parentheses, as in Neovim's `; inherits: (jsx)`.
+    /// Such modules are only inherited when the query is itself inherited by another query, and
+    /// are not reported when they cannot be found.
+    #[serde(default)]
+    pub optional_modules: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Default, Clone, Copy)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(rename_all = "snake_case")]
@@ -366,6 +485,17 @@ where
     Ok(raw.into_iter().map(|s| expand_env_vars(&s)).collect())
 }
 
+fn deserialize_and_expand_map<'de, D>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw = BTreeMap::<String, String>::deserialize(deserializer)?;
+    Ok(raw
+        .into_iter()
+        .map(|(k, v)| (k, expand_env_vars(&v)))
+        .collect())
+}
+
 fn default_regexes() -> Vec<SerializableRegex> {
     vec![
         LANGUAGE_REGEX_1.clone().into(),
@@ -1,7 +1,15 @@
 use clap::{Parser, Subcommand};
 use cli::{
-    check::check_directories, format::format_directories, lint::lint_directories,
+    check::check_directories,
+    coverage::{CoverageFormat, coverage_directories},
+    expand::expand_query_file,
+    format::format_directories,
+    lint::{LintOptions, lint_directories},
+    migrate::migrate_directories,
+    output::OutputFormat,
     profile::profile_directories,
+    run::run_query_file,
+    test_highlights::test_highlights,
 };
 use core::fmt;
 use std::{
@@ -10,13 +18,15 @@ use std::{
     fs::{self},
     path::{Path, PathBuf},
     str,
-    sync::{Arc, LazyLock, RwLock},
+    sync::{Arc, LazyLock, RwLock, atomic::Ordering},
+    time::SystemTime,
 };
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use ts_query_ls::{FormattingOptions, Options};
 
 use dashmap::DashMap;
 use ropey::Rope;
+use serde_json::Value;
 use tower_lsp::{
     Client, LanguageServer, LspService, Server,
     jsonrpc::Result,
@@ -24,13 +34,14 @@ use tower_lsp::{
         ClientCapabilities, CodeActionKind, CodeActionOptions, CodeActionParams,
         CodeActionProviderCapability, CodeActionResponse, CompletionOptions, CompletionParams,
         CompletionResponse, DiagnosticOptions, DiagnosticServerCapabilities,
-        DidChangeConfigurationParams, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
-        DidOpenTextDocumentParams, DidSaveTextDocumentParams, DocumentDiagnosticParams,
-        DocumentDiagnosticReportResult, DocumentFormattingParams, DocumentHighlight,
-        DocumentHighlightParams, DocumentRangeFormattingParams, DocumentSymbolParams,
-        DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverParams,
-        HoverProviderCapability, InitializeParams, InitializeResult, Location, OneOf,
-        ReferenceParams, RenameParams, SelectionRange, SelectionRangeParams,
+        DidChangeConfigurationParams, DidChangeTextDocumentParams, DidChangeWatchedFilesParams,
+        DidCloseTextDocumentParams, DidOpenTextDocumentParams, DidSaveTextDocumentParams,
+        DocumentDiagnosticParams, DocumentDiagnosticReportResult, DocumentFormattingParams,
+        DocumentHighlight, DocumentHighlightParams, DocumentRangeFormattingParams,
+        DocumentSymbolParams, DocumentSymbolResponse, ExecuteCommandOptions, ExecuteCommandParams,
+        GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverParams, HoverProviderCapability,
+        InitializeParams, InitializeResult, InitializedParams, InlayHint, InlayHintParams,
+        Location, OneOf, ReferenceParams, RenameParams, SelectionRange, SelectionRangeParams,
         SelectionRangeProviderCapability, SemanticTokenModifier, SemanticTokenType,
         SemanticTokensFullOptions, SemanticTokensLegend, SemanticTokensOptions,
         SemanticTokensParams, SemanticTokensRangeParams, SemanticTokensRangeResult,
@@ -42,17 +53,26 @@ use tower_lsp::{
 use tree_sitter::{Language, Tree, wasmtime::Engine};
 
 use handlers::{
-    code_action, completion, diagnostic, did_change, did_change_configuration, did_close, did_open,
-    did_save, document_highlight, document_symbol, formatting, goto_definition, hover, initialize,
+    code_action, completion, diagnostic, did_change, did_change_configuration,
+    did_change_watched_files, did_close, did_open, did_save, document_highlight, document_symbol,
+    execute_command, formatting, goto_definition, hover, initialize, initialized, inlay_hint,
     references, rename, selection_range, semantic_tokens, shutdown, workspace_symbol,
 };
 use logging::LspLogLayer;
+use node_types::NodeTypes;
+use workspace_index::WorkspaceIndex;
 
 mod cli;
+mod compiler;
+mod corpus;
 mod handlers;
 mod logging;
+mod node_types;
+mod parser_discovery;
+mod shadowing;
 mod test_helpers;
 mod util;
+mod workspace_index;
 
 static SERVER_CAPABILITIES: LazyLock<ServerCapabilities> = LazyLock::new(|| ServerCapabilities {
     text_document_sync: Some(TextDocumentSyncCapability::Kind(
@@ -89,6 +109,7 @@ static SERVER_CAPABILITIES: LazyLock<ServerCapabilities> = LazyLock::new(|| Serv
                     SemanticTokenType::VARIABLE,
                     SemanticTokenType::NAMESPACE,
                     SemanticTokenType::KEYWORD,
+                    SemanticTokenType::ENUM_MEMBER,
                 ],
                 token_modifiers: vec![SemanticTokenModifier::DEFAULT_LIBRARY],
             },
@@ -98,6 +119,7 @@ static SERVER_CAPABILITIES: LazyLock<ServerCapabilities> = LazyLock::new(|| Serv
         },
     )),
     hover_provider: Some(HoverProviderCapability::Simple(true)),
+    inlay_hint_provider: Some(OneOf::Left(true)),
     document_symbol_provider: Some(OneOf::Left(true)),
     selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
     workspace_symbol_provider: Some(OneOf::Right(WorkspaceSymbolOptions {
@@ -106,6 +128,13 @@ static SERVER_CAPABILITIES: LazyLock<ServerCapabilities> = LazyLock::new(|| Serv
         },
         resolve_provider: None,
     })),
+    execute_command_provider: Some(ExecuteCommandOptions {
+        commands: execute_command::COMMANDS
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        ..Default::default()
+    }),
     ..Default::default()
 });
 static ENGINE: LazyLock<Engine> = LazyLock::new(Engine::default);
@@ -128,6 +157,8 @@ impl fmt::Display for SymbolInfo {
 
 #[derive(Clone)]
 struct ImportedUri {
+    /// The line of the import directive.
+    line: u32,
     /// The start column, in bytes.
     start_col: u32,
     /// The end column, in bytes.
@@ -136,15 +167,27 @@ struct ImportedUri {
     name: String,
     /// The URI of the associated document, if it exists.
     uri: Option<Url>,
+    /// Whether the module is wrapped in parentheses, i.e. it is only inherited when the importing
+    /// query is itself inherited, and it is not reported when missing.
+    optional: bool,
 }
 
 impl ImportedUri {
-    const fn new(start_col: u32, end_col: u32, name: String, uri: Option<Url>) -> Self {
+    const fn new(
+        line: u32,
+        start_col: u32,
+        end_col: u32,
+        name: String,
+        uri: Option<Url>,
+        optional: bool,
+    ) -> Self {
         Self {
+            line,
             start_col,
             end_col,
             name,
             uri,
+            optional,
         }
     }
 }
@@ -172,7 +215,12 @@ struct LanguageData {
     fields_set: HashSet<String>,
     fields_vec: Vec<String>,
     supertype_map: HashMap<SymbolInfo, BTreeSet<SymbolInfo>>,
+    /// The IDs of the symbols which are only ever produced by an alias.
+    alias_ids: HashSet<u16>,
+    node_types: Option<NodeTypes>,
     language: Language,
+    /// The parser object the language was loaded from, and its modification time at that point.
+    parser_file: Option<(PathBuf, SystemTime)>,
 }
 
 trait LspClient: Send + Sync + 'static {
@@ -219,7 +267,10 @@ struct Backend<C: LspClient> {
     /// A map from URI -> URIs that depend on that URI
     dependents: DashMap<Url, HashSet<Url>>,
     options: Arc<tokio::sync::RwLock<Options>>,
+    /// The configuration sent by the client, which the configuration file is merged with.
+    client_options: Arc<tokio::sync::RwLock<Option<Value>>>,
     workspace_paths: Arc<RwLock<Vec<PathBuf>>>,
+    workspace_index: WorkspaceIndex,
 }
 
 #[tower_lsp::async_trait]
@@ -228,6 +279,10 @@ impl<C: LspClient> LanguageServer for Backend<C> {
         initialize::initialize(self, params).await
     }
 
+    async fn initialized(&self, _: InitializedParams) {
+        initialized::initialized(self).await;
+    }
+
     async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
         did_change_configuration::did_change_configuration(self, params).await;
     }
@@ -240,7 +295,7 @@ impl<C: LspClient> LanguageServer for Backend<C> {
         &self,
         params: GotoDefinitionParams,
     ) -> Result<Option<GotoDefinitionResponse>> {
-        Ok(goto_definition::goto_definition(self, &params))
+        Ok(goto_definition::goto_definition(self, &params).await)
     }
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
@@ -248,19 +303,23 @@ impl<C: LspClient> LanguageServer for Backend<C> {
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
-        did_close::did_close(self, &params);
+        did_close::did_close(self, &params).await;
     }
 
     async fn did_save(&self, params: DidSaveTextDocumentParams) {
-        did_save::did_save(self, params);
+        did_save::did_save(self, params).await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         did_change::did_change(self, params).await;
     }
 
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        did_change_watched_files::did_change_watched_files(self, params).await;
+    }
+
     async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
-        Ok(references::references(self, &params))
+        Ok(references::references(self, &params).await)
     }
 
     async fn document_highlight(
@@ -271,7 +330,7 @@ impl<C: LspClient> LanguageServer for Backend<C> {
     }
 
     async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
-        rename::rename(self, &params)
+        rename::rename(self, &params).await
     }
 
     async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
@@ -307,6 +366,10 @@ impl<C: LspClient> LanguageServer for Backend<C> {
         hover::hover(self, params).await
     }
 
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        inlay_hint::inlay_hint(self, &params).await
+    }
+
     async fn document_symbol(
         &self,
         params: DocumentSymbolParams,
@@ -338,6 +401,10 @@ impl<C: LspClient> LanguageServer for Backend<C> {
     ) -> Result<Option<Vec<SelectionRange>>> {
         Ok(selection_range::selection_range(self, &params))
     }
+
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        execute_command::execute_command(self, &params).await
+    }
 }
 
 #[derive(Parser)]
@@ -365,6 +432,10 @@ enum Commands {
         /// String representing server's JSON configuration.
         #[arg(long)]
         config: Option<String>,
+
+        /// The output format of formatting issues, when checking.
+        #[arg(long, value_enum, default_value_t)]
+        output_format: OutputFormat,
     },
     /// Check the query files in the given directories for errors. This command performs a superset
     /// of the work done by the lint command; it reads the query's language to validate query
@@ -393,6 +464,15 @@ enum Commands {
         /// List of languages to be ignored, e.g. `--ignore c lua ...`.
         #[arg(long, short, value_parser, num_args = 1..)]
         ignore: Vec<String>,
+
+        /// A directory of sample source files. Every file in it is parsed with the query's
+        /// language, and patterns which never produce a match are reported.
+        #[arg(long)]
+        corpus: Option<PathBuf>,
+
+        /// The output format of the diagnostics.
+        #[arg(long, value_enum, default_value_t)]
+        output_format: OutputFormat,
     },
     /// Lint the query files in the given directories for errors. This differs from `check` because
     /// it does not perform a full semantic analysis (e.g. analyzing for impossible patterns), but
@@ -414,6 +494,10 @@ enum Commands {
         /// Apply fixes to diagnostics, when possible.
         #[arg(long, short)]
         fix: bool,
+
+        /// The output format of the diagnostics.
+        #[arg(long, value_enum, default_value_t)]
+        output_format: OutputFormat,
     },
     /// Profile each pattern in the given queries, outputting the time it takes them to compile.
     Profile {
@@ -428,6 +512,118 @@ enum Commands {
         #[arg(long, short)]
         per_file: bool,
     },
+    /// Run the given query against a sample source file, printing each match with its pattern
+    /// index and captured nodes.
+    Run {
+        /// The query file to run.
+        query: PathBuf,
+
+        /// The source file to run the query against.
+        source: PathBuf,
+
+        /// String representing server's JSON configuration.
+        #[arg(long, short)]
+        config: Option<String>,
+
+        /// The name of the query's language. Defaults to the language inferred from the query's
+        /// path.
+        #[arg(long, short)]
+        language: Option<String>,
+    },
+    /// Report which of the configured `valid_captures` are used by each language's queries, along
+    /// with a capture × language matrix, or which of the language's nodes are never referenced.
+    /// Imported query modules are taken into account.
+    Coverage {
+        /// List of directories to search for queries.
+        directories: Vec<PathBuf>,
+
+        /// The workspace directory where imported query modules are searched when `; inherits` is
+        /// used. Defaults to the current directory.
+        #[arg(long, short)]
+        workspace: Option<PathBuf>,
+
+        /// String representing server's JSON configuration.
+        #[arg(long, short)]
+        config: Option<String>,
+
+        /// The output format of the report.
+        #[arg(long, short, value_enum, default_value_t)]
+        format: CoverageFormat,
+
+        /// Instead of captures, report the named node kinds and field names of each language which
+        /// are never referenced by any of its queries of a given type.
+        #[arg(long, short)]
+        nodes: bool,
+    },
+    /// Test the language's highlights query against sample source files containing highlight
+    /// assertion comments, e.g. `// <- @keyword` or `// ^ @variable`. Imported query modules are
+    /// resolved from the workspace.
+    Test {
+        /// List of sample files, or directories of sample files, to test.
+        paths: Vec<PathBuf>,
+
+        /// The name of the language of the sample files.
+        #[arg(long, short)]
+        language: String,
+
+        /// The workspace directory where the highlights query and its imported modules are
+        /// searched. Defaults to the current directory.
+        #[arg(long, short)]
+        workspace: Option<PathBuf>,
+
+        /// String representing server's JSON configuration.
+        #[arg(long, short)]
+        config: Option<String>,
+    },
+    /// Print the fully flattened query file, with the modules imported via `; inherits` inlined in
+    /// the order in which they are combined. Each pattern is annotated with its index and source
+    /// location.
+    Expand {
+        /// The query file to expand.
+        query: PathBuf,
+
+        /// The workspace directory where imported query modules are searched when `; inherits` is
+        /// used. Defaults to the current directory.
+        #[arg(long, short)]
+        workspace: Option<PathBuf>,
+
+        /// String representing server's JSON configuration.
+        #[arg(long, short)]
+        config: Option<String>,
+    },
+    /// Compare two versions of a language's parser object, reporting the node kinds, fields, and
+    /// supertypes which were added or removed, along with every location in the queries of the
+    /// given directories which references a removed symbol.
+    Migrate {
+        /// List of directories to search for queries.
+        directories: Vec<PathBuf>,
+
+        /// The current parser object (a shared library or WASM file).
+        #[arg(long)]
+        from: PathBuf,
+
+        /// The parser object to migrate to.
+        #[arg(long)]
+        to: PathBuf,
+
+        /// The name of the language. Defaults to the name of the `--to` parser object, without
+        /// its `tree-sitter-` prefix.
+        #[arg(long, short)]
+        language: Option<String>,
+
+        /// String representing server's JSON configuration.
+        #[arg(long, short)]
+        config: Option<String>,
+
+        /// A JSON file of confirmed renames, e.g. `{"nodes": {"old_kind": "new_kind"}, "fields":
+        /// {}}`. The queries are rewritten to use the new names.
+        #[arg(long, short)]
+        mapping: Option<PathBuf>,
+
+        /// Write a mapping file template, with suggested renames for each removed symbol.
+        #[arg(long)]
+        write_mapping: Option<PathBuf>,
+    },
 }
 
 /// Return the given config string, or read it from a config file if not given. This function can
@@ -447,11 +643,16 @@ fn get_config_str(config: Option<String>) -> String {
 #[tokio::main]
 async fn main() {
     let args = Arguments::parse();
+    // Parsers are never reloaded by the CLI
+    if args.commands.is_some() {
+        util::COPY_SHARED_LIBRARIES.store(false, Ordering::Relaxed);
+    }
     match args.commands {
         Some(Commands::Format {
             directories,
             check,
             config,
+            output_format,
         }) => {
             let fmt_options = if let Some(config) = config {
                 let Ok(options) = serde_json::from_str::<Options>(&config) else {
@@ -468,7 +669,9 @@ async fn main() {
             } else {
                 FormattingOptions::default()
             };
-            std::process::exit(format_directories(&directories, check, fmt_options).await);
+            std::process::exit(
+                format_directories(&directories, check, fmt_options, output_format).await,
+            );
         }
         Some(Commands::Check {
             directories,
@@ -477,10 +680,22 @@ async fn main() {
             config,
             format,
             fix,
+            corpus,
+            output_format,
         }) => {
             let config_str = get_config_str(config);
+            let dead_patterns = corpus.is_some();
             std::process::exit(
-                check_directories(&directories, ignore, config_str, workspace, format, fix).await,
+                check_directories(
+                    &directories,
+                    ignore,
+                    config_str,
+                    workspace,
+                    format,
+                    corpus,
+                    LintOptions::new(fix, false, dead_patterns, output_format),
+                )
+                .await,
             );
         }
         Some(Commands::Lint {
@@ -488,9 +703,12 @@ async fn main() {
             workspace,
             config,
             fix,
+            output_format,
         }) => {
             let config_str = get_config_str(config);
-            std::process::exit(lint_directories(&directories, config_str, workspace, fix).await)
+            std::process::exit(
+                lint_directories(&directories, config_str, workspace, fix, output_format).await,
+            )
         }
         Some(Commands::Profile {
             directories,
@@ -501,6 +719,68 @@ async fn main() {
             profile_directories(&directories, config_str, per_file).await;
             std::process::exit(0);
         }
+        Some(Commands::Run {
+            query,
+            source,
+            config,
+            language,
+        }) => {
+            let config_str = get_config_str(config);
+            std::process::exit(run_query_file(&query, &source, &config_str, language));
+        }
+        Some(Commands::Coverage {
+            directories,
+            workspace,
+            config,
+            format,
+            nodes,
+        }) => {
+            let config_str = get_config_str(config);
+            std::process::exit(coverage_directories(
+                &directories,
+                &config_str,
+                workspace,
+                format,
+                nodes,
+            ));
+        }
+        Some(Commands::Test {
+            paths,
+            language,
+            workspace,
+            config,
+        }) => {
+            let config_str = get_config_str(config);
+            std::process::exit(test_highlights(&paths, &language, &config_str, workspace));
+        }
+        Some(Commands::Expand {
+            query,
+            workspace,
+            config,
+        }) => {
+            let config_str = get_config_str(config);
+            std::process::exit(expand_query_file(&query, workspace, &config_str));
+        }
+        Some(Commands::Migrate {
+            directories,
+            from,
+            to,
+            language,
+            config,
+            mapping,
+            write_mapping,
+        }) => {
+            let config_str = get_config_str(config);
+            std::process::exit(migrate_directories(
+                &directories,
+                &from,
+                &to,
+                language,
+                &config_str,
+                mapping,
+                write_mapping,
+            ));
+        }
         None => {}
     }
 
@@ -520,6 +800,8 @@ async fn main() {
             client_capabilities: Arc::default(),
             dependents: DashMap::default(),
             options,
+            client_options: Arc::default(),
+            workspace_index: WorkspaceIndex::default(),
         }
     })
     .finish();
@@ -0,0 +1,290 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use ropey::Rope;
+use serde::Deserialize;
+use tracing::warn;
+use tree_sitter::Node;
+use ts_query_ls::Options;
+
+use crate::{
+    SymbolInfo,
+    parser_discovery::parser_directories,
+    util::{NodeUtil as _, escape_node_kind, remove_unnecessary_escapes},
+};
+
+#[derive(Deserialize)]
+struct RawNodeType {
+    #[serde(rename = "type")]
+    kind: String,
+    named: bool,
+    #[serde(default)]
+    fields: BTreeMap<String, RawChildren>,
+    children: Option<RawChildren>,
+    #[serde(default)]
+    subtypes: Vec<RawType>,
+}
+
+#[derive(Deserialize)]
+struct RawChildren {
+    types: Vec<RawType>,
+}
+
+#[derive(Deserialize)]
+struct RawType {
+    #[serde(rename = "type")]
+    kind: String,
+    named: bool,
+}
+
+impl From<RawType> for SymbolInfo {
+    fn from(value: RawType) -> Self {
+        Self {
+            label: if value.named {
+                value.kind
+            } else {
+                escape_node_kind(&value.kind)
+            },
+            named: value.named,
+        }
+    }
+}
+
+/// The fields and children which a node may have.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NodeTypeInfo {
+    pub fields: BTreeMap<String, BTreeSet<SymbolInfo>>,
+    pub children: BTreeSet<SymbolInfo>,
+}
+
+/// The contents of a grammar's `node-types.json`, which describes the fields and children of each
+/// node.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NodeTypes {
+    nodes: HashMap<SymbolInfo, NodeTypeInfo>,
+    subtypes: HashMap<SymbolInfo, BTreeSet<SymbolInfo>>,
+    /// Named nodes which never appear as the child of another node, e.g. comments. These can
+    /// appear anywhere.
+    extras: BTreeSet<SymbolInfo>,
+}
+
+impl NodeTypes {
+    pub fn parse(json: &str) -> serde_json::Result<Self> {
+        let raw: Vec<RawNodeType> = serde_json::from_str(json)?;
+        let mut node_types = Self::default();
+        let mut children = HashSet::new();
+        for node in raw {
+            let symbol = SymbolInfo::from(RawType {
+                kind: node.kind,
+                named: node.named,
+            });
+            if !node.subtypes.is_empty() {
+                let subtypes: BTreeSet<_> = node.subtypes.into_iter().map(Into::into).collect();
+                children.extend(subtypes.iter().cloned());
+                node_types.subtypes.insert(symbol.clone(), subtypes);
+            }
+            let info = NodeTypeInfo {
+                fields: node
+                    .fields
+                    .into_iter()
+                    .map(|(name, field)| (name, field.types.into_iter().map(Into::into).collect()))
+                    .collect(),
+                children: node
+                    .children
+                    .map(|children| children.types.into_iter().map(Into::into).collect())
+                    .unwrap_or_default(),
+            };
+            children.extend(info.children.iter().cloned());
+            children.extend(info.fields.values().flatten().cloned());
+            node_types.nodes.insert(symbol, info);
+        }
+        node_types.extras = node_types
+            .nodes
+            .keys()
+            .filter(|symbol| symbol.named && !children.contains(*symbol))
+            .cloned()
+            .collect();
+        Ok(node_types)
+    }
+
+    /// Get the fields and children of the named node with the given kind.
+    pub fn get(&self, kind: &str) -> Option<&NodeTypeInfo> {
+        self.nodes.get(&SymbolInfo {
+            label: kind.to_owned(),
+            named: true,
+        })
+    }
+
+    /// Get the nodes which are valid within the given field of the named node, including the
+    /// subtypes of any supertypes.
+    pub fn field_types(&self, kind: &str, field: &str) -> Option<BTreeSet<SymbolInfo>> {
+        let types = self.get(kind)?.fields.get(field)?;
+        Some(self.expand_supertypes(types))
+    }
+
+    /// Get the nodes which are valid as children of the named node, through a field or otherwise,
+    /// including the subtypes of any supertypes and nodes which may appear anywhere.
+    pub fn child_types(&self, kind: &str) -> Option<BTreeSet<SymbolInfo>> {
+        let info = self.get(kind)?;
+        let mut types =
+            self.expand_supertypes(info.children.iter().chain(info.fields.values().flatten()));
+        types.extend(self.extras.iter().cloned());
+        Some(types)
+    }
+
+    fn expand_supertypes<'a>(
+        &self,
+        types: impl IntoIterator<Item = &'a SymbolInfo>,
+    ) -> BTreeSet<SymbolInfo> {
+        let mut expanded = BTreeSet::new();
+        let mut pending: Vec<_> = types.into_iter().collect();
+        while let Some(symbol) = pending.pop() {
+            if expanded.insert(symbol.clone())
+                && let Some(subtypes) = self.subtypes.get(symbol)
+            {
+                pending.extend(subtypes);
+            }
+        }
+        expanded
+    }
+}
+
+/// Load the `node-types.json` of the given language, if enabled. It is searched for next to the
+/// language's parser object (as `<lang>.node-types.json` or `tree-sitter-<lang>.node-types.json`)
+/// and in the language's grammar source directory (as `src/node-types.json`).
+pub fn get_node_types(name: &str, options: &Options) -> Option<NodeTypes> {
+    if !options.load_node_types {
+        return None;
+    }
+    let parser_paths = parser_directories(options)
+        .into_iter()
+        .flat_map(|directory| {
+            [
+                directory.join(format!("{name}.node-types.json")),
+                directory.join(format!("tree-sitter-{name}.node-types.json")),
+            ]
+        });
+    let grammar_path = options
+        .grammar_source_directories
+        .get(name)
+        .map(|directory| Path::new(directory).join("src").join("node-types.json"));
+    let path = parser_paths
+        .chain(grammar_path)
+        .find(|path| path.is_file())?;
+    let json = fs::read_to_string(&path).ok()?;
+    match NodeTypes::parse(&json) {
+        Ok(node_types) => Some(node_types),
+        Err(err) => {
+            warn!("Failed to parse {}: {err}", path.display());
+            None
+        }
+    }
+}
+
+/// Get the node kind matched by a `named_node` or `anonymous_node` pattern. Returns `None` for
+/// wildcards.
+fn pattern_symbol(node: Node, rope: &Rope) -> Option<SymbolInfo> {
+    let name = node.child_by_field_name("name")?;
+    match node.kind() {
+        "named_node" if name.kind() == "identifier" => Some(SymbolInfo {
+            label: name.text(rope),
+            named: true,
+        }),
+        "anonymous_node" if name.kind() == "string" => {
+            let text = name.text(rope);
+            Some(SymbolInfo {
+                label: remove_unnecessary_escapes(&text[1..text.len() - 1]),
+                named: false,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn display_symbol(symbol: &SymbolInfo) -> String {
+    if symbol.named {
+        symbol.label.clone()
+    } else {
+        format!("\"{}\"", symbol.label)
+    }
+}
+
+fn display_symbols(symbols: &BTreeSet<SymbolInfo>) -> String {
+    symbols
+        .iter()
+        .map(display_symbol)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Explain an impossible pattern structure error, which was reported for the given node, in terms
+/// of the fields and children that are valid on its parent node.
+pub fn explain_structure_error(node: Node, rope: &Rope, node_types: &NodeTypes) -> Option<String> {
+    let (field_definition, child) = match node.kind() {
+        "field_definition" => (Some(node), None),
+        "identifier" => (
+            node.parent().filter(|p| p.kind() == "field_definition"),
+            None,
+        ),
+        "named_node" | "anonymous_node" => (
+            node.parent().filter(|p| p.kind() == "field_definition"),
+            Some(node),
+        ),
+        _ => return None,
+    };
+    let parent = field_definition.or(child)?.parent()?;
+    if parent.kind() != "named_node" {
+        return None;
+    }
+    let parent_kind = pattern_symbol(parent, rope)?.label;
+    let info = node_types.get(&parent_kind)?;
+
+    let Some(field_definition) = field_definition else {
+        let child = pattern_symbol(child?, rope)?;
+        if node_types.child_types(&parent_kind)?.contains(&child) {
+            return None;
+        }
+        let mut children = info.children.clone();
+        children.extend(info.fields.values().flatten().cloned());
+        return Some(if children.is_empty() {
+            format!(
+                "Node \"{}\" is not a valid child of \"{parent_kind}\", which has no children",
+                child.label
+            )
+        } else {
+            format!(
+                "Node \"{}\" is not a valid child of \"{parent_kind}\"; valid children: {}",
+                child.label,
+                display_symbols(&children)
+            )
+        });
+    };
+
+    let field_name = field_definition.child_by_field_name("name")?;
+    let field = field_name.text(rope);
+    let Some(field_types) = info.fields.get(&field) else {
+        return Some(if info.fields.is_empty() {
+            format!("Field \"{field}\" is not valid on \"{parent_kind}\", which has no fields")
+        } else {
+            format!(
+                "Field \"{field}\" is not valid on \"{parent_kind}\"; valid fields: {}",
+                info.fields.keys().cloned().collect::<Vec<_>>().join(", ")
+            )
+        });
+    };
+    let child = pattern_symbol(child.or_else(|| field_name.next_named_sibling())?, rope)?;
+    if node_types
+        .field_types(&parent_kind, &field)?
+        .contains(&child)
+    {
+        return None;
+    }
+    Some(format!(
+        "Node \"{}\" is not valid in field \"{field}\" of \"{parent_kind}\"; valid types: {}",
+        child.label,
+        display_symbols(field_types)
+    ))
+}
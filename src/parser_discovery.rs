@@ -0,0 +1,326 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+    time::SystemTime,
+};
+
+use dashmap::DashMap;
+use ts_query_ls::Options;
+
+/// The maximum depth below a parser directory at which parsers are searched for, when discovering
+/// parsers. This is deep enough for e.g. Zed's `<extension>/grammars/<lang>.wasm` and
+/// nvim-treesitter's `pack/<name>/start/nvim-treesitter/parser/<lang>.so`.
+const MAX_DISCOVERY_DEPTH: usize = 6;
+
+/// The extensions of parser objects.
+const PARSER_EXTENSIONS: [&str; 4] = ["so", "dll", "dylib", "wasm"];
+
+type DiscoveredParsers = (Option<SystemTime>, Arc<Vec<PathBuf>>);
+
+/// The parser objects within the subdirectories of each parser directory, along with the
+/// directory's modification time when it was searched.
+static DISCOVERED_PARSERS: LazyLock<DashMap<PathBuf, DiscoveredParsers>> =
+    LazyLock::new(DashMap::new);
+
+/// Forget the parser objects found in the subdirectories of parser directories, e.g. when parser
+/// objects are created or deleted. Such changes do not update the modification time of the parser
+/// directory itself.
+pub fn clear_discovered_parsers() {
+    DISCOVERED_PARSERS.clear();
+}
+
+/// Get the user's data directory, e.g. `~/.local/share`.
+fn data_home() -> Option<PathBuf> {
+    if let Some(data_home) = env::var_os("XDG_DATA_HOME") {
+        return Some(PathBuf::from(data_home));
+    }
+    if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        home().map(|home| home.join(".local").join("share"))
+    }
+}
+
+/// Get the user's configuration directory, e.g. `~/.config`.
+fn config_home() -> Option<PathBuf> {
+    if let Some(config_home) = env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(config_home));
+    }
+    if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        home().map(|home| home.join(".config"))
+    }
+}
+
+/// Get the user's cache directory, e.g. `~/.cache`.
+pub fn cache_home() -> Option<PathBuf> {
+    if let Some(cache_home) = env::var_os("XDG_CACHE_HOME") {
+        return Some(PathBuf::from(cache_home));
+    }
+    if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        home().map(|home| home.join(".cache"))
+    }
+}
+
+fn home() -> Option<PathBuf> {
+    env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" }).map(PathBuf::from)
+}
+
+/// Get the well-known directories in which editors and the tree-sitter CLI install parsers, which
+/// exist on this machine.
+pub fn well_known_parser_directories() -> Vec<PathBuf> {
+    let mut directories = Vec::new();
+    if let Some(data_home) = data_home() {
+        let nvim_data = data_home.join(if cfg!(windows) { "nvim-data" } else { "nvim" });
+        // Neovim's own parsers, and those installed by nvim-treesitter's main branch
+        directories.push(nvim_data.join("site").join("parser"));
+        // nvim-treesitter's install directory, when installed by a plugin manager
+        directories.push(
+            nvim_data
+                .join("lazy")
+                .join("nvim-treesitter")
+                .join("parser"),
+        );
+        directories.push(nvim_data.join("site").join("pack"));
+    }
+    let zed_data = if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library").join("Application Support").join("Zed"))
+    } else if cfg!(windows) {
+        data_home().map(|data_home| data_home.join("Zed"))
+    } else {
+        data_home().map(|data_home| data_home.join("zed"))
+    };
+    if let Some(zed_data) = zed_data {
+        directories.push(zed_data.join("extensions").join("installed"));
+    }
+    if let Some(runtime) = env::var_os("HELIX_RUNTIME") {
+        directories.push(PathBuf::from(runtime).join("grammars"));
+    }
+    if let Some(config_home) = config_home() {
+        directories.push(config_home.join("helix").join("runtime").join("grammars"));
+    }
+    if let Some(cache_home) = cache_home() {
+        directories.push(cache_home.join("tree-sitter").join("lib"));
+    }
+    directories.retain(|directory| directory.is_dir());
+    directories
+}
+
+/// Get the directories to search for parsers, i.e. the configured parser install directories,
+/// followed by the well-known parser directories if parser discovery is enabled.
+pub fn parser_directories(options: &Options) -> Vec<PathBuf> {
+    let mut directories: Vec<_> = options
+        .parser_install_directories
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+    if options.parser_discovery {
+        for directory in well_known_parser_directories() {
+            if !directories.contains(&directory) {
+                directories.push(directory);
+            }
+        }
+    }
+    directories
+}
+
+/// Whether the subdirectories of the given parser directory are searched for parsers. The
+/// well-known parser directories are always searched recursively, while the configured parser
+/// install directories are only searched recursively if `recursive_parser_search` is enabled.
+pub fn is_recursive_parser_directory(directory: &Path, options: &Options) -> bool {
+    options.recursive_parser_search
+        || !options
+            .parser_install_directories
+            .iter()
+            .any(|install_directory| Path::new(install_directory) == directory)
+}
+
+/// Find the parser objects within the subdirectories of a parser directory. Symbolic links are only
+/// followed if they point within the directory. The results are cached until the directory's
+/// modification time changes, or the cache is cleared.
+fn discover_parsers(directory: &Path) -> Arc<Vec<PathBuf>> {
+    let modified = fs::metadata(directory)
+        .and_then(|meta| meta.modified())
+        .ok();
+    if let Some(cached) = DISCOVERED_PARSERS.get(directory)
+        && cached.0 == modified
+    {
+        return cached.1.clone();
+    }
+    let root = directory
+        .canonicalize()
+        .unwrap_or_else(|_| directory.to_path_buf());
+    let parsers: Arc<Vec<_>> = Arc::new(
+        ignore::WalkBuilder::new(directory)
+            .standard_filters(false)
+            .follow_links(true)
+            .max_depth(Some(MAX_DISCOVERY_DEPTH))
+            .filter_entry(move |entry| {
+                !entry.path_is_symlink()
+                    || entry
+                        .path()
+                        .canonicalize()
+                        .is_ok_and(|path| path.starts_with(&root))
+            })
+            .build()
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                entry.depth() > 1
+                    && entry
+                        .file_type()
+                        .is_some_and(|file_type| file_type.is_file())
+                    && entry.path().extension().is_some_and(|extension| {
+                        PARSER_EXTENSIONS.iter().any(|parser| extension == *parser)
+                    })
+            })
+            .map(ignore::DirEntry::into_path)
+            .collect(),
+    );
+    DISCOVERED_PARSERS.insert(directory.to_path_buf(), (modified, parsers.clone()));
+    parsers
+}
+
+/// Get the candidate paths of the parser files with the given names in a parser directory. Unless
+/// searching recursively, these are only the immediate children of the directory (which may not
+/// exist); otherwise, matching files in subdirectories follow.
+pub fn find_parser_files(directory: &Path, file_names: &[String], recursive: bool) -> Vec<PathBuf> {
+    let mut paths: Vec<_> = file_names
+        .iter()
+        .map(|file_name| directory.join(file_name))
+        .collect();
+    if !recursive {
+        return paths;
+    }
+    let mut nested_paths: Vec<_> = discover_parsers(directory)
+        .iter()
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| file_names.iter().any(|file_name| file_name == name))
+        })
+        .cloned()
+        .collect();
+    // Prefer shallower parsers, and otherwise keep the order of the given file names
+    nested_paths.sort_by_key(|path| {
+        let depth = path.components().count();
+        let name_index = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| file_names.iter().position(|file_name| file_name == name));
+        (depth, name_index)
+    });
+    paths.extend(nested_paths);
+    paths
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::test_helpers::helpers::TempDir;
+
+    use super::{clear_discovered_parsers, find_parser_files};
+
+    #[test]
+    fn find_parser_files_recursively() {
+        // Arrange
+        let directory = TempDir::new("discovery-test");
+        let nested = directory.join("extension").join("grammars");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(nested.join("rust.wasm"), "").unwrap();
+        fs::write(nested.join("tree-sitter-rust.so"), "").unwrap();
+        fs::write(nested.join("python.wasm"), "").unwrap();
+        let file_names = [
+            String::from("rust.so"),
+            String::from("tree-sitter-rust.so"),
+            String::from("rust.wasm"),
+        ];
+
+        // Act
+        let parser_files = find_parser_files(&directory, &file_names, false);
+        let recursive_parser_files = find_parser_files(&directory, &file_names, true);
+
+        // Assert
+        assert_eq!(
+            parser_files,
+            vec![
+                directory.join("rust.so"),
+                directory.join("tree-sitter-rust.so"),
+                directory.join("rust.wasm"),
+            ]
+        );
+        assert_eq!(
+            recursive_parser_files,
+            vec![
+                directory.join("rust.so"),
+                directory.join("tree-sitter-rust.so"),
+                directory.join("rust.wasm"),
+                nested.join("tree-sitter-rust.so"),
+                nested.join("rust.wasm"),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_parser_files_cached() {
+        // Arrange
+        let directory = TempDir::new("discovery-cache-test");
+        let nested = directory.join("grammars");
+        fs::create_dir_all(&nested).unwrap();
+        let file_names = [String::from("rust.wasm")];
+        let parser_files = find_parser_files(&directory, &file_names, true);
+        fs::write(nested.join("rust.wasm"), "").unwrap();
+
+        // Act
+        let cached_parser_files = find_parser_files(&directory, &file_names, true);
+        clear_discovered_parsers();
+        let new_parser_files = find_parser_files(&directory, &file_names, true);
+
+        // Assert
+        assert_eq!(parser_files, vec![directory.join("rust.wasm")]);
+        // Nested parsers are found again once the cache is cleared
+        assert_eq!(cached_parser_files, vec![directory.join("rust.wasm")]);
+        assert_eq!(
+            new_parser_files,
+            vec![directory.join("rust.wasm"), nested.join("rust.wasm")]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn find_parser_files_within_directory() {
+        use std::os::unix::fs::symlink;
+
+        // Arrange
+        let directory = TempDir::new("discovery-symlink-test");
+        let inside = directory.join("parsers").join("grammars");
+        let outside = directory.join("outside").join("grammars");
+        fs::create_dir_all(&inside).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(inside.join("rust.wasm"), "").unwrap();
+        fs::write(outside.join("rust.wasm"), "").unwrap();
+        let parsers = directory.join("parsers");
+        symlink(&inside, parsers.join("inside")).unwrap();
+        symlink(&outside, parsers.join("outside")).unwrap();
+        let file_names = [String::from("rust.wasm")];
+
+        // Act
+        let mut nested = find_parser_files(&parsers, &file_names, true).split_off(1);
+
+        // Assert
+        nested.sort();
+        assert_eq!(
+            nested,
+            vec![
+                inside.join("rust.wasm"),
+                parsers.join("inside").join("rust.wasm")
+            ]
+        );
+    }
+}
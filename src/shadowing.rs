@@ -0,0 +1,560 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::PathBuf,
+    sync::{Arc, LazyLock},
+    time::SystemTime,
+};
+
+use dashmap::DashMap;
+use ropey::Rope;
+use streaming_iterator::StreamingIterator as _;
+use tower_lsp::lsp_types::{Location, Range, Url};
+use tree_sitter::{Language, Node, Query, QueryCursor, Tree};
+use ts_query_ls::CapturePrecedence;
+
+use crate::{
+    DocumentData, LanguageData, SymbolInfo,
+    corpus::{CorpusFile, corpus_modification_times, load_corpus},
+    util::{NodeUtil as _, get_query_modules, parse, remove_unnecessary_escapes, uri_to_basename},
+};
+
+/// A pattern of a query whose captures are all shadowed by other patterns which take precedence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadowedPattern {
+    /// The range of the shadowed pattern.
+    pub range: Range,
+    /// The patterns shadowing it, which may be in imported modules.
+    pub shadowed_by: Vec<Location>,
+}
+
+/// The inputs of a document's shadowing analysis, which its cached result is valid for.
+#[derive(PartialEq, Eq)]
+struct ShadowingInputs {
+    version: Option<i32>,
+    modules: Vec<(Url, Rope)>,
+    language_name: Option<String>,
+    corpus_directory: Option<PathBuf>,
+    /// The modification times of the corpus files, so that edits to the corpus are noticed.
+    corpus_files: Vec<(PathBuf, Option<SystemTime>)>,
+    precedence: CapturePrecedence,
+}
+
+/// The shadowed patterns of a document, along with the inputs they were found for.
+type CachedShadowedPatterns = (ShadowingInputs, Arc<Vec<ShadowedPattern>>);
+
+/// The shadowed patterns of each document.
+static SHADOWED_PATTERNS_CACHE: LazyLock<DashMap<Url, CachedShadowedPatterns>> =
+    LazyLock::new(DashMap::new);
+
+/// Forget the cached shadowed patterns of the given document, e.g. after it is closed.
+pub fn forget_shadowed_patterns(uri: &Url) {
+    SHADOWED_PATTERNS_CACHE.remove(uri);
+}
+
+/// Forget the cached shadowed patterns of the documents of the given language, e.g. when its parser
+/// is reloaded.
+pub fn clear_shadowed_patterns_cache(language_name: &str) {
+    SHADOWED_PATTERNS_CACHE
+        .retain(|_, (inputs, _)| inputs.language_name.as_deref() != Some(language_name));
+}
+
+/// A top-level pattern of one of the combined query modules.
+struct Pattern<'t> {
+    module: usize,
+    node: Node<'t>,
+}
+
+/// Find the patterns of the given highlights query which are shadowed by other patterns of the
+/// query or its imported modules, according to the given capture precedence, using the corpus in
+/// the given directory if any. Nothing is reported for other query types. The result is cached
+/// until the document, its imported modules or the other inputs change.
+pub async fn get_shadowed_patterns(
+    document_map: &DashMap<Url, DocumentData>,
+    uri: &Url,
+    language_data: Option<Arc<LanguageData>>,
+    corpus_directory: Option<PathBuf>,
+    precedence: CapturePrecedence,
+) -> Arc<Vec<ShadowedPattern>> {
+    if uri_to_basename(uri).is_none_or(|name| name != "highlights") {
+        return Arc::default();
+    }
+    let version = document_map.get(uri).and_then(|doc| doc.version);
+    let modules = get_query_modules(document_map, uri);
+    let uri = uri.clone();
+    tokio::task::spawn_blocking(move || {
+        let inputs = ShadowingInputs {
+            version,
+            modules,
+            language_name: language_data.as_ref().map(|ld| ld.name.clone()),
+            corpus_files: corpus_directory
+                .as_deref()
+                .map(corpus_modification_times)
+                .unwrap_or_default(),
+            corpus_directory,
+            precedence,
+        };
+        if let Some(cached) = SHADOWED_PATTERNS_CACHE.get(&uri)
+            && cached.0 == inputs
+        {
+            return cached.1.clone();
+        }
+
+        let corpus = language_data
+            .as_deref()
+            .zip(inputs.corpus_directory.as_deref())
+            .map(|(language_data, directory)| {
+                load_corpus(&language_data.name, &language_data.language, directory)
+            })
+            .unwrap_or_default();
+        let shadowed = Arc::new(find_shadowed_patterns(
+            &inputs.modules,
+            language_data
+                .as_deref()
+                .map(|language_data| &language_data.language),
+            &corpus,
+            precedence,
+        ));
+        SHADOWED_PATTERNS_CACHE.insert(uri, (inputs, shadowed.clone()));
+        shadowed
+    })
+    .await
+    .unwrap_or_default()
+}
+
+/// Find the patterns of the last module which are shadowed by other patterns. The modules are in
+/// the order in which they are combined, so the last module is the query itself. When a corpus is
+/// available, a pattern is shadowed if every node it captures in the corpus is also captured by a
+/// pattern which takes precedence. Otherwise, a pattern is shadowed if a pattern which takes
+/// precedence is identical to it (ignoring capture names), or if each of its captured nodes is
+/// captured unconditionally by such a pattern, e.g. `(identifier) @variable`.
+pub fn find_shadowed_patterns(
+    modules: &[(Url, Rope)],
+    language: Option<&Language>,
+    corpus: &[Arc<CorpusFile>],
+    precedence: CapturePrecedence,
+) -> Vec<ShadowedPattern> {
+    let Some(current_module) = modules.len().checked_sub(1) else {
+        return Vec::new();
+    };
+    let trees: Vec<Tree> = modules.iter().map(|(_, rope)| parse(rope, None)).collect();
+    let patterns: Vec<Pattern> = trees
+        .iter()
+        .enumerate()
+        .flat_map(|(module, tree)| {
+            let mut cursor = tree.walk();
+            tree.root_node()
+                .named_children(&mut cursor)
+                .filter(|node| node.kind() != "comment")
+                .map(|node| Pattern { module, node })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let shadowing = language
+        .filter(|_| !corpus.is_empty())
+        .and_then(|language| corpus_shadowing(modules, &patterns, language, corpus, precedence))
+        .unwrap_or_else(|| structural_shadowing(modules, &patterns, precedence));
+
+    patterns
+        .iter()
+        .zip(shadowing)
+        .filter(|(pattern, _)| pattern.module == current_module)
+        .filter_map(|(pattern, shadowed_by)| {
+            let shadowed_by = shadowed_by?;
+            Some(ShadowedPattern {
+                range: pattern.node.lsp_range(&modules[current_module].1),
+                shadowed_by: shadowed_by
+                    .into_iter()
+                    .map(|index| {
+                        let Pattern { module, node } = &patterns[index];
+                        let (uri, rope) = &modules[*module];
+                        Location::new(uri.clone(), node.lsp_range(rope))
+                    })
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// Whether the pattern at index `other` takes precedence over the pattern at index `index`.
+const fn takes_precedence(precedence: CapturePrecedence, other: usize, index: usize) -> bool {
+    match precedence {
+        CapturePrecedence::FirstMatch => other < index,
+        CapturePrecedence::LastMatch => other > index,
+    }
+}
+
+/// For each pattern, the patterns shadowing it in the corpus, or `None` if it is not shadowed.
+/// Returns `None` if the combined query is invalid.
+fn corpus_shadowing(
+    modules: &[(Url, Rope)],
+    patterns: &[Pattern],
+    language: &Language,
+    corpus: &[Arc<CorpusFile>],
+    precedence: CapturePrecedence,
+) -> Option<Vec<Option<BTreeSet<usize>>>> {
+    let mut combined = String::new();
+    let mut module_offsets = Vec::with_capacity(modules.len());
+    for (_, rope) in modules {
+        module_offsets.push(combined.len());
+        combined += &rope.to_string();
+        combined.push('\n');
+    }
+    let query = Query::new(language, &combined).ok()?;
+    let pattern_indices: Vec<Option<usize>> = (0..query.pattern_count())
+        .map(|index| {
+            let start_byte = query.start_byte_for_pattern(index);
+            let module = module_offsets.partition_point(|&offset| offset <= start_byte) - 1;
+            let start_byte = start_byte - module_offsets[module];
+            patterns.iter().position(|pattern| {
+                pattern.module == module && pattern.node.byte_range().contains(&start_byte)
+            })
+        })
+        .collect();
+
+    // The nodes captured by each pattern, as (corpus file index, node ID) pairs
+    let mut captured = vec![HashSet::new(); patterns.len()];
+    let capture_names = query.capture_names();
+    let mut cursor = QueryCursor::new();
+    for (file_index, file) in corpus.iter().enumerate() {
+        let mut matches = cursor.matches(&query, file.tree.root_node(), file.source.as_bytes());
+        while let Some(match_) = matches.next() {
+            let Some(pattern_index) = pattern_indices[match_.pattern_index] else {
+                continue;
+            };
+            captured[pattern_index].extend(
+                match_
+                    .captures
+                    .iter()
+                    .filter(|capture| !capture_names[capture.index as usize].starts_with('_'))
+                    .map(|capture| (file_index, capture.node.id())),
+            );
+        }
+    }
+
+    Some(
+        (0..patterns.len())
+            .map(|index| {
+                if captured[index].is_empty() {
+                    return None;
+                }
+                let shadowed_by: BTreeSet<usize> = (0..patterns.len())
+                    .filter(|&other| {
+                        takes_precedence(precedence, other, index)
+                            && !captured[other].is_disjoint(&captured[index])
+                    })
+                    .collect();
+                captured[index]
+                    .iter()
+                    .all(|node| {
+                        shadowed_by
+                            .iter()
+                            .any(|&other| captured[other].contains(node))
+                    })
+                    .then_some(shadowed_by)
+            })
+            .collect(),
+    )
+}
+
+/// For each pattern, the patterns shadowing it structurally, or `None` if it is not (known to be)
+/// shadowed.
+fn structural_shadowing(
+    modules: &[(Url, Rope)],
+    patterns: &[Pattern],
+    precedence: CapturePrecedence,
+) -> Vec<Option<BTreeSet<usize>>> {
+    let signatures: Vec<String> = patterns
+        .iter()
+        .map(|pattern| pattern_signature(pattern.node, &modules[pattern.module].1))
+        .collect();
+    let unconditional: Vec<Vec<SymbolInfo>> = patterns
+        .iter()
+        .map(|pattern| unconditionally_captured_kinds(pattern.node, &modules[pattern.module].1))
+        .collect();
+
+    (0..patterns.len())
+        .map(|index| {
+            let rope = &modules[patterns[index].module].1;
+            let captured_kinds = captured_kinds(patterns[index].node, rope)?;
+            let others = || {
+                (0..patterns.len()).filter(move |&other| takes_precedence(precedence, other, index))
+            };
+            if let Some(duplicate) = others().find(|&other| signatures[other] == signatures[index])
+            {
+                return Some(BTreeSet::from([duplicate]));
+            }
+            let mut shadowed_by = BTreeSet::new();
+            for kind in &captured_kinds {
+                let other = others().find(|&other| unconditional[other].contains(kind))?;
+                shadowed_by.insert(other);
+            }
+            Some(shadowed_by)
+        })
+        .collect()
+}
+
+/// A normalized form of the pattern, in which capture names are replaced by their order of
+/// appearance and whitespace and comments are ignored.
+fn pattern_signature(node: Node, rope: &Rope) -> String {
+    fn collect_tokens(
+        node: Node,
+        rope: &Rope,
+        captures: &mut Vec<String>,
+        tokens: &mut Vec<String>,
+    ) {
+        match node.kind() {
+            "comment" => {}
+            "capture" => {
+                let name = node.text(rope);
+                let index = captures.iter().position(|c| *c == name).unwrap_or_else(|| {
+                    captures.push(name);
+                    captures.len() - 1
+                });
+                tokens.push(format!("@{index}"));
+            }
+            "string" => tokens.push(node.text(rope)),
+            _ if node.child_count() == 0 => tokens.push(node.text(rope)),
+            _ => {
+                let mut cursor = node.walk();
+                for child in node.children(&mut cursor) {
+                    collect_tokens(child, rope, captures, tokens);
+                }
+            }
+        }
+    }
+    let mut tokens = Vec::new();
+    collect_tokens(node, rope, &mut Vec::new(), &mut tokens);
+    tokens.join(" ")
+}
+
+/// The kinds of the nodes captured (by non-auxiliary captures) by the pattern. Returns `None` if
+/// the pattern has no such captures, or if the kind of a captured node cannot be determined, e.g.
+/// for wildcards.
+fn captured_kinds(node: Node, rope: &Rope) -> Option<Vec<SymbolInfo>> {
+    let mut kinds = Vec::new();
+    let mut pending = vec![node];
+    while let Some(node) = pending.pop() {
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            match child.kind() {
+                "capture" if !child.text(rope).starts_with("@_") => {
+                    kinds.extend(node_kinds(node, rope)?);
+                }
+                "parameters" | "predicate" => {}
+                _ => pending.push(child),
+            }
+        }
+    }
+    (!kinds.is_empty()).then_some(kinds)
+}
+
+/// The kinds of the nodes which the given `named_node`, `anonymous_node`, or alternation of these
+/// can match.
+fn node_kinds(node: Node, rope: &Rope) -> Option<Vec<SymbolInfo>> {
+    match node.kind() {
+        "named_node" => {
+            let name = node.child_by_field_name("name")?;
+            (name.kind() == "identifier").then(|| {
+                vec![SymbolInfo {
+                    label: name.text(rope),
+                    named: true,
+                }]
+            })
+        }
+        "anonymous_node" => {
+            let name = node.child_by_field_name("name")?;
+            let text = name.text(rope);
+            (name.kind() == "string").then(|| {
+                vec![SymbolInfo {
+                    label: remove_unnecessary_escapes(&text[1..text.len() - 1]),
+                    named: false,
+                }]
+            })
+        }
+        "list" => {
+            let mut cursor = node.walk();
+            let mut kinds = Vec::new();
+            for child in node.named_children(&mut cursor) {
+                if !matches!(child.kind(), "capture" | "quantifier" | "comment") {
+                    kinds.extend(node_kinds(child, rope)?);
+                }
+            }
+            Some(kinds)
+        }
+        _ => None,
+    }
+}
+
+/// The kinds of the nodes which the pattern captures unconditionally, i.e. whenever a node of that
+/// kind appears: those of top-level nodes without children, predicates, or supertypes, e.g.
+/// `(identifier) @variable` or `["if" "else"] @keyword`.
+fn unconditionally_captured_kinds(node: Node, rope: &Rope) -> Vec<SymbolInfo> {
+    let has_capture = |node: Node| {
+        let mut cursor = node.walk();
+        node.named_children(&mut cursor)
+            .any(|child| child.kind() == "capture" && !child.text(rope).starts_with("@_"))
+    };
+    let is_bare = |node: Node| {
+        let mut cursor = node.walk();
+        matches!(node.kind(), "named_node" | "anonymous_node")
+            && node.child_by_field_name("supertype").is_none()
+            && node.named_children(&mut cursor).all(|child| {
+                matches!(
+                    child.kind(),
+                    "identifier" | "string" | "capture" | "comment"
+                )
+            })
+    };
+    if is_bare(node) && has_capture(node) {
+        return node_kinds(node, rope).unwrap_or_default();
+    }
+    if node.kind() != "list" {
+        return Vec::new();
+    }
+    let list_captured = has_capture(node);
+    let mut cursor = node.walk();
+    node.named_children(&mut cursor)
+        .filter(|child| is_bare(*child) && (list_captured || has_capture(*child)))
+        .filter_map(|child| node_kinds(child, rope))
+        .flatten()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use pretty_assertions::assert_eq;
+    use ropey::Rope;
+    use rstest::rstest;
+    use tower_lsp::lsp_types::{Position, Range, Url};
+    use ts_query_ls::CapturePrecedence;
+
+    use crate::{RUST_LANGUAGE, corpus::load_corpus};
+
+    use super::find_shadowed_patterns;
+
+    const CORPUS_DIR: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/example_source_files"
+    );
+
+    #[rstest]
+    #[case(
+        "(identifier) @variable",
+        "(call_expression function: (identifier) @function)",
+        CapturePrecedence::FirstMatch,
+        false,
+        &[(Range::new(Position::new(0, 0), Position::new(0, 50)), vec![("base", 0)])]
+    )]
+    #[case(
+        "(identifier) @variable",
+        "(call_expression function: (identifier) @function)",
+        CapturePrecedence::LastMatch,
+        false,
+        &[]
+    )]
+    #[case(
+        "",
+        "(call_expression function: (identifier) @function)\n\n(identifier) @variable",
+        CapturePrecedence::LastMatch,
+        false,
+        &[(Range::new(Position::new(0, 0), Position::new(0, 50)), vec![("rust", 2)])]
+    )]
+    #[case(
+        "",
+        "(call_expression (identifier) @a)\n\n(call_expression\n  (identifier) @b)",
+        CapturePrecedence::FirstMatch,
+        false,
+        &[(Range::new(Position::new(2, 0), Position::new(3, 18)), vec![("rust", 0)])]
+    )]
+    #[case(
+        r#"["fn" "let"] @keyword"#,
+        r#""fn" @keyword.function"#,
+        CapturePrecedence::FirstMatch,
+        false,
+        &[(Range::new(Position::new(0, 0), Position::new(0, 22)), vec![("base", 0)])]
+    )]
+    #[case(
+        "((identifier) @constant (#eq? @constant \"x\"))",
+        "(identifier) @variable",
+        CapturePrecedence::FirstMatch,
+        false,
+        &[]
+    )]
+    #[case(
+        "(identifier) @variable",
+        "(field_expression field: (field_identifier) @property)",
+        CapturePrecedence::FirstMatch,
+        false,
+        &[]
+    )]
+    #[case(
+        "((identifier) @variable (#match? @variable \"^[a-z]\"))",
+        "(function_item name: (identifier) @function)",
+        CapturePrecedence::FirstMatch,
+        true,
+        &[(Range::new(Position::new(0, 0), Position::new(0, 44)), vec![("base", 0)])]
+    )]
+    #[case(
+        "((identifier) @variable (#match? @variable \"^m\"))",
+        "(function_item name: (identifier) @function)",
+        CapturePrecedence::FirstMatch,
+        true,
+        &[]
+    )]
+    #[case(
+        "((identifier) @variable (#match? @variable \"^[a-z]\"))",
+        "(function_item name: (identifier) @function)",
+        CapturePrecedence::LastMatch,
+        true,
+        &[]
+    )]
+    fn shadowed_patterns(
+        #[case] imported: &str,
+        #[case] source: &str,
+        #[case] precedence: CapturePrecedence,
+        #[case] use_corpus: bool,
+        #[case] expected: &[(Range, Vec<(&str, u32)>)],
+    ) {
+        // Arrange
+        let imported_uri = Url::parse("file:///tmp/queries/base/highlights.scm").unwrap();
+        let uri = Url::parse("file:///tmp/queries/rust/highlights.scm").unwrap();
+        let modules = [
+            (imported_uri.clone(), Rope::from(imported)),
+            (uri, Rope::from(source)),
+        ];
+        let corpus = if use_corpus {
+            load_corpus("rust", &RUST_LANGUAGE, Path::new(CORPUS_DIR))
+        } else {
+            Vec::new()
+        };
+
+        // Act
+        let shadowed = find_shadowed_patterns(&modules, Some(&RUST_LANGUAGE), &corpus, precedence);
+
+        // Assert
+        assert_eq!(
+            shadowed
+                .iter()
+                .map(|shadowed| {
+                    let shadowed_by = shadowed
+                        .shadowed_by
+                        .iter()
+                        .map(|location| {
+                            let module = if location.uri == imported_uri {
+                                "base"
+                            } else {
+                                "rust"
+                            };
+                            (module, location.range.start.line)
+                        })
+                        .collect();
+                    (shadowed.range, shadowed_by)
+                })
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+}
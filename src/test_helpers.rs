@@ -3,7 +3,13 @@ pub mod helpers {
     use dashmap::DashMap;
     use serde_json::{Value, to_value};
 
-    use std::sync::{Arc, LazyLock, Mutex};
+    use std::{
+        env, fs,
+        ops::Deref,
+        path::{Path, PathBuf},
+        process,
+        sync::{Arc, LazyLock, Mutex},
+    };
     use tower::{Service, ServiceExt};
 
     use tower_lsp::{
@@ -17,7 +23,7 @@ pub mod helpers {
         },
     };
 
-    use crate::{Backend, LspClient, Options};
+    use crate::{Backend, LspClient, Options, workspace_index::WorkspaceIndex};
 
     pub static TEST_URI: LazyLock<Url> =
         LazyLock::new(|| Url::parse("file:///tmp/queries/js/test.scm").unwrap());
@@ -150,7 +156,9 @@ pub mod helpers {
             language_map: DashMap::default(),
             workspace_paths: Arc::default(),
             dependents: DashMap::default(),
+            workspace_index: WorkspaceIndex::default(),
             options: Arc::default(),
+            client_options: Arc::default(),
         })
         .finish();
 
@@ -297,6 +305,33 @@ pub mod helpers {
             }
         }
     }
+
+    /// An empty directory for a test, which is removed when dropped.
+    pub struct TempDir(PathBuf);
+
+    impl TempDir {
+        /// Create the directory, whose name must be unique among the tests.
+        pub fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("ts_query_ls-{name}-{}", process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Deref for TempDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
 }
 
 #[cfg(test)]
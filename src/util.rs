@@ -1,10 +1,18 @@
 use std::{
     cell::RefCell,
+    collections::{BTreeSet, HashSet},
+    env,
     fs::{self},
     path::{Path, PathBuf},
-    sync::LazyLock,
+    process,
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::SystemTime,
 };
 
+use dashmap::DashMap;
 use regex::Regex;
 use ropey::Rope;
 use serde_json::Value;
@@ -16,7 +24,9 @@ use tower_lsp::{
         DocumentDiagnosticReportResult, NumberOrString, PartialResultParams, Position,
         ProgressToken, PublishDiagnosticsParams, Range, RelatedFullDocumentDiagnosticReport,
         TextDocumentContentChangeEvent, TextDocumentIdentifier, Url, WorkDoneProgressCreateParams,
-        WorkDoneProgressParams, notification::PublishDiagnostics, request::WorkDoneProgressCreate,
+        WorkDoneProgressParams,
+        notification::PublishDiagnostics,
+        request::{WorkDoneProgressCreate, WorkspaceDiagnosticRefresh},
     },
 };
 use tracing::{error, warn};
@@ -25,14 +35,96 @@ use tree_sitter::{
     WasmStore,
 };
 
-use crate::{Backend, DocumentData, ENGINE, ImportedUri, LspClient, Options, QUERY_LANGUAGE};
+use ts_query_ls::ImportOptions;
+
+use crate::{
+    Backend, DocumentData, ENGINE, ImportedUri, LspClient, Options, QUERY_LANGUAGE,
+    compiler::{
+        compile_grammar, grammar_modified, is_grammar_compiled, library_path,
+        parser_cache_directory,
+    },
+    corpus::resolve_corpus_directory,
+    handlers::diagnostic::unknown_severity_codes,
+    parser_discovery::{find_parser_files, is_recursive_parser_directory, parser_directories},
+    workspace_index::ensure_workspace_index,
+};
 
 pub static CAPTURES_QUERY: LazyLock<Query> =
     LazyLock::new(|| Query::new(&QUERY_LANGUAGE, "(capture) @cap").unwrap());
-pub static INHERITS_REGEX: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"^;+\s*inherits: ([a-zA-Z0-9\-_,]+)").unwrap());
+/// References to node kinds (captured as `@node`) and field names (captured as `@field`).
+pub static NODE_REFERENCES_QUERY: LazyLock<Query> = LazyLock::new(|| {
+    Query::new(
+        &QUERY_LANGUAGE,
+        r"
+(named_node name: (identifier) @node)
+(named_node supertype: (identifier) @node)
+(missing_node name: (identifier) @node)
+(field_definition name: (identifier) @field)
+(negated_field (identifier) @field)
+",
+    )
+    .unwrap()
+});
+pub static PATTERN_DEFINITION_QUERY: LazyLock<Query> =
+    LazyLock::new(|| Query::new(&QUERY_LANGUAGE, "(program (definition) @def)").unwrap());
+/// Patterns for `; inherits:` directives, indexed by whether spaces are allowed between module
+/// names and by whether optional (parenthesized) modules are allowed.
+static INHERITS_REGEXES: LazyLock<[[Regex; 2]; 2]> = LazyLock::new(|| {
+    [false, true].map(|allow_spaces| {
+        [false, true].map(|optional_modules| {
+            let (separator, spaces) = if allow_spaces {
+                (r"\s*", r" \t")
+            } else {
+                (" ", "")
+            };
+            let parens = if optional_modules { "()" } else { "" };
+            Regex::new(&format!(
+                r"^;+\s*inherits:{separator}([a-zA-Z0-9\-_,{spaces}{parens}]+)"
+            ))
+            .unwrap()
+        })
+    })
+});
 pub static FORMAT_IGNORE_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^;+\s*(format-ignore)").unwrap());
+pub static SUPPRESSION_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^;+\s*(ts_query_ls-ignore(-file)?):").unwrap());
+/// Matches a rule definition in a `grammar.js`, e.g. `name: $ => ...` or `name: (_) => ...`
+static GRAMMAR_RULE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*(\w+)\s*:\s*(\w+|\$|\([^)]*\))\s*=>").unwrap());
+
+/// Get the name of the rule defined on the given line of a `grammar.js`, if any.
+pub fn grammar_rule_name(line: &str) -> Option<regex::Match<'_>> {
+    GRAMMAR_RULE_REGEX.captures(line)?.get(1)
+}
+
+/// A `; ts_query_ls-ignore: <codes>` or `; ts_query_ls-ignore-file: <codes>` comment.
+pub struct Suppression<'a> {
+    /// Whether the suppression applies to the whole file, rather than to the next node.
+    pub file: bool,
+    /// The byte range of the directive within the comment.
+    pub directive: std::ops::Range<usize>,
+    /// The comma-separated diagnostic codes, with their byte offsets within the comment.
+    pub codes: Vec<(usize, &'a str)>,
+}
+
+pub fn parse_suppression(comment: &str) -> Option<Suppression<'_>> {
+    let captures = SUPPRESSION_REGEX.captures(comment)?;
+    let mut offset = captures.get(0)?.end();
+    let mut codes = Vec::new();
+    for code in comment[offset..].split(',') {
+        let trimmed = code.trim();
+        if !trimmed.is_empty() {
+            codes.push((offset + code.len() - code.trim_start().len(), trimmed));
+        }
+        offset += code.len() + 1;
+    }
+    Some(Suppression {
+        file: captures.get(2).is_some(),
+        directive: captures.get(1)?.range(),
+        codes,
+    })
+}
 
 thread_local! {
     static QUERY_PARSER: RefCell<Parser> = {
@@ -42,6 +134,21 @@ thread_local! {
     };
 }
 
+/// Create a parser for the given language, setting up a WASM store if needed.
+pub fn language_parser(language: &Language) -> Result<Parser, String> {
+    let mut parser = Parser::new();
+    if language.is_wasm() {
+        let store = WasmStore::new(&ENGINE).map_err(|err| err.to_string())?;
+        parser
+            .set_wasm_store(store)
+            .map_err(|err| err.to_string())?;
+    }
+    parser
+        .set_language(language)
+        .map_err(|err| err.to_string())?;
+    Ok(parser)
+}
+
 /// Parse the text in the rope as Tree-sitter query source code.
 pub fn parse(rope: &Rope, old_tree: Option<&Tree>) -> Tree {
     QUERY_PARSER.with_borrow_mut(|parser| {
@@ -304,61 +411,97 @@ pub fn get_language_name_raw(path: &Path, options: &Options) -> Option<String> {
     None
 }
 
-/// Get the language object of the given name.
+/// Get the language object of the given name, compiling its grammar checkout first if needed. This
+/// blocks while compiling, so it is only used by the command line; the server compiles grammars
+/// ahead of time with [`compile_grammars`](crate::handlers::did_open::compile_grammars).
 pub fn get_language(name: &str, options: &Options) -> Option<Language> {
+    let cache_directory = parser_cache_directory();
+    // The first grammar which compiles is the one that is loaded
+    for grammar_directory in grammar_directories(name, options) {
+        if compile_grammar(name, &grammar_directory, &cache_directory).is_some() {
+            break;
+        }
+    }
+    get_language_with_path(name, options).map(|(language, _)| language)
+}
+
+/// Get the language object of the given name, along with the path of the parser object (or grammar
+/// source directory) it was loaded from. Grammar checkouts are only loaded once they are compiled.
+pub fn get_language_with_path(
+    name: &str,
+    options: &Options,
+) -> Option<(Language, Option<PathBuf>)> {
     // Return test language objects for mocks
     #[cfg(test)]
     if name == "rust" {
         use crate::RUST_LANGUAGE;
 
-        return Some(RUST_LANGUAGE.clone());
+        return Some((RUST_LANGUAGE.clone(), None));
     } else if name == "query" {
-        return Some(QUERY_LANGUAGE.clone());
+        return Some((QUERY_LANGUAGE.clone(), None));
     }
 
-    let directories = &options.parser_install_directories;
-    let language_fn_name = format!("tree_sitter_{}", name.replace('-', "_"));
+    // Grammar checkouts come first, since these are the parsers being worked on
+    let cache_directory = parser_cache_directory();
+    for grammar_directory in grammar_directories(name, options) {
+        if is_grammar_compiled(name, &grammar_directory, &cache_directory)
+            && let Some(language) = load_language_file(
+                &library_path(name, &grammar_directory, &cache_directory),
+                name,
+            )
+        {
+            return Some((language, Some(grammar_directory)));
+        }
+    }
 
-    for directory in directories {
-        for dylib_extension in DYLIB_EXTENSIONS {
-            let object_name = format!("{name}{dylib_extension}");
-            let mut library_path = Path::new(directory).join(&object_name);
-            if let Ok(library) = unsafe {
-                libloading::Library::new(&library_path).or_else(|_| {
-                    let prefixed_object_name = format!("tree-sitter-{object_name}");
-                    library_path.set_file_name(prefixed_object_name);
-                    libloading::Library::new(library_path)
-                })
-            } {
-                let language = unsafe {
-                    let language_fn: libloading::Symbol<unsafe extern "C" fn() -> Language> =
-                        library
-                            .get(language_fn_name.as_bytes())
-                            .expect("Failed to load symbol");
-                    language_fn()
-                };
-                std::mem::forget(library);
-                return Some(language);
+    let directories = parser_directories(options);
+    let file_names: Vec<_> = DYLIB_EXTENSIONS
+        .iter()
+        .chain(&[".wasm"])
+        .flat_map(|extension| {
+            [
+                format!("{name}{extension}"),
+                format!("tree-sitter-{name}{extension}"),
+            ]
+        })
+        .collect();
+    for directory in &directories {
+        let recursive = is_recursive_parser_directory(directory, options);
+        for library_path in find_parser_files(directory, &file_names, recursive) {
+            if let Some(language) = load_language_file(&library_path, name) {
+                return Some((language, Some(library_path)));
             }
         }
-        if let Some(lang) = get_language_object_wasm(name, directory) {
-            return Some(lang);
-        }
     }
     None
 }
 
-fn get_language_object_wasm(name: &str, directory: &String) -> Option<Language> {
-    let object_name = format!("{name}.wasm");
-    let mut language_store = WasmStore::new(&ENGINE).ok()?;
-    let mut library_path = Path::new(directory).join(&object_name);
-    if let Ok(wasm) = fs::read(&library_path).or_else(|_| {
-        let prefixed_object_name = format!("tree-sitter-{object_name}");
-        library_path.set_file_name(prefixed_object_name);
-        fs::read(library_path)
-    }) {
-        let lang = language_store.load_language(name.replace('-', "_").as_str(), &wasm);
-        return match lang {
+/// Get the directories which may contain a grammar checkout of the given language: its configured
+/// grammar source directory, followed by the parser directories named after it (e.g.
+/// `tree-sitter-rust`).
+pub fn grammar_directories(name: &str, options: &Options) -> Vec<PathBuf> {
+    options
+        .grammar_source_directories
+        .get(name)
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(parser_directories(options).into_iter().filter(|directory| {
+            directory
+                .file_name()
+                .and_then(|file_name| file_name.to_str())
+                .is_some_and(|file_name| {
+                    file_name.strip_prefix("tree-sitter-").unwrap_or(file_name) == name
+                })
+        }))
+        .collect()
+}
+
+/// Load the language object of the given name from a shared library or WASM file.
+pub fn load_language_file(path: &Path, name: &str) -> Option<Language> {
+    if path.extension().is_some_and(|ext| ext == "wasm") {
+        let wasm = fs::read(path).ok()?;
+        let mut language_store = WasmStore::new(&ENGINE).ok()?;
+        return match language_store.load_language(name.replace('-', "_").as_str(), &wasm) {
             Err(err) => {
                 warn!("Error loading language {name}: {err}");
                 None
@@ -366,7 +509,87 @@ fn get_language_object_wasm(name: &str, directory: &String) -> Option<Language>
             Ok(lang) => Some(lang),
         };
     }
-    None
+
+    // Load a copy of the shared library, so that the original can be safely overwritten when the
+    // grammar is rebuilt, and the rebuilt library is not deduplicated with the loaded one.
+    let library = if COPY_SHARED_LIBRARIES.load(Ordering::Relaxed) {
+        let library_copy = copy_shared_library(path)?;
+        let library = unsafe { libloading::Library::new(&library_copy) };
+        // The copy is no longer needed once loaded, unless it is locked (e.g. on Windows)
+        if fs::remove_file(&library_copy).is_err() {
+            SHARED_LIBRARY_COPIES.lock().unwrap().push(library_copy);
+        }
+        library
+    } else {
+        unsafe { libloading::Library::new(path) }
+    };
+    let library = library.ok()?;
+    let language_fn_name = format!("tree_sitter_{}", name.replace('-', "_"));
+    let language = unsafe {
+        let language_fn: libloading::Symbol<unsafe extern "C" fn() -> Language> =
+            library.get(language_fn_name.as_bytes()).ok()?;
+        language_fn()
+    };
+    // Language objects may outlive a reload of their parser (e.g. in requests which are still
+    // being processed), so the library must never be unloaded.
+    std::mem::forget(library);
+    Some(language)
+}
+
+/// Get the modification time of a parser object, or the latest one of a grammar's sources.
+pub fn parser_modified(path: &Path) -> Option<SystemTime> {
+    if path.is_dir() {
+        return grammar_modified(path);
+    }
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Whether shared libraries are copied before they are loaded, so that their parsers can be
+/// reloaded once they are rebuilt. Only the language server reloads parsers; the CLI loads them in
+/// place.
+pub static COPY_SHARED_LIBRARIES: AtomicBool = AtomicBool::new(true);
+
+/// Copies of shared libraries which could not be removed once they were loaded.
+static SHARED_LIBRARY_COPIES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Remove the copies of shared libraries which could not be removed when they were loaded, where
+/// possible. Called whenever a library is copied again, and when the server shuts down.
+pub fn remove_shared_library_copies() {
+    SHARED_LIBRARY_COPIES
+        .lock()
+        .unwrap()
+        .retain(|copy| fs::remove_file(copy).is_err());
+}
+
+/// Copy the given shared library to a uniquely named file in the temporary directory. The copy of
+/// e.g. `libfoo.so.1` is named `libfoo-<pid>-<n>.so.1`.
+fn copy_shared_library(path: &Path) -> Option<PathBuf> {
+    static COPY_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    if !path.is_file() {
+        return None;
+    }
+    remove_shared_library_copies();
+    let directory = env::temp_dir().join("ts_query_ls");
+    fs::create_dir_all(&directory).ok()?;
+    let file_name = path.file_name()?.to_string_lossy();
+    let mut parts = file_name.splitn(2, '.');
+    let (stem, extension) = (parts.next()?, parts.next());
+    let mut copy_name = format!(
+        "{stem}-{}-{}",
+        process::id(),
+        COPY_COUNT.fetch_add(1, Ordering::Relaxed)
+    );
+    if let Some(extension) = extension {
+        copy_name.push('.');
+        copy_name.push_str(extension);
+    }
+    let copy_path = directory.join(copy_name);
+    if let Err(err) = fs::copy(path, &copy_path) {
+        warn!("Failed to copy {}: {err}", path.display());
+        return None;
+    }
+    Some(copy_path)
 }
 
 pub trait NodeUtil {
@@ -411,15 +634,27 @@ fn get_first_valid_file_config(workspace_uris: Vec<PathBuf>) -> Option<Options>
     None
 }
 
+/// Set the configuration sent by the client, and merge it with the configuration file.
 pub async fn set_configuration_options<C: LspClient>(
     backend: &Backend<C>,
     init_options: Option<Value>,
     workspace_uris: Vec<PathBuf>,
 ) {
+    *backend.client_options.write().await = init_options;
+    reload_configuration_options(backend, workspace_uris).await;
+}
+
+/// Merge the configuration last sent by the client with the configuration file, e.g. after the
+/// configuration file changed.
+pub async fn reload_configuration_options<C: LspClient>(
+    backend: &Backend<C>,
+    workspace_uris: Vec<PathBuf>,
+) {
+    let client_options = backend.client_options.read().await.clone();
     let mut options = backend.options.write().await;
     *options = Options::default();
 
-    if let Some(init_options) = init_options {
+    if let Some(init_options) = client_options {
         if let Ok(parsed_options) = serde_json::from_value::<Options>(init_options) {
             *options = parsed_options;
         } else {
@@ -427,15 +662,32 @@ pub async fn set_configuration_options<C: LspClient>(
         }
     }
 
-    if let Some(mut file_options) = get_first_valid_file_config(workspace_uris) {
+    if let Some(mut file_options) = get_first_valid_file_config(workspace_uris.clone()) {
         // Merge parser_install_directories, since these are dependent on the local user's
         // installation paths
-        let mut config_file_install_dirs = options.parser_install_directories.clone();
-        config_file_install_dirs.append(&mut file_options.parser_install_directories);
-        file_options.parser_install_directories = config_file_install_dirs;
+        let mut install_dirs = Vec::new();
+        for directory in options
+            .parser_install_directories
+            .drain(..)
+            .chain(file_options.parser_install_directories.drain(..))
+        {
+            if !install_dirs.contains(&directory) {
+                install_dirs.push(directory);
+            }
+        }
+        file_options.parser_install_directories = install_dirs;
 
         *options = file_options;
     }
+
+    for directory in options.corpus_directories.values_mut() {
+        *directory = resolve_corpus_directory(directory, &workspace_uris)
+            .to_string_lossy()
+            .into_owned();
+    }
+    for code in unknown_severity_codes(&options) {
+        warn!("Unknown diagnostic code {code:?} in diagnostic_options.severity");
+    }
 }
 
 pub fn uri_to_basename(uri: &Url) -> Option<String> {
@@ -525,6 +777,54 @@ pub fn get_file_uris(
     urls
 }
 
+/// Parse the modules named by a `; inherits:` directive comment. Returns the byte offset of each
+/// module name within the comment, the name itself, and whether the module is parenthesized.
+pub fn parse_inherits_directive(
+    comment: &str,
+    options: ImportOptions,
+) -> Option<Vec<(usize, &str, bool)>> {
+    let regex =
+        &INHERITS_REGEXES[usize::from(options.allow_spaces)][usize::from(options.optional_modules)];
+    let modules = regex.captures(comment)?.get(1)?;
+    let mut offset = modules.start();
+    Some(
+        modules
+            .as_str()
+            .split(',')
+            .map(|module| {
+                let mut start = offset;
+                offset += module.len() + 1;
+                let mut module = module;
+                if options.allow_spaces {
+                    let trimmed = module.trim_start();
+                    start += module.len() - trimmed.len();
+                    module = trimmed.trim_end();
+                }
+                if options.optional_modules
+                    && let Some(name) = module.strip_prefix('(').and_then(|m| m.strip_suffix(')'))
+                {
+                    return (start + 1, name, true);
+                }
+                (start, module, false)
+            })
+            .collect(),
+    )
+}
+
+/// Returns the comments which may contain `; inherits:` directives: the comment on the first line
+/// of the document, or every comment at the top of the document if multiple directives are allowed.
+pub fn get_inherits_comments(
+    tree: &Tree,
+    options: ImportOptions,
+) -> impl Iterator<Item = Node<'_>> {
+    let root = tree.root_node();
+    let multiple_directives = options.multiple_directives;
+    (0..root.child_count() as u32)
+        .map_while(move |i| root.child(i).filter(|node| node.kind() == "comment"))
+        .take_while(move |node| multiple_directives || node.start_position().row == 0)
+        .take(if multiple_directives { usize::MAX } else { 1 })
+}
+
 /// Returns a list of URIs corresponding to the modules in the `; inherits: ` chain. `None` if the
 /// module could not be found.
 pub fn get_imported_uris(
@@ -535,49 +835,89 @@ pub fn get_imported_uris(
     tree: &Tree,
 ) -> Vec<ImportedUri> {
     let mut uris = Vec::new();
-    let Some(start_comment) = tree
-        .root_node()
-        .child(0)
-        .filter(|node| node.kind() == "comment" && node.start_position().row == 0)
-    else {
-        return uris;
-    };
-    let comment_text = start_comment.text(rope);
-    let Some(modules) = INHERITS_REGEX
-        .captures(&comment_text)
-        .and_then(|c| c.get(1))
-    else {
-        return uris;
-    };
     let Some(query_name) = uri_to_basename(uri) else {
         return uris;
     };
 
-    let mut byte_offset = (start_comment.start_byte() + modules.start()) as u32;
-    for module in modules.as_str().split(',') {
-        let (start, end) = (byte_offset, byte_offset + module.len() as u32);
-        byte_offset = end + 1;
-        if module.is_empty() {
-            uris.push(ImportedUri::new(start, end, module.to_string(), None));
+    for comment in get_inherits_comments(tree, options.import_options) {
+        let comment_text = comment.text(rope);
+        let Some(modules) = parse_inherits_directive(&comment_text, options.import_options) else {
             continue;
-        }
-        let module_uris = get_file_uris(workspace_dirs, options, module, &query_name);
-        if module_uris.len() > 1 {
-            warn!(
-                "Imported module {module} has more than one associated file location, analyzing the first one"
+        };
+        let line = comment.start_position().row as u32;
+        let column = comment.start_position().column;
+        for (offset, module, optional) in modules {
+            let (start, end) = (
+                (column + offset) as u32,
+                (column + offset + module.len()) as u32,
             );
+            if module.is_empty() {
+                uris.push(ImportedUri::new(
+                    line,
+                    start,
+                    end,
+                    module.to_string(),
+                    None,
+                    optional,
+                ));
+                continue;
+            }
+            let module_uris = get_file_uris(workspace_dirs, options, module, &query_name);
+            if module_uris.len() > 1 {
+                warn!(
+                    "Imported module {module} has more than one associated file location, analyzing the first one"
+                );
+            }
+            uris.push(ImportedUri::new(
+                line,
+                start,
+                end,
+                module.to_string(),
+                module_uris.first().cloned(),
+                optional,
+            ));
         }
-        uris.push(ImportedUri::new(
-            start,
-            end,
-            module.to_string(),
-            module_uris.first().cloned(),
-        ));
     }
 
     uris
 }
 
+/// Collect the given query module along with all of its (recursively) imported modules, in the
+/// order in which they are combined: a module's imports come before the module itself, and each
+/// module is only included once. Modules which are not in the document map are skipped. As in
+/// Neovim, parenthesized modules are only included when their importing module is itself imported.
+pub fn get_query_modules(document_map: &DashMap<Url, DocumentData>, uri: &Url) -> Vec<(Url, Rope)> {
+    let mut modules = Vec::new();
+    collect_query_modules(document_map, uri, false, &mut HashSet::new(), &mut modules);
+    modules
+}
+
+fn collect_query_modules(
+    document_map: &DashMap<Url, DocumentData>,
+    uri: &Url,
+    imported: bool,
+    visited: &mut HashSet<Url>,
+    modules: &mut Vec<(Url, Rope)>,
+) {
+    if !visited.insert(uri.clone()) {
+        return;
+    }
+    let Some((rope, imported_uris)) = document_map
+        .get(uri)
+        .map(|doc| (doc.rope.clone(), doc.imported_uris.clone()))
+    else {
+        return;
+    };
+    for imported_uri in imported_uris
+        .iter()
+        .filter(|import| imported || !import.optional)
+        .filter_map(|import| import.uri.as_ref())
+    {
+        collect_query_modules(document_map, imported_uri, true, visited, modules);
+    }
+    modules.push((uri.clone(), rope));
+}
+
 /// Check if a string is a subsequence of another string; in order words, it is contained in the
 /// other string with possible gaps between characters.
 pub fn is_subsequence(sub: &str, main: &str) -> bool {
@@ -601,32 +941,14 @@ pub fn get_imported_module_under_cursor(
     document: &DocumentData,
     position: Position,
 ) -> Option<&ImportedUri> {
-    if position.line != 0 {
-        return None;
-    }
-    let tree = &document.tree;
     let rope = &document.rope;
-    let ts_point = position.to_ts_point(rope);
-    let comment_node = tree
-        .root_node()
-        .descendant_for_point_range(ts_point, ts_point)
-        .filter(|node| node.kind() == "comment")?;
-    let node_text = comment_node.text(rope);
-    let modules = INHERITS_REGEX.captures(&node_text).and_then(|c| c.get(1))?;
-    let cursor_offset = position.to_byte_offset(rope);
-    let mut comment_offset = comment_node.start_byte() + modules.start();
-
-    let module_name = modules.as_str().split(',').find_map(|module| {
-        let end = comment_offset + module.len();
-        let cursor_in_module = cursor_offset >= comment_offset && cursor_offset < end;
-        comment_offset = end + 1;
-        cursor_in_module.then(|| module.to_string())
-    })?;
-
-    document
-        .imported_uris
-        .iter()
-        .find(|import| import.name == module_name)
+    let column =
+        position.to_byte_offset(rope) - rope.try_line_to_byte(position.line as usize).ok()?;
+    document.imported_uris.iter().find(|import| {
+        import.line == position.line
+            && column >= import.start_col as usize
+            && column < import.end_col as usize
+    })
 }
 
 /// Push diagnostics to the client (only if it does not support pull diagnostics).
@@ -697,6 +1019,34 @@ pub async fn push_diagnostics<C: LspClient>(backend: &Backend<C>, uri: Url) {
     }
 }
 
+/// Re-diagnose the given documents after something they depend on changed outside of the editor.
+/// Clients using pull diagnostics are asked to refresh them instead.
+pub async fn refresh_diagnostics<C: LspClient>(backend: &Backend<C>, uris: Vec<Url>) {
+    if uris.is_empty() {
+        return;
+    }
+    let refresh_support = backend
+        .client_capabilities
+        .read()
+        .await
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.diagnostic.as_ref())
+        .and_then(|diagnostic| diagnostic.refresh_support)
+        .unwrap_or_default();
+    if refresh_support
+        && let Err(error) = backend
+            .client
+            .send_request::<WorkspaceDiagnosticRefresh>(())
+            .await
+    {
+        warn!("Failed to refresh diagnostics: {error}");
+    }
+    for uri in uris {
+        push_diagnostics(backend, uri).await;
+    }
+}
+
 #[cfg(not(test))]
 fn make_uuid() -> String {
     uuid::Uuid::new_v4().to_string()
@@ -742,6 +1092,75 @@ pub async fn get_work_done_token<C: LspClient>(
     }
 }
 
+/// The references to a node kind or field name within a single query file.
+pub struct NodeReferences {
+    pub uri: Url,
+    pub version: Option<i32>,
+    pub ranges: Vec<Range>,
+}
+
+/// Find the references to the given node kind (when `capture_name` is `node`) or field name (when
+/// it is `field`) in every query file of the document's language, and in every query module they
+/// inherit. The results are sorted by URI.
+pub async fn get_node_references<C: LspClient>(
+    backend: &Backend<C>,
+    uri: &Url,
+    doc: &DocumentData,
+    capture_name: &str,
+    name: &str,
+) -> Vec<NodeReferences> {
+    ensure_workspace_index(backend, None).await;
+    let index = &backend.workspace_index;
+    let mut uris = BTreeSet::from([uri.clone()]);
+    if let Some(language_name) = &doc.language_name {
+        uris.extend(index.language_uris(language_name));
+    }
+
+    let mut pending: Vec<_> = uris.iter().cloned().collect();
+    let mut references = Vec::new();
+    while let Some(uri) = pending.pop() {
+        let Some(file) = index.get(&uri) else {
+            continue;
+        };
+        for imported_uri in &file.imports {
+            if uris.insert(imported_uri.clone()) {
+                pending.push(imported_uri.clone());
+            }
+        }
+
+        let symbols = if capture_name == "node" {
+            &file.node_kinds
+        } else {
+            &file.fields
+        };
+        let ranges: Vec<_> = symbols
+            .iter()
+            .filter(|symbol| symbol.name == name)
+            .map(|symbol| symbol.range)
+            .collect();
+        drop(file);
+        if !ranges.is_empty() {
+            references.push(NodeReferences {
+                version: backend.document_map.get(&uri).and_then(|doc| doc.version),
+                uri,
+                ranges,
+            });
+        }
+    }
+    references.sort_unstable_by(|a, b| a.uri.cmp(&b.uri));
+    references
+}
+
+/// Escape the kind of an anonymous node, so that it matches its form within a query string.
+pub fn escape_node_kind(kind: &str) -> String {
+    kind.replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n")
+        .replace('\r', r"\r")
+        .replace('\t', r"\t")
+        .replace('\0', r"\0")
+}
+
 /// Remove unnecessary backslashes from the given string content.
 pub fn remove_unnecessary_escapes(input: &str) -> String {
     let mut result = String::new();
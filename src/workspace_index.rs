@@ -0,0 +1,478 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{
+        Arc, LazyLock, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use ropey::Rope;
+use streaming_iterator::StreamingIterator as _;
+use tokio::sync::Mutex;
+use tower_lsp::lsp_types::{
+    Location, ProgressParams, ProgressParamsValue, ProgressToken, Range, Url, WorkDoneProgress,
+    WorkDoneProgressBegin, WorkDoneProgressEnd, WorkDoneProgressReport, notification::Progress,
+};
+use tree_sitter::{Query, QueryCursor, Tree};
+use ts_query_ls::Options;
+
+use crate::{
+    Backend, DocumentData, ImportedUri, LspClient, QUERY_LANGUAGE,
+    util::{
+        CAPTURES_QUERY, NODE_REFERENCES_QUERY, NodeUtil as _, TextProviderRope, get_imported_uris,
+        get_language_name, get_scm_files, parse, uri_to_basename,
+    },
+};
+
+/// Predicate and directive names (captured as `@name`), along with their `?` or `!` suffix
+/// (captured as `@type`).
+pub static PREDICATES_QUERY: LazyLock<Query> = LazyLock::new(|| {
+    Query::new(
+        &QUERY_LANGUAGE,
+        "(predicate name: (identifier) @name (predicate_type) @type)",
+    )
+    .unwrap()
+});
+
+/// A capture within an indexed query file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexedCapture {
+    /// The capture name, sans `@`.
+    pub name: String,
+    pub range: Range,
+    /// Whether the capture is a predicate or directive argument, rather than part of a pattern.
+    pub is_argument: bool,
+}
+
+/// A node kind, field name, or predicate within an indexed query file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexedSymbol {
+    pub name: String,
+    pub range: Range,
+}
+
+/// The symbols of a single query file, in document order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IndexedFile {
+    /// The language of the query, if it could be determined.
+    pub language_name: Option<String>,
+    pub captures: Vec<IndexedCapture>,
+    pub node_kinds: Vec<IndexedSymbol>,
+    pub fields: Vec<IndexedSymbol>,
+    /// Predicate and directive names, including their `?` or `!` suffix.
+    pub predicates: Vec<IndexedSymbol>,
+    /// The modules imported by the query which could be found.
+    pub imports: Vec<Url>,
+}
+
+/// An in-memory index of the symbols of every query file in the workspace, along with any other
+/// open query files. It is built when the server is initialized (or on first use), and kept up to
+/// date as documents are edited and as files change on disk.
+#[derive(Default)]
+pub struct WorkspaceIndex {
+    files: RwLock<HashMap<Url, Arc<IndexedFile>>>,
+    built: AtomicBool,
+    /// Held while the index is (re)built, so that it is only built by one task at a time.
+    building: Mutex<()>,
+}
+
+impl WorkspaceIndex {
+    /// Whether every query file in the workspace has been indexed.
+    pub fn is_built(&self) -> bool {
+        self.built.load(Ordering::Acquire)
+    }
+
+    pub fn get(&self, uri: &Url) -> Option<Arc<IndexedFile>> {
+        self.files.read().unwrap().get(uri).cloned()
+    }
+
+    fn insert(&self, uri: Url, file: IndexedFile) {
+        self.files.write().unwrap().insert(uri, Arc::new(file));
+    }
+
+    /// Index the contents of an open document.
+    pub fn index_document(&self, uri: &Url, document: &DocumentData) {
+        self.insert(uri.clone(), index_document(document));
+    }
+
+    pub fn remove(&self, uri: &Url) {
+        self.files.write().unwrap().remove(uri);
+    }
+
+    /// The URIs of every indexed file, sorted.
+    pub fn uris(&self) -> Vec<Url> {
+        let mut uris: Vec<_> = self.files.read().unwrap().keys().cloned().collect();
+        uris.sort_unstable();
+        uris
+    }
+
+    /// The URIs of every indexed file of the given language, sorted.
+    pub fn language_uris(&self, language_name: &str) -> Vec<Url> {
+        let mut uris: Vec<_> = self
+            .files
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, file)| file.language_name.as_deref() == Some(language_name))
+            .map(|(uri, _)| uri.clone())
+            .collect();
+        uris.sort_unstable();
+        uris
+    }
+
+    /// Find the uses of the given capture (sans `@`) in every indexed query file of the given type
+    /// (e.g. `highlights`), regardless of language. Unless definitions are included, only predicate
+    /// and directive arguments are returned. The results are sorted by URI and position.
+    pub fn capture_references(
+        &self,
+        query_type: &str,
+        name: &str,
+        include_definitions: bool,
+    ) -> Vec<Location> {
+        let mut locations = Vec::new();
+        for uri in self.uris() {
+            if uri_to_basename(&uri).is_none_or(|basename| basename != query_type) {
+                continue;
+            }
+            let Some(file) = self.get(&uri) else {
+                continue;
+            };
+            locations.extend(
+                file.captures
+                    .iter()
+                    .filter(|capture| {
+                        capture.name == name && (include_definitions || capture.is_argument)
+                    })
+                    .map(|capture| Location::new(uri.clone(), capture.range)),
+            );
+        }
+        locations
+    }
+
+    /// Find the uses of the given predicate or directive (including its `?` or `!` suffix) in every
+    /// indexed query file. The results are sorted by URI and position.
+    pub fn predicate_references(&self, name: &str) -> Vec<Location> {
+        let mut locations = Vec::new();
+        for uri in self.uris() {
+            let Some(file) = self.get(&uri) else {
+                continue;
+            };
+            locations.extend(
+                file.predicates
+                    .iter()
+                    .filter(|predicate| predicate.name == name)
+                    .map(|predicate| Location::new(uri.clone(), predicate.range)),
+            );
+        }
+        locations
+    }
+}
+
+/// Collect the symbols of a query file.
+pub fn index_query(
+    rope: &Rope,
+    tree: &Tree,
+    language_name: Option<String>,
+    imported_uris: &[ImportedUri],
+) -> IndexedFile {
+    let provider = &TextProviderRope(rope);
+    let mut cursor = QueryCursor::new();
+    let mut file = IndexedFile {
+        language_name,
+        imports: imported_uris
+            .iter()
+            .filter_map(|import| import.uri.clone())
+            .collect(),
+        ..Default::default()
+    };
+
+    let mut matches = cursor.matches(&CAPTURES_QUERY, tree.root_node(), provider);
+    while let Some(match_) = matches.next() {
+        for capture in match_.captures {
+            let node = capture.node;
+            file.captures.push(IndexedCapture {
+                name: node.text(rope).trim_start_matches('@').to_owned(),
+                range: node.lsp_range(rope),
+                is_argument: node.parent().is_some_and(|p| p.kind() == "parameters"),
+            });
+        }
+    }
+
+    let mut matches = cursor.matches(&NODE_REFERENCES_QUERY, tree.root_node(), provider);
+    while let Some(match_) = matches.next() {
+        for capture in match_.captures {
+            let symbol = IndexedSymbol {
+                name: capture.node.text(rope),
+                range: capture.node.lsp_range(rope),
+            };
+            if NODE_REFERENCES_QUERY.capture_names()[capture.index as usize] == "node" {
+                file.node_kinds.push(symbol);
+            } else {
+                file.fields.push(symbol);
+            }
+        }
+    }
+
+    let mut matches = cursor.matches(&PREDICATES_QUERY, tree.root_node(), provider);
+    while let Some(match_) = matches.next() {
+        let [name, predicate_type] = match_.captures else {
+            continue;
+        };
+        let mut range = name.node.lsp_range(rope);
+        // Include the # in the range
+        range.start.character = range.start.character.saturating_sub(1);
+        range.end = predicate_type.node.lsp_range(rope).end;
+        file.predicates.push(IndexedSymbol {
+            name: name.node.text(rope) + &predicate_type.node.text(rope),
+            range,
+        });
+    }
+
+    file
+}
+
+fn index_document(document: &DocumentData) -> IndexedFile {
+    index_query(
+        &document.rope,
+        &document.tree,
+        document.language_name.clone(),
+        &document.imported_uris,
+    )
+}
+
+/// Index the query file with the given URI, preferring its contents in the document map over its
+/// contents on disk. Returns `None` if the file could not be read.
+fn index_uri<C: LspClient>(
+    backend: &Backend<C>,
+    uri: &Url,
+    options: &Options,
+    workspace_dirs: &[PathBuf],
+) -> Option<IndexedFile> {
+    if let Some(document) = backend.document_map.get(uri) {
+        return Some(index_document(&document));
+    }
+    let contents = fs::read_to_string(uri.to_file_path().ok()?).ok()?;
+    let rope = Rope::from(contents);
+    let tree = parse(&rope, None);
+    let imported_uris = get_imported_uris(workspace_dirs, options, uri, &rope, &tree);
+    Some(index_query(
+        &rope,
+        &tree,
+        get_language_name(uri, options),
+        &imported_uris,
+    ))
+}
+
+/// Re-index a single query file, e.g. after it changed on disk. It is removed from the index if it
+/// can no longer be read.
+pub async fn reindex_uri<C: LspClient>(backend: &Backend<C>, uri: &Url) {
+    let options = backend.options.read().await;
+    let workspace_dirs = backend.workspace_paths.read().unwrap().clone();
+    match index_uri(backend, uri, &options, &workspace_dirs) {
+        Some(file) => backend.workspace_index.insert(uri.clone(), file),
+        None => backend.workspace_index.remove(uri),
+    }
+}
+
+/// Build the workspace index, if it has not been built yet. If it is being built by another task,
+/// wait for that build to finish instead.
+pub async fn ensure_workspace_index<C: LspClient>(
+    backend: &Backend<C>,
+    token: Option<ProgressToken>,
+) {
+    if backend.workspace_index.is_built() {
+        return;
+    }
+    let _building = backend.workspace_index.building.lock().await;
+    if !backend.workspace_index.is_built() {
+        build_workspace_index(backend, token).await;
+    }
+}
+
+/// (Re)build the workspace index from every query file in the workspace and every open document,
+/// reporting progress with the given token.
+pub async fn index_workspace<C: LspClient>(backend: &Backend<C>, token: Option<ProgressToken>) {
+    let _building = backend.workspace_index.building.lock().await;
+    build_workspace_index(backend, token).await;
+}
+
+/// Build the workspace index into a new map, which replaces the current one once it is complete,
+/// so that the index remains usable while it is rebuilt.
+async fn build_workspace_index<C: LspClient>(backend: &Backend<C>, token: Option<ProgressToken>) {
+    let options = backend.options.read().await.clone();
+    let workspace_dirs = backend.workspace_paths.read().unwrap().clone();
+    let mut files = HashMap::new();
+
+    let paths = get_scm_files(&workspace_dirs).collect::<Vec<_>>();
+    let file_count = paths.len();
+    let file_count_div_100 = file_count as f64 * 0.01;
+    let mut num_processed_files = 0;
+    let mut progress_percent = 0;
+
+    if let Some(token) = token.clone() {
+        backend
+            .client
+            .send_notification::<Progress>(ProgressParams {
+                token,
+                value: ProgressParamsValue::WorkDone(WorkDoneProgress::Begin(
+                    WorkDoneProgressBegin {
+                        title: "Indexing workspace".into(),
+                        percentage: Some(0),
+                        message: Some(format!("0/{file_count} files indexed")),
+                        cancellable: Some(false),
+                    },
+                )),
+            })
+            .await;
+    }
+
+    for path in paths {
+        if let Ok(uri) = Url::from_file_path(path)
+            && let Some(file) = index_uri(backend, &uri, &options, &workspace_dirs)
+        {
+            files.insert(uri, Arc::new(file));
+        }
+        num_processed_files += 1;
+        let percentage = (num_processed_files as f64 / file_count_div_100).floor() as u32;
+        if percentage > progress_percent + 4 {
+            progress_percent = percentage;
+            if let Some(token) = token.clone() {
+                backend
+                    .client
+                    .send_notification::<Progress>(ProgressParams {
+                        token,
+                        value: ProgressParamsValue::WorkDone(WorkDoneProgress::Report(
+                            WorkDoneProgressReport {
+                                percentage: Some(progress_percent),
+                                message: Some(format!(
+                                    "{num_processed_files}/{file_count} files indexed"
+                                )),
+                                cancellable: Some(false),
+                            },
+                        )),
+                    })
+                    .await;
+            }
+        }
+    }
+    // Open documents may have been edited while the files were indexed
+    for document in &backend.document_map {
+        files.insert(document.key().clone(), Arc::new(index_document(&document)));
+    }
+    *backend.workspace_index.files.write().unwrap() = files;
+    backend.workspace_index.built.store(true, Ordering::Release);
+
+    if let Some(token) = token {
+        backend
+            .client
+            .send_notification::<Progress>(ProgressParams {
+                token,
+                value: ProgressParamsValue::WorkDone(WorkDoneProgress::End(WorkDoneProgressEnd {
+                    message: Some(format!("{file_count}/{file_count} files indexed")),
+                })),
+            })
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+    use ropey::Rope;
+    use tower_lsp::lsp_types::{
+        NumberOrString, Position, ProgressParams, ProgressParamsValue, Range, WorkDoneProgress,
+        WorkDoneProgressEnd, notification::Progress,
+    };
+
+    use super::{IndexedCapture, IndexedFile, IndexedSymbol, ensure_workspace_index, index_query};
+    use crate::{
+        Options,
+        test_helpers::helpers::{MockRequest, initialize_server},
+        util::parse,
+    };
+
+    #[test]
+    fn index_query_symbols() {
+        // Arrange
+        let rope = Rope::from(
+            r#"(function_definition
+  name: (identifier) @function
+  !body
+  (#eq? @function "main")
+  (#set! priority 105))"#,
+        );
+        let tree = parse(&rope, None);
+        let symbol = |name: &str, start: (u32, u32), end: (u32, u32)| IndexedSymbol {
+            name: name.to_owned(),
+            range: Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1)),
+        };
+
+        // Act
+        let file = index_query(&rope, &tree, Some(String::from("c")), &[]);
+
+        // Assert
+        assert_eq!(
+            file,
+            IndexedFile {
+                language_name: Some(String::from("c")),
+                captures: vec![
+                    IndexedCapture {
+                        name: String::from("function"),
+                        range: Range::new(Position::new(1, 21), Position::new(1, 30)),
+                        is_argument: false,
+                    },
+                    IndexedCapture {
+                        name: String::from("function"),
+                        range: Range::new(Position::new(3, 8), Position::new(3, 17)),
+                        is_argument: true,
+                    },
+                ],
+                node_kinds: vec![
+                    symbol("function_definition", (0, 1), (0, 20)),
+                    symbol("identifier", (1, 9), (1, 19)),
+                ],
+                fields: vec![
+                    symbol("name", (1, 2), (1, 6)),
+                    symbol("body", (2, 3), (2, 7))
+                ],
+                predicates: vec![
+                    symbol("eq?", (3, 3), (3, 7)),
+                    symbol("set!", (4, 3), (4, 8)),
+                ],
+                imports: Vec::new(),
+            }
+        );
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn ensure_workspace_index_builds_once() {
+        // Arrange
+        let service = initialize_server(&[], &Options::default()).await;
+        let backend = service.inner();
+        let token = || Some(NumberOrString::String(String::from("index")));
+
+        // Act
+        tokio::join!(
+            ensure_workspace_index(backend, token()),
+            ensure_workspace_index(backend, token())
+        );
+
+        // Assert
+        let end = MockRequest::from_notification::<Progress>(ProgressParams {
+            token: NumberOrString::String(String::from("index")),
+            value: ProgressParamsValue::WorkDone(WorkDoneProgress::End(WorkDoneProgressEnd {
+                message: Some(String::from("4/4 files indexed")),
+            })),
+        });
+        let builds = backend
+            .client
+            .get_notifications()
+            .iter()
+            .filter(|notification| **notification == end)
+            .count();
+        assert_eq!(builds, 1);
+        assert!(backend.workspace_index.is_built());
+    }
+}
@@ -0,0 +1,156 @@
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+    use serde_json::{Value, json};
+    use std::{
+        collections::{BTreeMap, HashMap},
+        path::Path,
+        process::Command,
+    };
+    use ts_query_ls::Options;
+
+    const QUERIES_DIR: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/coverage_test_files"
+    );
+
+    fn captures(names: &[&str]) -> BTreeMap<String, String> {
+        names
+            .iter()
+            .map(|name| ((*name).to_string(), String::new()))
+            .collect()
+    }
+
+    #[rstest]
+    #[case(
+        HashMap::from([(
+            String::from("highlights"),
+            captures(&["comment", "function.call", "keyword", "variable"]),
+        )]),
+        json!({
+            "highlights": {
+                "base": {
+                    "comment": true,
+                    "function.call": false,
+                    "keyword": false,
+                    "variable": true,
+                },
+                "derived": {
+                    "comment": true,
+                    "function.call": true,
+                    "keyword": false,
+                    "variable": true,
+                },
+            },
+        })
+    )]
+    #[case(
+        HashMap::from([
+            (String::from("folds"), captures(&["fold"])),
+            (String::from("indents"), captures(&["indent.begin"])),
+        ]),
+        json!({
+            "folds": {
+                "derived": {
+                    "fold": true,
+                },
+            },
+        })
+    )]
+    fn cli_coverage_json(
+        #[case] valid_captures: HashMap<String, BTreeMap<String, String>>,
+        #[case] expected: Value,
+    ) {
+        // Arrange
+        let path = Path::new(QUERIES_DIR);
+        let options = Options {
+            valid_captures,
+            ..Default::default()
+        };
+
+        // Act
+        let output = Command::new(env!("CARGO_BIN_EXE_ts_query_ls"))
+            .arg("coverage")
+            .arg(path)
+            .arg("--workspace")
+            .arg(path)
+            .arg("--format")
+            .arg("json")
+            .arg("--config")
+            .arg(serde_json::to_string(&options).unwrap())
+            .output()
+            .expect("Failed to execute coverage command");
+
+        // Assert
+        assert!(output.status.success());
+        let actual: Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn cli_coverage_text() {
+        // Arrange
+        let path = Path::new(QUERIES_DIR);
+        let options = Options {
+            valid_captures: HashMap::from([(
+                String::from("highlights"),
+                captures(&["comment", "function.call", "keyword", "variable"]),
+            )]),
+            ..Default::default()
+        };
+
+        // Act
+        let output = Command::new(env!("CARGO_BIN_EXE_ts_query_ls"))
+            .arg("coverage")
+            .arg(path)
+            .arg("--workspace")
+            .arg(path)
+            .arg("--config")
+            .arg(serde_json::to_string(&options).unwrap())
+            .output()
+            .expect("Failed to execute coverage command");
+
+        // Assert
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "highlights:
+  base (2/4 captures used)
+    unused: @function.call @keyword
+  derived (3/4 captures used)
+    unused: @keyword
+
+                  base  derived
+  @comment         x       x
+  @function.call   -       x
+  @keyword         -       -
+  @variable        x       x
+
+"
+        );
+    }
+
+    #[test]
+    fn cli_coverage_nodes_missing_language() {
+        // Arrange
+        let path = Path::new(QUERIES_DIR);
+
+        // Act
+        let output = Command::new(env!("CARGO_BIN_EXE_ts_query_ls"))
+            .arg("coverage")
+            .arg(path)
+            .arg("--workspace")
+            .arg(path)
+            .arg("--nodes")
+            .arg("--config")
+            .arg("{}")
+            .output()
+            .expect("Failed to execute coverage command");
+
+        // Assert
+        assert_eq!(output.status.code(), Some(1));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("Could not retrieve language \"base\""));
+        assert!(stderr.contains("Could not retrieve language \"derived\""));
+    }
+}
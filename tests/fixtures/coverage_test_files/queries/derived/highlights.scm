@@ -0,0 +1,4 @@
+; inherits: base
+
+(call
+  function: (identifier) @function.call)
@@ -0,0 +1,5 @@
+fn main() {
+    let x = 42;
+    helper(x);
+}
+fn helper(_: i32) {}
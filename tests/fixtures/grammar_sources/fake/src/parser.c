@@ -0,0 +1,5 @@
+#include "scanner.h"
+
+const void *tree_sitter_fake(void) {
+  return fake_scanner();
+}
@@ -0,0 +1,5 @@
+#include "scanner.h"
+
+const void *fake_scanner(void) {
+  return 0;
+}
@@ -0,0 +1 @@
+const void *fake_scanner(void);
@@ -0,0 +1,19 @@
+module.exports = grammar({
+  name: 'cpp',
+
+  word: $ => $.identifier,
+
+  rules: {
+    translation_unit: $ => repeat($._top_level_item),
+
+    function_definition: $ => seq(
+      field('type', $.identifier),
+      field('declarator', $.identifier),
+      field('body', $.compound_statement),
+    ),
+
+    compound_statement: _ => seq('{', '}'),
+
+    identifier: (_) => /[a-zA-Z_]\w*/,
+  },
+});
@@ -0,0 +1,4 @@
+[
+  "a"
+  "a"
+] @keyword
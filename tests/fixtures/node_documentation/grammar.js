@@ -0,0 +1,27 @@
+module.exports = grammar({
+  name: 'rust',
+
+  // The word token, which is not a rule
+  word: $ => $.identifier,
+
+  rules: {
+    source_file: $ => repeat($._statement),
+
+    /**
+     * A function definition, e.g. `fn main() {}`.
+     *
+     * The body is a `block`.
+     */
+    function_item: $ => seq(
+      'fn',
+      field('name', $.identifier),
+      field('body', $.block),
+    ),
+
+    block: _ => seq('{', '}'),
+
+    // An identifier, such as the name of a function.
+    // Raw identifiers are included.
+    identifier: (_) => /(r#)?[a-zA-Z_]\w*/,
+  },
+});
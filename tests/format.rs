@@ -109,4 +109,38 @@ mod test {
             }
         }
     }
+
+    #[rstest]
+    #[case("after_trailing_whitespace.scm", "", Some(0))]
+    #[case(
+        "before_trailing_whitespace.scm",
+        "::error file=before_trailing_whitespace.scm,line=1,col=2,endLine=3,endColumn=4,title=formatting::Incorrect formatting
+::error file=before_trailing_whitespace.scm,line=3,col=13,endLine=3,endColumn=18,title=formatting::Incorrect formatting
+",
+        Some(1)
+    )]
+    fn cli_format_check_github(
+        #[case] file: &str,
+        #[case] expected: &str,
+        #[case] exit_code: Option<i32>,
+    ) {
+        // Act
+        let output = Command::new(env!("CARGO_BIN_EXE_ts_query_ls"))
+            .current_dir(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/formatting_test_files"
+            ))
+            .arg("format")
+            .arg("--check")
+            .arg(file)
+            .arg("--output-format")
+            .arg("github")
+            .output()
+            .expect("Failed to wait on ts-query-ls format command");
+
+        // Assert
+        assert!(output.stderr.is_empty());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+        assert_eq!(output.status.code(), exit_code);
+    }
 }
@@ -7,7 +7,8 @@ mod test {
         process::Command,
         sync::LazyLock,
     };
-    use ts_query_ls::{Options, Predicate, PredicateParameter};
+    use tower_lsp::lsp_types::Url;
+    use ts_query_ls::{Options, Predicate, PredicateParameter, SeverityOverride};
 
     static CONFIG: LazyLock<Options> = LazyLock::new(|| Options {
         valid_predicates: BTreeMap::from([
@@ -66,6 +67,10 @@ mod test {
         concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/formatting_test_files/before_syntax_error.scm"),
         Some(["Invalid syntax"].as_slice())
     )]
+    #[case(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/lint_test_files/redundant_alternant.scm"),
+        Some(["\"a\" already captured by this alternation (fix available)"].as_slice())
+    )]
     #[case(
         concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/example_test_files/simple.scm"),
         None
@@ -95,4 +100,187 @@ mod test {
             assert_eq!(output.status.code(), Some(0));
         }
     }
+
+    #[rstest]
+    #[case("json")]
+    #[case("sarif")]
+    fn cli_lint_output_format(#[case] format: &str) {
+        // Arrange
+        let path = Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/formatting_test_files/after_trailing_whitespace.scm"
+        ));
+
+        // Act
+        let output = Command::new(env!("CARGO_BIN_EXE_ts_query_ls"))
+            .arg("lint")
+            .arg(path)
+            .arg("--config")
+            .arg(serde_json::to_string::<Options>(&CONFIG).unwrap())
+            .arg("--output-format")
+            .arg(format)
+            .output()
+            .expect("Failed to wait on ts-query-ls lint command");
+
+        // Assert
+        assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+        assert_eq!(output.status.code(), Some(1));
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let diagnostic = if format == "json" {
+            let uri = Url::from_file_path(path).unwrap();
+            assert_eq!(json[0]["uri"], uri.as_str());
+            assert_eq!(json[0]["severity"], "warning");
+            assert_eq!(
+                json[0]["range"],
+                serde_json::json!({
+                    "start": { "line": 0, "character": 7 },
+                    "end": { "line": 0, "character": 11 },
+                })
+            );
+            json[0].clone()
+        } else {
+            let result = &json["runs"][0]["results"][0];
+            assert_eq!(json["version"], "2.1.0");
+            assert_eq!(result["ruleId"], "invalid-capture-name");
+            assert_eq!(result["level"], "warning");
+            assert_eq!(
+                result["locations"][0]["physicalLocation"]["region"],
+                serde_json::json!({
+                    "startLine": 1,
+                    "startColumn": 8,
+                    "endLine": 1,
+                    "endColumn": 12,
+                })
+            );
+            serde_json::json!({
+                "code": result["ruleId"],
+                "message": result["message"]["text"],
+            })
+        };
+        assert_eq!(diagnostic["code"], "invalid-capture-name");
+        assert_eq!(
+            diagnostic["message"],
+            "Invalid capture name \"@cap\" (fix available)"
+        );
+    }
+
+    #[rstest]
+    #[case(
+        "github",
+        "::warning file=before_missing.scm,line=9,col=1,endLine=9,endColumn=14,title=unnecessary-pattern::This pattern has no captures, and will not be processed (fix available)
+"
+    )]
+    #[case(
+        "checkstyle",
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<checkstyle version="4.3">
+  <file name="before_missing.scm">
+    <error line="9" column="1" severity="warning" message="This pattern has no captures, and will not be processed (fix available)" source="ts_query_ls.unnecessary-pattern"/>
+  </file>
+  <file name="before_trailing_whitespace.scm">
+  </file>
+</checkstyle>
+"#
+    )]
+    #[case(
+        "junit",
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="ts_query_ls" tests="2" failures="1">
+  <testsuite name="before_missing.scm" tests="1" failures="1">
+    <testcase name="unnecessary-pattern (9:1)" classname="before_missing.scm">
+      <failure message="This pattern has no captures, and will not be processed (fix available)" type="unnecessary-pattern">before_missing.scm:9:1: This pattern has no captures, and will not be processed (fix available)</failure>
+    </testcase>
+  </testsuite>
+  <testsuite name="before_trailing_whitespace.scm" tests="1" failures="0">
+    <testcase name="before_trailing_whitespace.scm" classname="before_trailing_whitespace.scm"/>
+  </testsuite>
+</testsuites>
+"#
+    )]
+    fn cli_lint_reporters(#[case] format: &str, #[case] expected: &str) {
+        // Act
+        let output = Command::new(env!("CARGO_BIN_EXE_ts_query_ls"))
+            .current_dir(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/formatting_test_files"
+            ))
+            .arg("lint")
+            .arg("before_missing.scm")
+            .arg("before_trailing_whitespace.scm")
+            .arg("--config")
+            .arg(serde_json::to_string::<Options>(&CONFIG).unwrap())
+            .arg("--output-format")
+            .arg(format)
+            .output()
+            .expect("Failed to wait on ts-query-ls lint command");
+
+        // Assert
+        assert!(output.stderr.is_empty());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+        assert_eq!(output.status.code(), Some(1));
+    }
+
+    #[rstest]
+    #[case(SeverityOverride::Error, "Error in", Some(1))]
+    #[case(SeverityOverride::Warning, "Warning in", Some(1))]
+    #[case(SeverityOverride::Info, "Info in", Some(0))]
+    #[case(SeverityOverride::Hint, "Hint in", Some(0))]
+    #[case(SeverityOverride::Off, "", Some(0))]
+    fn cli_lint_severity_override(
+        #[case] severity: SeverityOverride,
+        #[case] expected: &str,
+        #[case] exit_code: Option<i32>,
+    ) {
+        // Arrange
+        let path = Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/formatting_test_files/before_missing.scm"
+        ));
+        let mut config = CONFIG.clone();
+        config.diagnostic_options.severity =
+            BTreeMap::from([(String::from("unnecessary-pattern"), severity)]);
+
+        // Act
+        let output = Command::new(env!("CARGO_BIN_EXE_ts_query_ls"))
+            .arg("lint")
+            .arg(path)
+            .arg("--config")
+            .arg(serde_json::to_string::<Options>(&config).unwrap())
+            .output()
+            .expect("Failed to wait on ts-query-ls lint command");
+
+        // Assert
+        let string_output = String::from_utf8(output.stderr).unwrap();
+        assert!(string_output.starts_with(expected));
+        assert_eq!(string_output.is_empty(), expected.is_empty());
+        assert_eq!(output.status.code(), exit_code);
+    }
+
+    #[test]
+    fn cli_lint_unknown_severity_code() {
+        // Arrange
+        let path = Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/example_test_files/simple.scm"
+        ));
+        let mut config = CONFIG.clone();
+        config.diagnostic_options.severity =
+            BTreeMap::from([(String::from("unused-capture"), SeverityOverride::Off)]);
+
+        // Act
+        let output = Command::new(env!("CARGO_BIN_EXE_ts_query_ls"))
+            .arg("lint")
+            .arg(path)
+            .arg("--config")
+            .arg(serde_json::to_string::<Options>(&config).unwrap())
+            .output()
+            .expect("Failed to wait on ts-query-ls lint command");
+
+        // Assert
+        assert_eq!(
+            String::from_utf8(output.stderr).unwrap(),
+            "Warning: Unknown diagnostic code \"unused-capture\" in `diagnostic_options.severity`\n"
+        );
+        assert_eq!(output.status.code(), Some(0));
+    }
 }